@@ -0,0 +1,104 @@
+// Baseline numbers for the emulator core, run with:
+//
+//     cargo bench --bench core
+//
+// or a single group with e.g. `cargo bench --bench core -- dispatch`. Criterion keeps the
+// previous run under target/criterion and reports the change against it.
+use chip8::Chip8;
+use chip8::testutil::ProgramBuilder;
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+
+const CYCLES_PER_FRAME: u32 = 11;
+
+// A tight loop touching the common opcode families, so the dispatch cost
+// isn't hidden behind drawing.
+fn dispatch_rom() -> Vec<u8> {
+    ProgramBuilder::new()
+        .ops(&[
+            0x6005, // LD V0, 5
+            0x7101, // ADD V1, 1
+            0x8214, // ADD V2, V1
+            0x8322, // AND V3, V2
+            0x8431, // OR V4, V3
+            0x8543, // XOR V5, V4
+            0x8656, // SHR V6, V5
+            0x3100, // SE V1, 0
+            0x4200, // SNE V2, 0
+            0xA300, // LD I, 0x300
+            0xF01E, // ADD I, V0
+            0xF107, // LD V1, DT
+            0x1200, // JP 0x200
+        ])
+        .build()
+}
+
+fn loaded(rom: &[u8]) -> Chip8 {
+    let mut chip8 = Chip8::new();
+    chip8.load_program(rom).unwrap();
+    chip8
+}
+
+fn bench_rom(c: &mut Criterion) {
+    // Pong rather than a test ROM, so the mix includes drawing and timers
+    let rom = include_bytes!("../roms/pong.ch8");
+    let mut group = c.benchmark_group("rom");
+    group.throughput(Throughput::Elements(60 * CYCLES_PER_FRAME as u64));
+    group.bench_function("pong_60_frames", |b| {
+        b.iter_batched_ref(
+            || loaded(rom),
+            |chip8| {
+                chip8.run_frames(60);
+            },
+            criterion::BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn bench_dispatch(c: &mut Criterion) {
+    let mut chip8 = loaded(&dispatch_rom());
+    let mut group = c.benchmark_group("dispatch");
+    group.throughput(Throughput::Elements(1000));
+    group.bench_function("arithmetic_loop", |b| {
+        b.iter(|| {
+            for _ in 0..1000 {
+                let _ = black_box(&mut chip8).cycle();
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_display_buffer(c: &mut Criterion) {
+    // The IBM logo leaves a realistic amount of pixels lit
+    let mut chip8 = loaded(include_bytes!("../roms/ibm.ch8"));
+    chip8.run_frames(60);
+    c.bench_function("get_display_buffer", |b| {
+        b.iter(|| black_box(&chip8).get_display_buffer())
+    });
+}
+
+fn bench_clear_display(c: &mut Criterion) {
+    // Cleared from the lit IBM logo each time, setup isn't timed
+    c.bench_function("clear_display", |b| {
+        b.iter_batched_ref(
+            || {
+                let mut chip8 = loaded(include_bytes!("../roms/ibm.ch8"));
+                chip8.run_frames(60);
+                chip8
+            },
+            |chip8| black_box(chip8).clear_display(),
+            criterion::BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_rom,
+    bench_dispatch,
+    bench_display_buffer,
+    bench_clear_display
+);
+criterion_main!(benches);
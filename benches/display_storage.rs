@@ -0,0 +1,147 @@
+// Packed vs unpacked display storage for DXYN, run with:
+//
+//     cargo bench --bench display_storage
+//
+// The display is packed today: a bit per pixel, 256 bytes per plane in
+// lores, read and written through get_pixel/set_pixel. These are stand-alone
+// copies of the DXYN pixel loop over that layout and over a byte per pixel,
+// drawing the same sprites, so the numbers compare the storage and nothing
+// else. `machine` is the real interpreter running a draw loop, to show how
+// much of an instruction the pixel loop is.
+use chip8::Chip8;
+use chip8::testutil::ProgramBuilder;
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+const DRAWS: usize = 1000;
+
+// x, y and sprite rows for each draw, with the sprite bytes, from a fixed
+// LCG so every run draws the same thing
+struct Workload {
+    draws: Vec<(usize, usize, usize)>,
+    sprite: [u8; 15],
+}
+
+fn workload() -> Workload {
+    let mut state: u32 = 0x1234_5678;
+    let mut next = || {
+        state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (state >> 16) as usize
+    };
+    let draws = (0..DRAWS)
+        .map(|_| (next() % WIDTH, next() % HEIGHT, 1 + next() % 15))
+        .collect();
+    let mut sprite = [0; 15];
+    for byte in &mut sprite {
+        *byte = next() as u8;
+    }
+    Workload { draws, sprite }
+}
+
+// Bottom rows wrap and the right edge clips, the default quirks
+trait Display {
+    fn get(&self, x: usize, y: usize) -> bool;
+    fn set(&mut self, x: usize, y: usize, on: bool);
+
+    fn draw(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
+        let mut collided = false;
+        for (row, &byte) in sprite.iter().enumerate() {
+            let pixel_y = (y + row) % HEIGHT;
+            for column in 0..8 {
+                if byte & (0x80 >> column) == 0 {
+                    continue;
+                }
+                let pixel_x = x + column;
+                if pixel_x >= WIDTH {
+                    break;
+                }
+                let was_on = self.get(pixel_x, pixel_y);
+                collided |= was_on;
+                self.set(pixel_x, pixel_y, !was_on);
+            }
+        }
+        collided
+    }
+}
+
+struct Packed([u8; WIDTH * HEIGHT / 8]);
+
+impl Display for Packed {
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.0[y * 8 + x / 8] & (0x80 >> (x % 8)) != 0
+    }
+
+    fn set(&mut self, x: usize, y: usize, on: bool) {
+        let byte = &mut self.0[y * 8 + x / 8];
+        if on {
+            *byte |= 0x80 >> (x % 8);
+        } else {
+            *byte &= !(0x80 >> (x % 8));
+        }
+    }
+}
+
+struct Unpacked([bool; WIDTH * HEIGHT]);
+
+impl Display for Unpacked {
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.0[y * WIDTH + x]
+    }
+
+    fn set(&mut self, x: usize, y: usize, on: bool) {
+        self.0[y * WIDTH + x] = on;
+    }
+}
+
+fn run(display: &mut impl Display, work: &Workload) -> usize {
+    work.draws
+        .iter()
+        .filter(|&&(x, y, rows)| display.draw(x, y, &work.sprite[..rows]))
+        .count()
+}
+
+fn bench_storage(c: &mut Criterion) {
+    let work = workload();
+    let mut group = c.benchmark_group("display_storage");
+    group.throughput(Throughput::Elements(DRAWS as u64));
+    group.bench_function("packed", |b| {
+        let mut display = Packed([0; WIDTH * HEIGHT / 8]);
+        b.iter(|| run(black_box(&mut display), &work))
+    });
+    group.bench_function("unpacked", |b| {
+        let mut display = Unpacked([false; WIDTH * HEIGHT]);
+        b.iter(|| run(black_box(&mut display), &work))
+    });
+    group.finish();
+}
+
+fn bench_machine(c: &mut Criterion) {
+    // DXYN with 15 rows of font and code bytes, moving each time round
+    let rom = ProgramBuilder::new()
+        .ops(&[
+            0xA200, // LD I, 0x200
+            0xD01F, // DRW V0, V1, 15
+            0x7007, // ADD V0, 7
+            0x7103, // ADD V1, 3
+            0x1202, // JP 0x202
+        ])
+        .build();
+    let mut chip8 = Chip8::new();
+    chip8.load_program(&rom).unwrap();
+    let mut group = c.benchmark_group("display_storage");
+    // Four instructions a draw
+    group.throughput(Throughput::Elements(DRAWS as u64));
+    group.bench_function("machine", |b| {
+        b.iter(|| {
+            for _ in 0..DRAWS * 4 {
+                let _ = black_box(&mut chip8).cycle();
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_storage, bench_machine);
+criterion_main!(benches);
@@ -0,0 +1,166 @@
+// The ROM running now, which Page Up/Down, --watch and the debugger's `load`
+// can change after startup. Its RPL flags file and settings follow it: a
+// change saves the flags and anything changed at runtime under the ROM that
+// was running, then loads the new ROM's, so high scores and tuning stay with
+// their game.
+use crate::{Config, inputprofile, romsettings, rpl};
+use chip8::{Chip8, Quirks};
+use std::path::PathBuf;
+
+// What a ROM runs with, its remembered settings over the command line and
+// the built-in defaults
+#[derive(Clone, Copy)]
+pub struct RomConfig {
+    pub hz: u32,
+    pub quirks: Quirks,
+    pub palette: [u32; 4],
+    pub invert: bool,
+    pub input_profile: Option<&'static inputprofile::InputProfile>,
+}
+
+pub struct ActiveRom {
+    path: String,
+    // Off with --no-rpl-save
+    persist_rpl: bool,
+    // Off with --no-rom-settings
+    persist_settings: bool,
+    // For a ROM with nothing remembered
+    defaults: RomConfig,
+    // The running ROM's. The quirks and colors are the machine's once it
+    // started, anything different when it stops was changed at runtime.
+    settings: RomConfig,
+    // Quirks remembered for the ROM or given on the command line, which
+    // beat --compat-db
+    own_quirks: bool,
+}
+
+impl ActiveRom {
+    // For the ROM given at startup, before the machine is built from its
+    // settings. Anything on the command line this time replaces what's
+    // remembered for it.
+    pub fn new(config: &Config) -> Self {
+        let defaults = RomConfig {
+            hz: config.hz,
+            quirks: config.quirks,
+            palette: config.palette,
+            invert: config.invert,
+            input_profile: config.input_profile,
+        };
+        let mut rom = ActiveRom {
+            path: config.rom_path.clone(),
+            persist_rpl: config.persist_rpl,
+            persist_settings: config.persist_rom_settings,
+            defaults,
+            settings: defaults,
+            own_quirks: config.quirks_from_args,
+        };
+        if let Some(path) = rom.settings_path() {
+            let mut stored = romsettings::load(&path);
+            if stored.update(&config.rom_settings) {
+                romsettings::save(&path, &stored);
+            }
+            rom.use_settings(&stored);
+            rom.own_quirks |= stored.quirks.is_some();
+        }
+        rom
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn settings(&self) -> &RomConfig {
+        &self.settings
+    }
+
+    pub fn own_quirks(&self) -> bool {
+        self.own_quirks
+    }
+
+    // A ROM from stdin has no name to file its flags or settings under
+    fn rpl_path(&self) -> Option<PathBuf> {
+        rpl::flags_path(&self.path).filter(|_| self.persist_rpl && self.path != "-")
+    }
+
+    fn settings_path(&self) -> Option<PathBuf> {
+        romsettings::settings_path(&self.path).filter(|_| self.persist_settings && self.path != "-")
+    }
+
+    fn use_settings(&mut self, stored: &romsettings::RomSettings) {
+        self.settings = RomConfig {
+            hz: stored.hz.unwrap_or(self.defaults.hz),
+            quirks: stored.quirks.unwrap_or(self.defaults.quirks),
+            palette: stored.palette.unwrap_or(self.defaults.palette),
+            invert: stored.invert.unwrap_or(self.defaults.invert),
+            input_profile: match &stored.input_profile {
+                Some(name) => inputprofile::find(name).or_else(|| {
+                    eprintln!("Ignoring unknown input profile '{}' in ROM settings", name);
+                    self.defaults.input_profile
+                }),
+                None => self.defaults.input_profile,
+            },
+        };
+    }
+
+    // Once the startup ROM is set up to run, with its flags put in the
+    // machine. Whatever --compat-db chose counts as how it started.
+    pub fn start(&mut self, chip8: &mut Chip8) {
+        if let Some(flags_path) = self.rpl_path() {
+            chip8.set_rpl_flags(rpl::load(&flags_path));
+        }
+        self.settings.quirks = chip8.quirks();
+    }
+
+    // Once `path` is loaded, before it runs. The machine still has the
+    // flags and settings of the ROM before it, a reset keeps them. The
+    // frontend picks up the speed and input profile from `settings`.
+    pub fn switch(&mut self, path: &str, chip8: &mut Chip8) {
+        if path == self.path {
+            return;
+        }
+        self.save_settings(chip8);
+        self.save_rpl(chip8);
+        self.path = path.to_string();
+        let flags = self
+            .rpl_path()
+            .map_or([0; 16], |flags_path| rpl::load(&flags_path));
+        chip8.set_rpl_flags(flags);
+
+        let stored = self
+            .settings_path()
+            .map(|path| romsettings::load(&path))
+            .unwrap_or_default();
+        self.use_settings(&stored);
+        self.own_quirks = stored.quirks.is_some();
+        chip8.set_quirks(self.settings.quirks);
+        chip8.set_palette(self.settings.palette);
+        chip8.set_invert_display(self.settings.invert);
+    }
+
+    // Quirks and colors changed while it ran (P, F8 and F10 in the window)
+    // stick for next time
+    pub fn save_settings(&self, chip8: &Chip8) {
+        let Some(path) = self.settings_path() else {
+            return;
+        };
+        let changed = romsettings::RomSettings {
+            quirks: Some(chip8.quirks()).filter(|&quirks| quirks != self.settings.quirks),
+            palette: Some(chip8.palette()).filter(|&palette| palette != self.settings.palette),
+            invert: Some(chip8.invert_display()).filter(|&invert| invert != self.settings.invert),
+            ..Default::default()
+        };
+        let mut stored = romsettings::load(&path);
+        if stored.update(&changed) {
+            romsettings::save(&path, &stored);
+        }
+    }
+
+    // Only games that used the flags get a file
+    pub fn save_rpl(&self, chip8: &Chip8) {
+        if let Some(flags_path) = self.rpl_path()
+            && (flags_path.exists() || chip8.rpl_flags() != [0; 16])
+        {
+            rpl::save(&flags_path, chip8.rpl_flags());
+        }
+    }
+}
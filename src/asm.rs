@@ -0,0 +1,344 @@
+// A small two-pass CHIP-8 assembler.
+//
+// Syntax follows the common Cowgod mnemonics:
+//
+//     start:
+//         LD V0, #10      ; hex with #, $ or 0x, binary with %, else decimal
+//         LD I, sprite
+//         DRW V0, V1, 5
+//         JP start
+//     sprite:
+//         DB #F0, #90, #F0
+//
+// The first pass records the address of every label, the second pass emits
+// the bytes with labels resolved. Programs are assumed to load at 0x200.
+//
+// The labels can also be written out as a symbol file, one `address = name`
+// per line, so the disassembler can show names instead of addresses.
+use crate::opcodes::{self, Extension, OperandFormat};
+use std::collections::HashMap;
+
+const START_ADDRESS: u16 = 0x200;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    V(u8),
+    I,
+    // [I], the memory at I
+    IndirectI,
+    DT,
+    ST,
+    K,
+    F,
+    B,
+    // SCHIP RPL user flags
+    R,
+    Number(u16),
+    Label(String),
+}
+
+struct Statement {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<Operand>,
+}
+
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    assemble_with_symbols(source).map(|(program, _)| program)
+}
+
+// Label addresses and names, in address order
+pub type Symbols = Vec<(u16, String)>;
+
+pub fn assemble_with_symbols(source: &str) -> Result<(Vec<u8>, Symbols), String> {
+    assemble_with_warnings(source).map(|(program, symbols, _)| (program, symbols))
+}
+
+// Things that assemble but are probably mistakes, one message per line
+pub type Warnings = Vec<String>;
+
+pub fn assemble_with_warnings(source: &str) -> Result<(Vec<u8>, Symbols, Warnings), String> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = START_ADDRESS;
+
+    // Pass 1: parse and record label addresses
+    for (index, raw_line) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut text = raw_line.split(';').next().unwrap_or("").trim();
+
+        while let Some((label, rest)) = split_label(text) {
+            if labels.insert(label.to_string(), address).is_some() {
+                return Err(format!("line {}: duplicate label '{}'", line, label));
+            }
+            text = rest.trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = match text.split_once(char::is_whitespace) {
+            Some((mnemonic, rest)) => (mnemonic, rest.trim()),
+            None => (text, ""),
+        };
+        let operands = if rest.is_empty() {
+            Vec::new()
+        } else {
+            rest.split(',')
+                .map(|operand| parse_operand(operand.trim()))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("line {}: {}", line, e))?
+        };
+
+        let statement = Statement {
+            line,
+            mnemonic: mnemonic.to_uppercase(),
+            operands,
+        };
+        let size = match statement.mnemonic.as_str() {
+            "DB" => statement.operands.len(),
+            "DW" => statement.operands.len() * 2,
+            _ => 2,
+        };
+        address = u16::try_from(address as usize + size)
+            .map_err(|_| format!("line {}: program is too large", line))?;
+        statements.push(statement);
+    }
+
+    // Pass 2: emit bytes
+    let mut output = Vec::new();
+    let mut warnings = Vec::new();
+    for statement in &statements {
+        // Instructions are fetched as two bytes from wherever PC is, so an odd
+        // target usually means a DB before it has an odd number of bytes.
+        // Only a warning, as data tables are fine at odd addresses.
+        if let ("JP" | "CALL", [.., Operand::Label(name)]) =
+            (statement.mnemonic.as_str(), statement.operands.as_slice())
+            && let Some(&target) = labels.get(name)
+            && target % 2 != 0
+        {
+            warnings.push(format!(
+                "line {}: {} target '{}' is at odd address {:#05X}",
+                statement.line, statement.mnemonic, name, target
+            ));
+        }
+
+        encode(statement, &labels, &mut output)
+            .map_err(|e| format!("line {}: {}", statement.line, e))?;
+    }
+
+    let mut symbols: Symbols = labels
+        .into_iter()
+        .map(|(name, addr)| (addr, name))
+        .collect();
+    symbols.sort();
+    Ok((output, symbols, warnings))
+}
+
+// Symbols as the text of a symbol file
+pub fn format_symbols(symbols: &[(u16, String)]) -> String {
+    symbols
+        .iter()
+        .map(|(address, name)| format!("{:#05X} = {}\n", address, name))
+        .collect()
+}
+
+// Split a leading `label:` off a line
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let (label, rest) = text.split_once(':')?;
+    let label = label.trim();
+    let valid = !label.is_empty()
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !label.starts_with(|c: char| c.is_ascii_digit());
+    valid.then_some((label, rest))
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    let upper = text.to_uppercase();
+    match upper.as_str() {
+        "I" => return Ok(Operand::I),
+        "[I]" => return Ok(Operand::IndirectI),
+        "DT" => return Ok(Operand::DT),
+        "ST" => return Ok(Operand::ST),
+        "K" => return Ok(Operand::K),
+        "F" => return Ok(Operand::F),
+        "B" => return Ok(Operand::B),
+        "R" => return Ok(Operand::R),
+        _ => {}
+    }
+
+    if upper.len() == 2
+        && let Some(register) = upper.strip_prefix('V')
+        && let Ok(register) = u8::from_str_radix(register, 16)
+    {
+        return Ok(Operand::V(register));
+    }
+
+    if let Some(value) = parse_number(text) {
+        return Ok(Operand::Number(value));
+    }
+
+    if !text.is_empty()
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !text.starts_with(|c: char| c.is_ascii_digit())
+    {
+        return Ok(Operand::Label(text.to_string()));
+    }
+
+    Err(format!("invalid operand '{}'", text))
+}
+
+fn parse_number(text: &str) -> Option<u16> {
+    let lower = text.to_lowercase();
+    if let Some(hex) = lower
+        .strip_prefix('#')
+        .or_else(|| lower.strip_prefix('$'))
+        .or_else(|| lower.strip_prefix("0x"))
+    {
+        u16::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix('%').or_else(|| lower.strip_prefix("0b")) {
+        u16::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+fn value(operand: &Operand, labels: &HashMap<String, u16>) -> Result<u16, String> {
+    match operand {
+        Operand::Number(n) => Ok(*n),
+        Operand::Label(name) => labels
+            .get(name)
+            .copied()
+            .ok_or_else(|| format!("unknown label '{}'", name)),
+        _ => Err(format!("expected a number or label, got {:?}", operand)),
+    }
+}
+
+fn address(operand: &Operand, labels: &HashMap<String, u16>) -> Result<u16, String> {
+    let addr = value(operand, labels)?;
+    if addr > 0xFFF {
+        return Err(format!("address {:#X} is out of range", addr));
+    }
+    Ok(addr)
+}
+
+fn byte(operand: &Operand, labels: &HashMap<String, u16>) -> Result<u16, String> {
+    let byte = value(operand, labels)?;
+    if byte > 0xFF {
+        return Err(format!("value {:#X} doesn't fit in a byte", byte));
+    }
+    Ok(byte)
+}
+
+// A number or label operand that fits in one hex digit
+fn nibble(operand: &Operand, labels: &HashMap<String, u16>) -> Result<u16, String> {
+    let nibble = value(operand, labels)?;
+    if nibble > 0xF {
+        return Err(format!("value {:#X} doesn't fit in a nibble", nibble));
+    }
+    Ok(nibble)
+}
+
+// Whether `operand` can go where `format` is, before looking at its value
+fn fits(format: OperandFormat, operand: &Operand) -> bool {
+    match (format, operand) {
+        (OperandFormat::Vx | OperandFormat::Vy, Operand::V(_)) => true,
+        (OperandFormat::V0, Operand::V(register)) => *register == 0,
+        (
+            OperandFormat::Address
+            | OperandFormat::Byte
+            | OperandFormat::Nibble
+            | OperandFormat::XNibble,
+            Operand::Number(_) | Operand::Label(_),
+        ) => true,
+        (OperandFormat::I, Operand::I)
+        | (OperandFormat::IndirectI, Operand::IndirectI)
+        | (OperandFormat::DT, Operand::DT)
+        | (OperandFormat::ST, Operand::ST)
+        | (OperandFormat::K, Operand::K)
+        | (OperandFormat::F, Operand::F)
+        | (OperandFormat::B, Operand::B)
+        | (OperandFormat::R, Operand::R) => true,
+        _ => false,
+    }
+}
+
+// Instructions come from `opcodes::OPCODES`, the first entry with the
+// mnemonic and operands that fit wins
+fn encode(
+    statement: &Statement,
+    labels: &HashMap<String, u16>,
+    output: &mut Vec<u8>,
+) -> Result<(), String> {
+    let ops = statement.operands.as_slice();
+
+    match statement.mnemonic.as_str() {
+        "DB" => {
+            for operand in ops {
+                output.push(byte(operand, labels)? as u8);
+            }
+            return Ok(());
+        }
+        "DW" => {
+            for operand in ops {
+                output.extend_from_slice(&value(operand, labels)?.to_be_bytes());
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    // SHR VX and SHL VX are the shifts with VY left at 0
+    let shift;
+    let ops = match (statement.mnemonic.as_str(), ops) {
+        ("SHR" | "SHL", [Operand::V(x)]) => {
+            shift = [Operand::V(*x), Operand::V(0)];
+            &shift[..]
+        }
+        _ => ops,
+    };
+
+    // Chip-8X opcodes mean something else everywhere else, so there's no
+    // writing them by name
+    let info = opcodes::OPCODES
+        .iter()
+        .find(|info| {
+            info.extension != Extension::Chip8x
+                && info.mnemonic == statement.mnemonic
+                && info.operands.len() == ops.len()
+                && info
+                    .operands
+                    .iter()
+                    .zip(ops)
+                    .all(|(&format, operand)| fits(format, operand))
+        })
+        .ok_or_else(|| {
+            format!(
+                "invalid instruction '{}' with {} operand(s)",
+                statement.mnemonic,
+                ops.len()
+            )
+        })?;
+
+    let mut opcode = info.bits;
+    for (&format, operand) in info.operands.iter().zip(ops) {
+        opcode |= match (format, operand) {
+            (OperandFormat::Vx, Operand::V(x)) => reg(*x) << 8,
+            (OperandFormat::Vy, Operand::V(y)) => reg(*y) << 4,
+            (OperandFormat::Address, _) => address(operand, labels)?,
+            (OperandFormat::Byte, _) => byte(operand, labels)?,
+            (OperandFormat::Nibble, _) => nibble(operand, labels)?,
+            (OperandFormat::XNibble, _) => nibble(operand, labels)? << 8,
+            // Fixed by the opcode
+            _ => 0,
+        };
+    }
+
+    output.extend_from_slice(&opcode.to_be_bytes());
+    Ok(())
+}
+
+fn reg(register: u8) -> u16 {
+    register as u16
+}
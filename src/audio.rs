@@ -0,0 +1,156 @@
+// Beep output for the sound timer.
+//
+// The real backend needs the `audio` feature (cpal). Without it the beeper
+// is a silent stand-in so the frontend doesn't need cfg checks everywhere.
+
+#[cfg(feature = "audio")]
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+#[cfg(feature = "audio")]
+use std::sync::Arc;
+#[cfg(feature = "audio")]
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+}
+
+impl Waveform {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "square" => Some(Waveform::Square),
+            "sine" => Some(Waveform::Sine),
+            "triangle" => Some(Waveform::Triangle),
+            _ => None,
+        }
+    }
+
+    // Sample in -1.0..=1.0 at `phase` (0.0..1.0) through one period
+    #[cfg(feature = "audio")]
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (phase * std::f32::consts::TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+        }
+    }
+}
+
+// State shared with the audio callback thread
+#[cfg(feature = "audio")]
+struct BeepState {
+    playing: AtomicBool,
+    muted: AtomicBool,
+    // f32 volume and pitch in Hz stored as bits so they can live in atomics
+    volume: AtomicU32,
+    frequency: AtomicU32,
+}
+
+#[cfg(feature = "audio")]
+pub struct Beeper {
+    state: Arc<BeepState>,
+    stream: cpal::Stream,
+}
+
+#[cfg(feature = "audio")]
+impl Beeper {
+    pub fn new(volume: f32, waveform: Waveform, frequency: f32) -> Option<Self> {
+        let state = Arc::new(BeepState {
+            playing: AtomicBool::new(false),
+            muted: AtomicBool::new(false),
+            volume: AtomicU32::new(volume.clamp(0.0, 1.0).to_bits()),
+            frequency: AtomicU32::new(frequency.to_bits()),
+        });
+
+        let host = cpal::default_host();
+        let device = host.default_output_device()?;
+        let config: cpal::StreamConfig = device.default_output_config().ok()?.into();
+
+        let sample_rate = config.sample_rate as f32;
+        let channels = config.channels as usize;
+        let mut phase = 0.0f32;
+
+        let callback_state = Arc::clone(&state);
+        let stream = device
+            .build_output_stream(
+                config,
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    // Checked every buffer so muting cuts the tone right away
+                    let on = callback_state.playing.load(Ordering::Relaxed)
+                        && !callback_state.muted.load(Ordering::Relaxed);
+                    let volume = f32::from_bits(callback_state.volume.load(Ordering::Relaxed));
+                    let frequency =
+                        f32::from_bits(callback_state.frequency.load(Ordering::Relaxed));
+
+                    for frame in data.chunks_mut(channels) {
+                        phase = (phase + frequency / sample_rate) % 1.0;
+                        let value = if on {
+                            waveform.sample(phase) * volume
+                        } else {
+                            0.0
+                        };
+                        frame.fill(value);
+                    }
+                },
+                |e| eprintln!("Audio stream error: {}", e),
+                None,
+            )
+            .map_err(|e| eprintln!("Failed to open audio output: {}", e))
+            .ok()?;
+        stream.play().ok()?;
+
+        Some(Beeper { state, stream })
+    }
+
+    pub fn set_playing(&self, playing: bool) {
+        self.state.playing.store(playing, Ordering::Relaxed);
+    }
+
+    // Changes the pitch, e.g. when a ROM uses XO-CHIP's FX3A
+    pub fn set_frequency(&self, frequency: f32) {
+        self.state
+            .frequency
+            .store(frequency.to_bits(), Ordering::Relaxed);
+    }
+
+    // Returns whether the beeper is now muted
+    pub fn toggle_mute(&self) -> bool {
+        !self.state.muted.fetch_xor(true, Ordering::Relaxed)
+    }
+}
+
+#[cfg(feature = "audio")]
+impl Drop for Beeper {
+    // Stop the stream itself on the way out rather than relying on the
+    // callback, which may not run again before the stream is torn down
+    fn drop(&mut self) {
+        self.set_playing(false);
+        let _ = self.stream.pause();
+    }
+}
+
+#[cfg(not(feature = "audio"))]
+pub struct Beeper;
+
+#[cfg(not(feature = "audio"))]
+impl Beeper {
+    pub fn new(_volume: f32, _waveform: Waveform, _frequency: f32) -> Option<Self> {
+        None
+    }
+
+    pub fn set_playing(&self, _playing: bool) {}
+
+    pub fn set_frequency(&self, _frequency: f32) {}
+
+    pub fn toggle_mute(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,272 @@
+// Debugger frontend built on egui: the display next to live registers, the
+// stack, a disassembly around PC and a memory view, with step/continue/reset.
+//
+// Build with `cargo run --features ui --bin chip8-ui -- <rom_file>`.
+use chip8::{Chip8, disasm};
+use eframe::egui;
+use std::fs;
+use std::time::{Duration, Instant};
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// Same layout as the minifb frontend
+const KEYMAP: [(egui::Key, u8); 16] = [
+    (egui::Key::Num1, 0x1),
+    (egui::Key::Num2, 0x2),
+    (egui::Key::Num3, 0x3),
+    (egui::Key::Num4, 0xC),
+    (egui::Key::Q, 0x4),
+    (egui::Key::W, 0x5),
+    (egui::Key::E, 0x6),
+    (egui::Key::R, 0xD),
+    (egui::Key::A, 0x7),
+    (egui::Key::S, 0x8),
+    (egui::Key::D, 0x9),
+    (egui::Key::F, 0xE),
+    (egui::Key::Z, 0xA),
+    (egui::Key::X, 0x0),
+    (egui::Key::C, 0xB),
+    (egui::Key::V, 0xF),
+];
+
+struct DebuggerApp {
+    chip8: Chip8,
+    rom: Vec<u8>,
+    running: bool,
+    // Time of the last emulated frame, so speed doesn't depend on repaint rate
+    last_frame: Instant,
+    screen: Option<egui::TextureHandle>,
+}
+
+impl DebuggerApp {
+    fn new(rom: Vec<u8>) -> Self {
+        let mut app = DebuggerApp {
+            chip8: Chip8::new(),
+            rom,
+            running: false,
+            last_frame: Instant::now(),
+            screen: None,
+        };
+        app.reset();
+        app
+    }
+
+    fn reset(&mut self) {
+        // Already checked to fit when the app started
+        self.chip8.load_rom(&self.rom).unwrap();
+        self.running = false;
+    }
+
+    fn step(&mut self) {
+        // Unknown opcodes are logged by the core, faults stop the run below
+        let _ = self.chip8.cycle();
+    }
+
+    fn run_frame(&mut self) {
+        self.chip8.run_frame();
+        if self.chip8.fault().is_some() || self.chip8.is_halted() {
+            self.running = false;
+        }
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!self.running, egui::Button::new("Step"))
+                .clicked()
+            {
+                self.step();
+            }
+            let label = if self.running { "Pause" } else { "Continue" };
+            if ui.button(label).clicked() {
+                self.running = !self.running;
+                self.last_frame = Instant::now();
+            }
+            if ui.button("Reset").clicked() {
+                self.reset();
+            }
+        });
+
+        if let Some(fault) = self.chip8.fault() {
+            ui.colored_label(egui::Color32::RED, format!("Fault: {}", fault));
+        } else if self.chip8.is_halted() {
+            ui.label("Halted");
+        } else if self.chip8.is_waiting_for_key() {
+            ui.label("Waiting for a key");
+        }
+        if self.chip8.is_beeping() {
+            ui.label("Beeping");
+        }
+    }
+
+    fn registers(&self, ui: &mut egui::Ui) {
+        ui.heading("Registers");
+        egui::Grid::new("registers").striped(true).show(ui, |ui| {
+            for (row, pair) in self.chip8.registers().chunks(4).enumerate() {
+                for (column, value) in pair.iter().enumerate() {
+                    ui.monospace(format!("V{:X} {:02X}", row * 4 + column, value));
+                }
+                ui.end_row();
+            }
+        });
+        ui.monospace(format!(
+            "PC {:03X}  I {:03X}",
+            self.chip8.pc(),
+            self.chip8.index()
+        ));
+        ui.monospace(format!(
+            "DT {:02X}   ST {:02X}",
+            self.chip8.delay_timer(),
+            self.chip8.sound_timer()
+        ));
+        ui.monospace(format!("Cycle {}", self.chip8.cycle_count()));
+
+        ui.separator();
+        ui.heading("Stack");
+        let stack = self.chip8.stack();
+        if stack.is_empty() {
+            ui.monospace("(empty)");
+        }
+        // Most recent call on top
+        for (depth, address) in stack.iter().enumerate().rev() {
+            ui.monospace(format!("{:X}: {:03X}", depth, address));
+        }
+    }
+
+    fn disassembly(&self, ui: &mut egui::Ui) {
+        ui.heading("Disassembly");
+        let pc = self.chip8.pc();
+        let start = pc.saturating_sub(16) & !1;
+        for address in (start..start.saturating_add(40)).step_by(2) {
+            let opcode = self.chip8.opcode_at(address);
+            let marker = if address == pc { ">" } else { " " };
+            let text = format!(
+                "{} {:03X}  {:04X}  {}",
+                marker,
+                address,
+                opcode,
+                disasm::decode(opcode)
+            );
+            if address == pc {
+                ui.colored_label(egui::Color32::YELLOW, egui::RichText::new(text).monospace());
+            } else {
+                ui.monospace(text);
+            }
+        }
+    }
+
+    fn memory_view(&self, ui: &mut egui::Ui) {
+        ui.heading("Memory");
+        egui::ScrollArea::vertical().id_salt("memory").show_rows(
+            ui,
+            14.0,
+            self.chip8.memory().len() / 16,
+            |ui, rows| {
+                for row in rows {
+                    let bytes = self.chip8.memory_slice((row * 16) as u16, 16);
+                    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                    ui.monospace(format!("{:04X}  {}", row * 16, hex.join(" ")));
+                }
+            },
+        );
+    }
+
+    fn display(&mut self, ui: &mut egui::Ui) {
+        let pixels: Vec<egui::Color32> = self
+            .chip8
+            .get_display_buffer()
+            .iter()
+            .map(|&color| {
+                egui::Color32::from_rgb((color >> 16) as u8, (color >> 8) as u8, color as u8)
+            })
+            .collect();
+        let (width, height) = self.chip8.display_dimensions();
+        let image = egui::ColorImage::new([width, height], pixels);
+
+        let texture = match &mut self.screen {
+            Some(texture) => {
+                texture.set(image, egui::TextureOptions::NEAREST);
+                texture
+            }
+            None => self.screen.insert(ui.ctx().load_texture(
+                "screen",
+                image,
+                egui::TextureOptions::NEAREST,
+            )),
+        };
+
+        // Largest whole 2:1 fit in the space left over
+        let available = ui.available_size();
+        let scale = (available.x / 64.0)
+            .min(available.y / 32.0)
+            .floor()
+            .max(1.0);
+        ui.add(
+            egui::Image::new(&*texture).fit_to_exact_size(egui::vec2(64.0 * scale, 32.0 * scale)),
+        );
+    }
+}
+
+impl eframe::App for DebuggerApp {
+    fn logic(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        ctx.input(|input| {
+            for (key, chip8_key) in KEYMAP {
+                self.chip8.set_key(chip8_key, input.key_down(key));
+            }
+        });
+
+        if self.running {
+            // Catch up on missed frames, but not forever after a stall
+            let mut frames = 0;
+            while self.last_frame.elapsed() >= FRAME && frames < 4 {
+                self.run_frame();
+                self.last_frame += FRAME;
+                frames += 1;
+            }
+            if frames == 4 {
+                self.last_frame = Instant::now();
+            }
+            ctx.request_repaint_after(FRAME);
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        egui::Panel::right("state").show(ui, |ui| {
+            self.controls(ui);
+            ui.separator();
+            self.registers(ui);
+        });
+        egui::Panel::bottom("code").show(ui, |ui| {
+            ui.columns(2, |columns| {
+                self.disassembly(&mut columns[0]);
+                self.memory_view(&mut columns[1]);
+            });
+        });
+        egui::CentralPanel::default().show(ui, |ui| {
+            self.display(ui);
+        });
+    }
+}
+
+fn main() -> eframe::Result {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(rom_path) = args.get(1) else {
+        eprintln!("Usage: {} <rom_file>", args[0]);
+        std::process::exit(1);
+    };
+
+    let rom = fs::read(rom_path).unwrap_or_else(|e| {
+        eprintln!("Failed to read ROM file '{}' : {}", rom_path, e);
+        std::process::exit(1);
+    });
+    if let Err(e) = Chip8::new().load_program(&rom) {
+        eprintln!("Failed to load ROM file '{}' : {}", rom_path, e);
+        std::process::exit(1);
+    }
+
+    eframe::run_native(
+        "Chip-8 Debugger",
+        eframe::NativeOptions::default(),
+        Box::new(|_| Ok(Box::new(DebuggerApp::new(rom)))),
+    )
+}
@@ -0,0 +1,2900 @@
+use crate::clock::{Clock, TimeSource};
+use crate::error::Chip8Error;
+use crate::framehistogram::FrameHistogram;
+use crate::profile::Profile;
+use crate::snapshot::{DisplaySnapshot, Snapshot};
+use minifb::{Key, Window};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+
+// Hex digit sprites 0-F, 4x5 pixels each
+pub const FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// Black, white, and two accents for pixels drawn on XO-CHIP's second plane
+pub const DEFAULT_PALETTE: [u32; 4] = [0x000000, 0xFFFFFF, 0xAA4400, 0xFFAA00];
+
+// Named foreground and background colors to switch between while running.
+// XO-CHIP's second plane keeps the accents from DEFAULT_PALETTE.
+pub const PALETTE_PRESETS: [(&str, u32, u32); 4] = [
+    ("classic", 0xFFFFFF, 0x000000),
+    ("green phosphor", 0x33FF66, 0x0A1A0F),
+    ("amber", 0xFFB000, 0x1A1000),
+    ("lcd", 0x2F3A2F, 0x9EA792),
+];
+
+// Give up after this many unknown opcodes, the ROM is most likely running garbage
+const MAX_UNKNOWN_OPCODES: u32 = 16;
+
+// FX3A pitch a machine starts with, XO-CHIP's 4000Hz playback rate
+const DEFAULT_PITCH: u8 = 64;
+
+// Bytes either side of the PC where a write counts as self-modifying code
+const SELF_MODIFYING_WINDOW: u16 = 4;
+
+// The interpreter and font area, programs have no business running below it
+const INTERPRETER_AREA_END: u16 = 0x200;
+
+// Bytes of a plane at its largest, SCHIP's 128x64 hires mode
+const PLANE_BYTES: usize = 128 * 64 / 8;
+
+// Errors that stop the machine, or that the frontend gets to decide about
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    StackOverflow,
+    StackUnderflow,
+    UnknownOpcode(u16),
+    UnknownOpcodes(u16),
+    // 0NNN with SysCallMode::Trap, holds NNN
+    SysCall(u16),
+    // A panic inside an instruction, caught with `robust` on. Holds the
+    // panic message.
+    Internal(String),
+    // Ran the number of instructions in `max_cycles`
+    CycleLimit(u64),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::StackOverflow => write!(f, "stack overflow"),
+            Fault::StackUnderflow => write!(f, "return with an empty stack"),
+            Fault::UnknownOpcode(opcode) => write!(f, "unknown opcode {:#06X}", opcode),
+            Fault::UnknownOpcodes(last) => {
+                write!(f, "too many unknown opcodes (last was {:#06X})", last)
+            }
+            Fault::SysCall(address) => {
+                write!(f, "call to machine code routine at {:#05X} (0NNN)", address)
+            }
+            Fault::Internal(message) => write!(f, "internal emulator error: {}", message),
+            Fault::CycleLimit(limit) => write!(f, "reached the limit of {} instructions", limit),
+        }
+    }
+}
+
+// Behaviours that differ between CHIP-8 interpreters
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Quirks {
+    // Sprite columns past the right edge wrap to the left instead of being clipped
+    pub horizontal_wrap: bool,
+    // Sprite rows past the bottom of the screen wrap to the top. SCHIP clips them.
+    pub vertical_wrap: bool,
+    // The scroll opcodes move half as far in lores, SCHIP 1.1 scrolls by
+    // hires pixels in either mode
+    pub half_lores_scroll: bool,
+    // 8XY6/8XYE shift VY into VX like the COSMAC VIP, instead of shifting VX
+    // in place like SCHIP
+    pub shift_uses_vy: bool,
+    // 8XY4-8XYE write VF before the result, so with X = F the result is what
+    // stays in VF. Most interpreters write the flag last and keep the flag.
+    pub vf_written_first: bool,
+    // DXYN sets VF to the number of sprite rows that hit a lit pixel rather
+    // than just 1, which a few XO-CHIP titles rely on
+    pub collision_row_count: bool,
+    // DXYN waits for the next 60Hz frame before anything else runs, as the
+    // VIP's interpreter waited for the vertical blank
+    pub display_wait: bool,
+    // 8XY1/8XY2/8XY3 leave VF at 0, as the VIP's interpreter did by running
+    // them through a routine that used VF as scratch. The quirks test ROM
+    // checks for it.
+    pub vf_reset: bool,
+    // FX55/FX65 leave I just past the last register, as the VIP's interpreter
+    // did. Only where a program starts: 00FA flips it while running.
+    pub load_store_increments_i: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            horizontal_wrap: false,
+            vertical_wrap: true,
+            half_lores_scroll: false,
+            shift_uses_vy: false,
+            vf_written_first: false,
+            collision_row_count: false,
+            display_wait: false,
+            vf_reset: false,
+            load_store_increments_i: false,
+        }
+    }
+}
+
+// Names `Quirks::preset` knows, in the order the window cycles through them
+pub const QUIRK_PRESETS: [&str; 3] = ["chip8", "schip", "xochip"];
+
+impl Quirks {
+    // Quirks for a family of interpreters: "chip8" (the COSMAC VIP's
+    // original interpreter), "schip" or "xochip"
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "chip8" => Some(Quirks {
+                horizontal_wrap: false,
+                vertical_wrap: false,
+                half_lores_scroll: false,
+                shift_uses_vy: true,
+                vf_written_first: false,
+                collision_row_count: false,
+                display_wait: true,
+                vf_reset: true,
+                load_store_increments_i: true,
+            }),
+            "schip" => Some(Quirks {
+                horizontal_wrap: false,
+                vertical_wrap: false,
+                half_lores_scroll: true,
+                shift_uses_vy: false,
+                vf_written_first: false,
+                collision_row_count: false,
+                display_wait: false,
+                vf_reset: false,
+                load_store_increments_i: false,
+            }),
+            "xochip" => Some(Quirks {
+                horizontal_wrap: true,
+                vertical_wrap: true,
+                half_lores_scroll: false,
+                shift_uses_vy: true,
+                vf_written_first: false,
+                collision_row_count: false,
+                display_wait: false,
+                vf_reset: false,
+                load_store_increments_i: true,
+            }),
+            _ => None,
+        }
+    }
+}
+
+// Everything about a machine that's fixed before it starts running
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chip8Config {
+    // A power of two from 1KB to 64KB
+    pub memory_size: usize,
+    // Where programs load and execution starts, ETI-660 programs use 0x600
+    pub start_address: u16,
+    // Fixed seed for CXNN so runs can be reproduced, random when None
+    pub seed: Option<u64>,
+    // Instructions per 60Hz frame for `run_frame`
+    pub cycles_per_frame: u32,
+    pub font: [u8; 80],
+    // Where the font is loaded and FX29 points into, 0x050 by convention
+    pub font_address: u16,
+    pub quirks: Quirks,
+    pub unknown_opcode_mode: UnknownOpcodeMode,
+    pub sys_call_mode: SysCallMode,
+    pub index_mode: IndexMode,
+    pub timing: TimingModel,
+    // Hold a key pressed within a frame until the frame ends, see set_key
+    pub latch_keys: bool,
+    pub warn_self_modifying: bool,
+    // Warn when the PC lands on an odd address, usually a bad jump target
+    pub warn_unaligned: bool,
+    // Warn when the PC goes below 0x200, running font or interpreter bytes
+    pub warn_low_pc: bool,
+    // Tick the delay timer every `cycles_per_frame` instructions instead of
+    // on the frame boundary, see `cycle`
+    pub smooth_delay_timer: bool,
+    // Turn a panic while executing an instruction into Fault::Internal, for
+    // hosts that have to outlive any ROM
+    pub robust: bool,
+    // Stop with Fault::CycleLimit after this many instructions, so a ROM
+    // stuck in a loop can't hang an unattended run
+    pub max_cycles: Option<u64>,
+    pub palette: [u32; 4],
+    // Show lit pixels in the background color and the other way round
+    pub invert_display: bool,
+    // Also show pixels lit at the end of the frame before, see
+    // `get_display_buffer`
+    pub persistence: bool,
+    // Decode the Chip-8X color opcodes, see CHIP8X_COLORS
+    pub chip8x: bool,
+}
+
+impl Default for Chip8Config {
+    fn default() -> Self {
+        Chip8Config {
+            memory_size: 4096,
+            start_address: 0x200,
+            seed: None,
+            cycles_per_frame: 11,
+            font: FONT,
+            font_address: 0x050,
+            quirks: Quirks::default(),
+            unknown_opcode_mode: UnknownOpcodeMode::Skip,
+            sys_call_mode: SysCallMode::Ignore,
+            index_mode: IndexMode::Full,
+            timing: TimingModel::Flat,
+            latch_keys: true,
+            warn_self_modifying: false,
+            warn_unaligned: false,
+            warn_low_pc: false,
+            smooth_delay_timer: false,
+            robust: false,
+            max_cycles: None,
+            palette: DEFAULT_PALETTE,
+            invert_display: false,
+            persistence: false,
+            chip8x: false,
+        }
+    }
+}
+
+// A write to a watched address, kept until the frontend takes it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WatchHit {
+    // Address of the instruction that did the write
+    pub pc: u16,
+    pub address: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+// One write to memory, for logging what a program stores where
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryWrite {
+    // Address of the instruction that did the write
+    pub pc: u16,
+    pub address: u16,
+    pub value: u8,
+}
+
+// What `cycle` does when it hits an opcode it doesn't know
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnknownOpcodeMode {
+    // Log it and carry on with the next instruction
+    Skip,
+    // Stop the machine with a fault
+    Halt,
+}
+
+// What `0NNN` does. It ran RCA 1802 machine code on the VIP, which nothing
+// here can emulate, so most interpreters just step over it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SysCallMode {
+    Ignore,
+    // Stop the machine with Fault::SysCall
+    Trap,
+}
+
+// What happens when ANNN, FX1E, FX29, F000 NNNN or the FX55/FX65 increment
+// would put I past the end of memory. Reads and writes through I always wrap into memory, so this
+// only changes the value I itself holds (and so what FX1E adds onto next).
+// With 64KB of memory all three behave the same.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IndexMode {
+    // Any 16-bit value, wrapping at 0xFFFF like XO-CHIP
+    Full,
+    // Wrapped to the memory size
+    Wrap,
+    // Held at the last memory address
+    Clamp,
+}
+
+// Chip-8X, the VIP with the VP-590 color board. With `chip8x` on these
+// opcodes change meaning, everything else stays Chip-8:
+//
+//     02A0  Step the background color: blue, black, green, red, round again
+//     5XY1  VX = VX + VY, each nibble added on its own and kept to 0-7
+//     BXY0  Color 8x4 pixel zones VY: columns from VX's low nibble, VX's high
+//           nibble more to the right, rows likewise from V(X+1)
+//     BXYN  Color the 8 pixel wide column at (VX, V(X+1)) for N rows VY
+//
+// Colors are 0-7: black, red, blue, violet, green, yellow, aqua, white.
+// BNNN (jump with V0 offset) doesn't exist on Chip-8X. The second keypad
+// and I/O port opcodes (EXF2, EXF5, FXF8, FXFB) aren't supported.
+pub const CHIP8X_COLORS: [u32; 8] = [
+    0x000000, 0xFF0000, 0x0000FF, 0xFF00FF, 0x00FF00, 0xFFFF00, 0x00FFFF, 0xFFFFFF,
+];
+pub const CHIP8X_BACKGROUNDS: [u32; 4] = [0x000080, 0x000000, 0x008000, 0x800000];
+
+// Keyboard keys for each Chip-8 key, as read by `set_keys`
+// Original Chip-8 keyboard layout:
+// 1 2 3 C
+// 4 5 6 D
+// 7 8 9 E
+// A 0 B F
+//
+// Mapped to normal keyboard:
+// 1 2 3 4
+// Q W E R
+// A S D F
+// Z X C V
+pub const KEYPAD_LAYOUT: [(Key, u8); 16] = [
+    (Key::Key1, 0x1),
+    (Key::Key2, 0x2),
+    (Key::Key3, 0x3),
+    (Key::Key4, 0xC),
+    (Key::Q, 0x4),
+    (Key::W, 0x5),
+    (Key::E, 0x6),
+    (Key::R, 0xD),
+    (Key::A, 0x7),
+    (Key::S, 0x8),
+    (Key::D, 0x9),
+    (Key::F, 0xE),
+    (Key::Z, 0xA),
+    (Key::X, 0x0),
+    (Key::C, 0xB),
+    (Key::V, 0xF),
+];
+
+// The Chip-8X color RAM: a foreground color per 8 pixel wide column of each
+// row, and one background for the whole screen
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Chip8xColors {
+    // Index into CHIP8X_BACKGROUNDS
+    pub background: u8,
+    // Index into CHIP8X_COLORS, zones[row][column]
+    pub zones: [[u8; 8]; 32],
+}
+
+impl Default for Chip8xColors {
+    // Red on blue, what the color board starts up with
+    fn default() -> Self {
+        Chip8xColors {
+            background: 0,
+            zones: [[1; 8]; 32],
+        }
+    }
+}
+
+// What an instruction costs out of the cycles a frame is given
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimingModel {
+    // One cycle each, the usual flat instructions per second
+    Flat,
+    // Roughly what they took on the COSMAC VIP, relative to a simple register
+    // instruction, see `vip_cost`. Drawing, clearing and the memory loops
+    // take much longer than arithmetic, which timing-sensitive ROMs notice.
+    Vip,
+}
+
+// Cycles `TimingModel::Vip` charges for an opcode. Rounded, the shape of
+// the VIP's timings rather than an exact match: the interpreter's fetch and
+// dispatch made even the quickest instruction cost something, and work
+// that loops over bytes or rows grows with them.
+fn vip_cost(opcode: u16) -> u32 {
+    let x = ((opcode & 0x0F00) >> 8) as u32;
+    let n = (opcode & 0x000F) as u32;
+    match opcode & 0xF000 {
+        0x0000 if opcode == 0x00E0 => 24,
+        0x0000 | 0x1000 | 0x2000 | 0xB000 => 2,
+        0x8000 => 2,
+        0xC000 => 3,
+        // Sprite rows are shifted into place and XORed a byte at a time
+        0xD000 => 4 + 3 * n.max(1),
+        0xF000 => match opcode & 0x00FF {
+            0x1E | 0x29 => 2,
+            0x33 => 8,
+            0x55 | 0x65 => 2 + x + 1,
+            _ => 1,
+        },
+        _ => 1,
+    }
+}
+
+// Runs opcodes the interpreter doesn't know, see `set_opcode_handler`
+pub type OpcodeHandler = Box<dyn FnMut(u16, &mut Chip8) -> bool + Send>;
+
+pub struct Chip8 {
+    // Memory: 4096 bytes (4KB) by default, XO-CHIP uses 64KB.
+    // Always a power of two so addresses can be masked into range.
+    memory: Vec<u8>,
+
+    // 16 general-purpose 8-bit registers (V0 to VF)
+    // VF is often used as a flag register so should be avoided.
+    registers: [u8; 16],
+
+    // Program counter, and where it starts
+    pc: u16,
+    start_address: u16,
+
+    // Index register
+    i: u16,
+
+    // 64 x 32 display, or 128 x 64 in hires, 8 pixels per byte with rows of
+    // `display_width() / 8` bytes. Lores only uses the start of each plane.
+    // XO-CHIP has two bitplanes that combine into a 2-bit color index per
+    // pixel.
+    display: [[u8; PLANE_BYTES]; 2],
+    // SCHIP's 128x64 mode, 00FF turns it on and 00FE off
+    hires: bool,
+    // Bitmask of the planes that drawing and clearing affect (FN01)
+    selected_planes: u8,
+    // Pixels last drawn by a sprite that wrapped around an edge to get there,
+    // same layout as a plane. Only for showing the wrap quirks.
+    wrapped: [u8; PLANE_BYTES],
+    // Set whenever a pixel changes, until the frontend clears it
+    display_dirty: bool,
+    // Color for each pixel value: off, plane 1 only, plane 2 only, both
+    palette: [u32; 4],
+    // Only changes how `get_display_buffer` colors the planes
+    invert_display: bool,
+    // The display at the last two 60Hz ticks, newest first, for
+    // `persistence`
+    frame_displays: [[[u8; PLANE_BYTES]; 2]; 2],
+    persistence: bool,
+    // With `chip8x`, pixels take their color from `chip8x_colors` instead of
+    // the palette
+    chip8x: bool,
+    chip8x_colors: Chip8xColors,
+
+    // Keypad input
+    keys: [bool; 16],      // Current key states
+    waiting_for_key: bool, // Is CPU waiting for input?
+    key_register: usize,   // Which register to store key in
+    // Keys already down when FX0A started, they have to be let go and
+    // pressed again to count
+    keys_held_at_wait: [bool; 16],
+    // A DXYN with `display_wait` ran, nothing more until the next timer tick
+    waiting_for_vblank: bool,
+    // Cycles an instruction still has to pay for under the timing model,
+    // they pass without running anything
+    stall_cycles: u32,
+    // Keys pressed since the last timer tick, so a tap shorter than a frame
+    // still reaches EX9E/EXA1/FX0A. Off with `latch_keys` false, except for
+    // the key that ended an FX0A wait.
+    latched_keys: [bool; 16],
+    latch_keys: bool,
+
+    // Source for CXNN, and the seed it was given if any
+    rng: StdRng,
+    seed: Option<u64>,
+    cycles_per_frame: u32,
+    font: [u8; 80],
+    font_address: u16,
+
+    // Timers
+    delay_timer: u8,
+    sound_timer: u8,
+    // Set by a nonzero FX18 until the frontend takes it, so a timer that runs
+    // out within the frame it was set still gets a frame of tone
+    beep_started: bool,
+    // With `smooth_delay_timer`, instructions left until the delay timer's
+    // next tick
+    smooth_delay_timer: bool,
+    cycles_until_delay_tick: u32,
+    // Neither timer counts down while set, for debugging timer-driven code
+    timers_frozen: bool,
+    // XO-CHIP audio pitch from FX3A, 64 is the neutral pitch
+    pitch: u8,
+
+    // Stack
+    stack: [u16; 16],
+    sp: usize,
+
+    // SCHIP's RPL user flags (HP48 calculator storage), saved with FX75 and
+    // read back with FX85. XO-CHIP allows all 16.
+    rpl: [u8; 16],
+
+    // Instructions executed since power-on, not counting FX0A waiting
+    cycle_count: u64,
+
+    // Set when the program stops on purpose, e.g. running into empty memory
+    halted: bool,
+
+    // Set when the machine hits something it can't recover from
+    fault: Option<Fault>,
+    // What the next instruction fails with instead of running, see
+    // `inject_fault`
+    #[cfg(test)]
+    injected_fault: Option<Fault>,
+    // Gets unknown opcodes before they're skipped or fault
+    opcode_handler: Option<OpcodeHandler>,
+    unknown_opcode_mode: UnknownOpcodeMode,
+    unknown_opcodes: u32,
+    sys_call_mode: SysCallMode,
+    index_mode: IndexMode,
+    timing: TimingModel,
+    robust: bool,
+    max_cycles: Option<u64>,
+
+    quirks: Quirks,
+    // `quirks.load_store_increments_i` until a 00FA flips it
+    load_store_increments_i: bool,
+
+    // Warn (once) when the program writes over the code it's running
+    warn_self_modifying: bool,
+    warned_self_modifying: bool,
+
+    // Warn (once) when an instruction is fetched from an odd address
+    warn_unaligned: bool,
+    warned_unaligned: bool,
+
+    // Warn (once) when an instruction is fetched from below 0x200
+    warn_low_pc: bool,
+    warned_low_pc: bool,
+
+    // Addresses the debugger wants to hear about writes to, and the first
+    // write that hit one since it last asked
+    watchpoints: HashSet<u16>,
+    watch_hit: Option<WatchHit>,
+
+    // Per-family instruction counts, only kept once profiling is turned on
+    profile: Option<Box<Profile>>,
+    // Instructions run per timer tick, only kept once turned on
+    frame_histogram: Option<Box<FrameHistogram>>,
+    // `cycle_count` at the last timer tick
+    frame_start_cycle: u64,
+    // Writes since the frontend last took them, once logging is turned on
+    write_log: Option<Vec<MemoryWrite>>,
+    // State after each DXYN since the frontend last took them, once turned on
+    draw_snapshots: Option<Vec<Snapshot>>,
+}
+
+impl Chip8 {
+    pub fn new() -> Self {
+        Self::with_config(Chip8Config::default())
+    }
+
+    pub fn with_memory_size(memory_size: usize) -> Self {
+        Self::with_config(Chip8Config {
+            memory_size,
+            ..Chip8Config::default()
+        })
+    }
+
+    pub fn with_config(config: Chip8Config) -> Self {
+        let memory_size = config.memory_size;
+        assert!(
+            memory_size.is_power_of_two() && (0x400..=0x10000).contains(&memory_size),
+            "memory size must be a power of two between 1KB and 64KB"
+        );
+        assert!(
+            (config.start_address as usize) < memory_size,
+            "start address must be inside memory"
+        );
+        assert!(
+            config.font_address as usize + FONT.len() <= memory_size,
+            "font must fit in memory"
+        );
+
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        let mut chip8 = Chip8 {
+            memory: vec![0; memory_size],
+            registers: [0; 16],
+            pc: config.start_address,
+            start_address: config.start_address,
+            i: 0,
+            display: [[0; PLANE_BYTES]; 2],
+            hires: false,
+            selected_planes: 0b01,
+            wrapped: [0; PLANE_BYTES],
+            display_dirty: false,
+            palette: config.palette,
+            invert_display: config.invert_display,
+            frame_displays: [[[0; PLANE_BYTES]; 2]; 2],
+            persistence: config.persistence,
+            chip8x: config.chip8x,
+            chip8x_colors: Chip8xColors::default(),
+            keys: [false; 16],
+            waiting_for_key: false,
+            key_register: 0,
+            keys_held_at_wait: [false; 16],
+            waiting_for_vblank: false,
+            stall_cycles: 0,
+            latched_keys: [false; 16],
+            latch_keys: config.latch_keys,
+            rng,
+            seed: config.seed,
+            cycles_per_frame: config.cycles_per_frame,
+            font: config.font,
+            font_address: config.font_address,
+            delay_timer: 0,
+            sound_timer: 0,
+            beep_started: false,
+            smooth_delay_timer: config.smooth_delay_timer,
+            cycles_until_delay_tick: config.cycles_per_frame,
+            timers_frozen: false,
+            pitch: DEFAULT_PITCH,
+            stack: [0; 16],
+            sp: 0,
+            rpl: [0; 16],
+            cycle_count: 0,
+            halted: false,
+            fault: None,
+            #[cfg(test)]
+            injected_fault: None,
+            opcode_handler: None,
+            unknown_opcode_mode: config.unknown_opcode_mode,
+            unknown_opcodes: 0,
+            sys_call_mode: config.sys_call_mode,
+            index_mode: config.index_mode,
+            timing: config.timing,
+            robust: config.robust,
+            max_cycles: config.max_cycles,
+            quirks: config.quirks,
+            load_store_increments_i: config.quirks.load_store_increments_i,
+            warn_self_modifying: config.warn_self_modifying,
+            warned_self_modifying: false,
+            warn_unaligned: config.warn_unaligned,
+            warned_unaligned: false,
+            warn_low_pc: config.warn_low_pc,
+            warned_low_pc: false,
+            watchpoints: HashSet::new(),
+            watch_hit: None,
+            profile: None,
+            frame_histogram: None,
+            frame_start_cycle: 0,
+            write_log: None,
+            draw_snapshots: None,
+        };
+
+        let font_start = config.font_address as usize;
+        chip8.memory[font_start..font_start + FONT.len()].copy_from_slice(&config.font);
+
+        chip8
+    }
+
+    pub fn set_unknown_opcode_mode(&mut self, mode: UnknownOpcodeMode) {
+        self.unknown_opcode_mode = mode;
+    }
+
+    // Also undoes any 00FA the program ran
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.load_store_increments_i = quirks.load_store_increments_i;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_warn_self_modifying(&mut self, warn: bool) {
+        self.warn_self_modifying = warn;
+    }
+
+    pub fn set_warn_unaligned(&mut self, warn: bool) {
+        self.warn_unaligned = warn;
+    }
+
+    // Whether the PC has been seen on an odd address, with `warn_unaligned` on
+    pub fn warned_unaligned(&self) -> bool {
+        self.warned_unaligned
+    }
+
+    pub fn set_warn_low_pc(&mut self, warn: bool) {
+        self.warn_low_pc = warn;
+    }
+
+    // Whether the PC has been seen below 0x200, with `warn_low_pc` on
+    pub fn warned_low_pc(&self) -> bool {
+        self.warned_low_pc
+    }
+
+    // The settings the machine is running with, including any changed since
+    // it was created
+    pub fn config(&self) -> Chip8Config {
+        Chip8Config {
+            memory_size: self.memory.len(),
+            start_address: self.start_address,
+            seed: self.seed,
+            cycles_per_frame: self.cycles_per_frame,
+            font: self.font,
+            font_address: self.font_address,
+            quirks: self.quirks,
+            unknown_opcode_mode: self.unknown_opcode_mode,
+            sys_call_mode: self.sys_call_mode,
+            index_mode: self.index_mode,
+            timing: self.timing,
+            latch_keys: self.latch_keys,
+            warn_self_modifying: self.warn_self_modifying,
+            warn_unaligned: self.warn_unaligned,
+            warn_low_pc: self.warn_low_pc,
+            smooth_delay_timer: self.smooth_delay_timer,
+            robust: self.robust,
+            max_cycles: self.max_cycles,
+            palette: self.palette,
+            invert_display: self.invert_display,
+            persistence: self.persistence,
+            chip8x: self.chip8x,
+        }
+    }
+
+    // Back to power-on state with an empty program. The settings are kept
+    // (a seeded RNG starts over), the ROM has to be loaded again.
+    pub fn reset(&mut self) {
+        let mut fresh = Self::with_config(self.config());
+        fresh.watchpoints = std::mem::take(&mut self.watchpoints);
+        // Like the calculator's storage, the flags outlive a reset
+        fresh.rpl = self.rpl;
+        fresh.profile = self.profile.take();
+        fresh.frame_histogram = self.frame_histogram.take();
+        fresh.opcode_handler = self.opcode_handler.take();
+        fresh.write_log = self.write_log.take();
+        fresh.draw_snapshots = self.draw_snapshots.take();
+        *self = fresh;
+    }
+
+    // Starts counting executed instructions by family, see `profile`
+    pub fn enable_profiling(&mut self) {
+        self.profile.get_or_insert_with(Box::default);
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_deref()
+    }
+
+    // Starts counting instructions per frame, see `frame_histogram`. A frame
+    // ends at each `update_timers`.
+    pub fn enable_frame_histogram(&mut self) {
+        self.frame_histogram.get_or_insert_with(Box::default);
+    }
+
+    pub fn frame_histogram(&self) -> Option<&FrameHistogram> {
+        self.frame_histogram.as_deref()
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    // Moves execution somewhere else, for debuggers. Drops an FX0A wait,
+    // which would otherwise step past the new PC when a key comes in. An
+    // address past the end of memory wraps, like every read does.
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = (pc as usize & (self.memory.len() - 1)) as u16;
+        self.waiting_for_key = false;
+    }
+
+    pub fn index(&self) -> u16 {
+        self.i
+    }
+
+    // Kept as given, what I past the end of memory means is up to the
+    // index mode
+    pub fn set_index(&mut self, i: u16) {
+        self.i = i;
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn set_register(&mut self, register: usize, value: u8) {
+        self.registers[register & 0xF] = value;
+    }
+
+    // Return addresses currently on the stack, oldest first
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp]
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    // Up to `len` bytes from `start`, cut short at the end of memory, for
+    // tools that show or export part of it
+    pub fn memory_slice(&self, start: u16, len: u16) -> &[u8] {
+        let start = (start as usize).min(self.memory.len());
+        let end = (start + len as usize).min(self.memory.len());
+        &self.memory[start..end]
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    // Stops both timers counting down while the CPU keeps running. FX15 and
+    // FX18 still set them.
+    pub fn set_timers_frozen(&mut self, frozen: bool) {
+        self.timers_frozen = frozen;
+    }
+
+    pub fn timers_frozen(&self) -> bool {
+        self.timers_frozen
+    }
+
+    // What FX15 and FX18 do, for tests and the debugger
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+        self.beep_started |= value > 0;
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    // How far FX3A moved the tone from its normal pitch, as a frequency
+    // multiplier. XO-CHIP raises it an octave for every 48 steps above 64.
+    pub fn pitch_ratio(&self) -> f32 {
+        2f32.powf((self.pitch as f32 - DEFAULT_PITCH as f32) / 48.0)
+    }
+
+    // The buzzer sounds for as long as the sound timer is running, and stops
+    // with the machine so a halt or fault never leaves a tone hanging. A
+    // reset clears the timer.
+    pub fn is_beeping(&self) -> bool {
+        self.sound_timer > 0 && !self.halted && self.fault.is_none()
+    }
+
+    // Whether the sound timer was set to anything since the last call, even
+    // if it has already run out. A click of FX18 with 1 is set and counted
+    // down within one frame, so a frontend sampling `is_beeping` after the
+    // timers never sees it; this is for playing at least a frame of it.
+    pub fn take_beep_started(&mut self) -> bool {
+        std::mem::take(&mut self.beep_started) && !self.halted && self.fault.is_none()
+    }
+
+    pub fn rpl_flags(&self) -> [u8; 16] {
+        self.rpl
+    }
+
+    pub fn set_rpl_flags(&mut self, flags: [u8; 16]) {
+        self.rpl = flags;
+    }
+
+    pub fn cycle_count(&self) -> u64 {
+        self.cycle_count
+    }
+
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.waiting_for_key
+    }
+
+    // True from a DXYN with the `display_wait` quirk until the next timer
+    // tick. Cycles run meanwhile do nothing, so a frontend can end the frame.
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.waiting_for_vblank
+    }
+
+    // True while the last instruction's cost under the timing model is
+    // being paid off, the next `cycle` won't run anything
+    pub fn is_stalled(&self) -> bool {
+        self.stall_cycles > 0
+    }
+
+    // Cycles the timing model charges for `opcode`, at least 1
+    pub fn instruction_cost(&self, opcode: u16) -> u32 {
+        match self.timing {
+            TimingModel::Flat => 1,
+            TimingModel::Vip => vip_cost(opcode),
+        }
+    }
+
+    // Answers a pending FX0A with `key` straight away, without going through
+    // the keypad. Returns false if nothing was waiting.
+    pub fn supply_key(&mut self, key: u8) -> bool {
+        if !self.waiting_for_key {
+            return false;
+        }
+        self.finish_key_wait(key as usize & 0xF);
+        true
+    }
+
+    // Ends an FX0A wait with `key`. The key stays down for EX9E/EXA1 until
+    // the frame ends, even if it was let go or never really pressed, so a ROM
+    // checking the key it just waited for sees it.
+    fn finish_key_wait(&mut self, key: usize) {
+        self.registers[self.key_register] = key as u8;
+        self.latched_keys[key] = true;
+        self.waiting_for_key = false;
+        self.pc = self.pc.wrapping_add(2);
+    }
+
+    // True when the PC is at the top of a loop that just waits for the delay
+    // timer to run out:
+    //
+    //     loop: LD VX, DT
+    //           SE VX, 0
+    //           JP loop
+    //
+    // Nothing changes until the timer ticks, so a frontend can stop running
+    // cycles for the rest of the frame. Only this exact shape is recognised.
+    // Never true with `smooth_delay_timer`, where the cycles are the clock.
+    pub fn is_waiting_on_delay_timer(&self) -> bool {
+        if self.smooth_delay_timer {
+            return false;
+        }
+        let pc = self.pc;
+        let read_dt = self.opcode_at(pc);
+        let x = read_dt & 0x0F00;
+        self.delay_timer > 0
+            && read_dt & 0xF0FF == 0xF007
+            && self.opcode_at(pc.wrapping_add(2)) == 0x3000 | x
+            && self.opcode_at(pc.wrapping_add(4)) == 0x1000 | (pc & 0x0FFF)
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn fault(&self) -> Option<&Fault> {
+        self.fault.as_ref()
+    }
+
+    pub fn set_palette(&mut self, palette: [u32; 4]) {
+        self.palette = palette;
+    }
+
+    pub fn palette(&self) -> [u32; 4] {
+        self.palette
+    }
+
+    pub fn set_invert_display(&mut self, invert: bool) {
+        self.invert_display = invert;
+    }
+
+    pub fn invert_display(&self) -> bool {
+        self.invert_display
+    }
+
+    pub fn set_persistence(&mut self, persistence: bool) {
+        self.persistence = persistence;
+    }
+
+    pub fn persistence(&self) -> bool {
+        self.persistence
+    }
+
+    // Size of the active display mode in pixels, 64x32 or 128x64 in hires
+    pub fn display_width(&self) -> usize {
+        if self.hires { 128 } else { 64 }
+    }
+
+    pub fn display_height(&self) -> usize {
+        if self.hires { 64 } else { 32 }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    // Bytes of each plane the active mode uses
+    fn plane_bytes(&self) -> usize {
+        self.display_width() * self.display_height() / 8
+    }
+
+    // Width and height together, for frontends sizing their surface. Ask
+    // again after running instructions rather than keeping the first answer.
+    pub fn display_dimensions(&self) -> (usize, usize) {
+        (self.display_width(), self.display_height())
+    }
+
+    // Bitplanes the display shows: two (XO-CHIP's FN01 picks which ones are
+    // drawn to), one on Chip-8X where only the first plane is colored in
+    pub fn plane_count(&self) -> usize {
+        if self.chip8x { 1 } else { 2 }
+    }
+
+    // The display as 0xRRGGBB pixels, row by row. With `persistence` a pixel
+    // lit at the end of the previous frame stays lit for this one too, which
+    // hides the flicker of games that erase and redraw sprites with XOR. The
+    // emulated display itself is unchanged.
+    pub fn get_display_buffer(&self) -> Vec<u32> {
+        let (width, height) = self.display_dimensions();
+        let mut buffer = vec![0u32; width * height];
+        // Inverting flips both plane bits, so off and "both planes" swap too
+        let invert = if self.invert_display { 0b11 } else { 0 };
+        let mut display = self.display;
+        if self.persistence {
+            // The newest tick's copy is what's on the display right after
+            // it, the one before is the previous frame
+            for (plane, previous) in display.iter_mut().zip(&self.frame_displays[1]) {
+                for (byte, previous) in plane.iter_mut().zip(previous) {
+                    *byte |= previous;
+                }
+            }
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                let byte_index = (y * width / 8) + (x / 8);
+                let bit_position = 7 - (x % 8);
+                let plane0 = (display[0][byte_index] >> bit_position) & 1;
+                let plane1 = (display[1][byte_index] >> bit_position) & 1;
+
+                buffer[y * width + x] = if self.chip8x {
+                    // One plane, lit pixels in their zone's color. The zones
+                    // are lores sized, so hires pixels share them.
+                    let colors = &self.chip8x_colors;
+                    if (plane0 as usize ^ invert) & 1 == 1 {
+                        let zone = colors.zones[y * 32 / height][x * 64 / width / 8];
+                        CHIP8X_COLORS[zone as usize]
+                    } else {
+                        CHIP8X_BACKGROUNDS[colors.background as usize]
+                    }
+                } else {
+                    self.palette[(plane1 << 1 | plane0) as usize ^ invert]
+                };
+            }
+        }
+        buffer
+    }
+
+    // `get_display_buffer` with every pixel as a `scale` x `scale` block, so
+    // the buffer is width * scale by height * scale. A scale of 0 is treated
+    // as 1.
+    pub fn get_display_buffer_scaled(&self, scale: usize) -> Vec<u32> {
+        let scale = scale.max(1);
+        let (width, height) = self.display_dimensions();
+        let mut scaled = Vec::with_capacity(width * height * scale * scale);
+        for row in self.get_display_buffer().chunks(width) {
+            let wide: Vec<u32> = row
+                .iter()
+                .flat_map(|&color| std::iter::repeat_n(color, scale))
+                .collect();
+            for _ in 0..scale {
+                scaled.extend_from_slice(&wide);
+            }
+        }
+        scaled
+    }
+
+    // Whether any pixel changed since the last `clear_display_dirty`
+    pub fn display_dirty(&self) -> bool {
+        self.display_dirty
+    }
+
+    pub fn clear_display_dirty(&mut self) {
+        self.display_dirty = false;
+    }
+
+    // Which pixels of `get_display_buffer` a sprite wrapped around an edge
+    // to reach, for debug overlays
+    pub fn wrapped_pixels(&self) -> Vec<bool> {
+        (0..self.plane_bytes() * 8)
+            .map(|pixel| self.wrapped[pixel / 8] & (0x80 >> (pixel % 8)) != 0)
+            .collect()
+    }
+
+    // One plane of the packed display as it's stored, for frontends that
+    // unpack it themselves: `display_width() / 8` bytes per row for
+    // `display_height()` rows, leftmost pixel in the top bit
+    pub fn display_bytes(&self, plane: usize) -> &[u8] {
+        &self.display[plane][..self.plane_bytes()]
+    }
+
+    // The display as rows of pixels, true where a pixel is lit in either
+    // plane. Indexed as grid[y][x].
+    pub fn display_grid(&self) -> Vec<Vec<bool>> {
+        (0..self.display_height())
+            .map(|y| {
+                (0..self.display_width())
+                    .map(|x| self.get_pixel(0, x, y) || self.get_pixel(1, x, y))
+                    .collect()
+            })
+            .collect()
+    }
+
+    // The display as text, one line per row: '#' for a pixel lit in either
+    // plane, '.' for off
+    pub fn render_text(&self) -> String {
+        let mut text = String::with_capacity((self.display_width() + 1) * self.display_height());
+        for row in self.display_grid() {
+            text.extend(row.iter().map(|&lit| if lit { '#' } else { '.' }));
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn print_display(&self) {
+        print!("{}", self.render_text());
+    }
+
+    pub fn set_keys(&mut self, window: &Window) {
+        for (host_key, key) in KEYPAD_LAYOUT {
+            self.set_key(key, window.is_key_down(host_key));
+        }
+    }
+
+    // For frontends that don't use a minifb window
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        let key = key as usize & 0xF;
+        self.keys[key] = pressed;
+        if pressed && self.latch_keys {
+            self.latched_keys[key] = true;
+        }
+    }
+
+    // Keys held right now, as last set by `set_key` or `set_keys`
+    pub fn keys(&self) -> [bool; 16] {
+        self.keys
+    }
+
+    // Held now, or pressed at some point this frame when latching
+    fn key_down(&self, key: usize) -> bool {
+        self.keys[key] || self.latched_keys[key]
+    }
+
+    // Copies the program to the start address and nothing else, so on a
+    // machine that already ran call `reset` first or use `load_rom`
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), Chip8Error> {
+        self.force_load_at(self.start_address, program)
+    }
+
+    // Writes data anywhere in memory, e.g. tables a ROM expects preloaded.
+    // Refuses to touch the interpreter area below 0x200 or the font, see
+    // `force_load_at` for that.
+    pub fn load_at(&mut self, addr: u16, bytes: &[u8]) -> Result<(), Chip8Error> {
+        let start = addr as usize;
+        let end = start + bytes.len();
+        let font = self.font_address as usize..self.font_address as usize + FONT.len();
+        if let Some(reserved) =
+            (start..end).find(|&a| a < INTERPRETER_AREA_END as usize || font.contains(&a))
+        {
+            return Err(Chip8Error::ReservedMemory(reserved as u16));
+        }
+        self.force_load_at(addr, bytes)
+    }
+
+    pub fn force_load_at(&mut self, addr: u16, bytes: &[u8]) -> Result<(), Chip8Error> {
+        let start = addr as usize;
+        let capacity = self.memory.len().saturating_sub(start);
+        if bytes.len() > capacity {
+            return Err(Chip8Error::RomTooLarge(bytes.len(), capacity));
+        }
+        self.memory[start..start + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    // Resets the machine and loads a new program. A ROM that doesn't fit
+    // leaves the machine as it was.
+    pub fn load_rom(&mut self, program: &[u8]) -> Result<(), Chip8Error> {
+        let capacity = self.memory.len() - self.start_address as usize;
+        if program.len() > capacity {
+            return Err(Chip8Error::RomTooLarge(program.len(), capacity));
+        }
+        self.reset();
+        self.load_program(program)
+    }
+
+    // All memory access goes through these so addresses wrap to the memory size
+    // A new value for I, kept in memory by `index_mode`
+    fn index_in_range(&self, value: u32) -> u16 {
+        let last = self.memory.len() as u32 - 1;
+        match self.index_mode {
+            IndexMode::Full => value as u16,
+            IndexMode::Wrap => (value & last) as u16,
+            IndexMode::Clamp => value.min(last) as u16,
+        }
+    }
+
+    // No checks on reads: the self-modifying warning and watchpoints are
+    // about writes, the unaligned and low PC warnings about the PC. A ROM
+    // pointing I at its own code to draw it as a sprite never trips them.
+    fn read_byte(&self, addr: u16) -> u8 {
+        self.memory[addr as usize & (self.memory.len() - 1)]
+    }
+
+    fn write_byte(&mut self, addr: u16, value: u8) {
+        let mask = self.memory.len() - 1;
+
+        // A write to the current or neighbouring instructions
+        if self.warn_self_modifying
+            && !self.warned_self_modifying
+            && addr.abs_diff(self.pc) <= SELF_MODIFYING_WINDOW
+        {
+            println!(
+                "Warning: instruction at {:#05X} wrote to {:#05X}, the program may be modifying itself",
+                self.pc, addr
+            );
+            self.warned_self_modifying = true;
+        }
+
+        let index = addr as usize & mask;
+        if self.watch_hit.is_none() && self.watchpoints.contains(&(index as u16)) {
+            self.watch_hit = Some(WatchHit {
+                pc: self.pc,
+                address: index as u16,
+                old: self.memory[index],
+                new: value,
+            });
+        }
+
+        if let Some(log) = &mut self.write_log {
+            log.push(MemoryWrite {
+                pc: self.pc,
+                address: index as u16,
+                value,
+            });
+        }
+
+        self.memory[index] = value;
+    }
+
+    // Starts keeping every memory write, see `take_writes`
+    pub fn enable_write_log(&mut self) {
+        self.write_log.get_or_insert_with(Vec::new);
+    }
+
+    // Writes logged since the last call, oldest first
+    pub fn take_writes(&mut self) -> Vec<MemoryWrite> {
+        self.write_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    // Starts keeping a snapshot after every DXYN, see `take_draw_snapshots`.
+    // Each one holds all of memory, so take them often.
+    pub fn enable_draw_snapshots(&mut self) {
+        self.draw_snapshots.get_or_insert_with(Vec::new);
+    }
+
+    // Snapshots taken since the last call, oldest first
+    pub fn take_draw_snapshots(&mut self) -> Vec<Snapshot> {
+        self.draw_snapshots
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    pub fn add_watchpoint(&mut self, addr: u16) {
+        self.watchpoints
+            .insert((addr as usize & (self.memory.len() - 1)) as u16);
+    }
+
+    pub fn remove_watchpoint(&mut self, addr: u16) {
+        self.watchpoints
+            .remove(&((addr as usize & (self.memory.len() - 1)) as u16));
+    }
+
+    // The first watched write since the last call, if any
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self.memory.clone(),
+            registers: self.registers,
+            pc: self.pc,
+            i: self.i,
+            stack: self.stack().to_vec(),
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            pitch: self.pitch,
+            display: self
+                .display
+                .iter()
+                .map(|plane| plane[..self.plane_bytes()].to_vec())
+                .collect(),
+            hires: self.hires,
+            selected_planes: self.selected_planes,
+            waiting_for_key: self.waiting_for_key.then_some(self.key_register),
+            halted: self.halted,
+            cycle_count: self.cycle_count,
+            rpl: self.rpl,
+            chip8x_colors: self.chip8x_colors,
+        }
+    }
+
+    // Settings stay as they are. The snapshot has to come from a machine with
+    // the same memory size.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), Chip8Error> {
+        if snapshot.memory.len() != self.memory.len() {
+            return Err(Chip8Error::BadSnapshot(format!(
+                "snapshot has {} bytes of memory, this machine has {}",
+                snapshot.memory.len(),
+                self.memory.len()
+            )));
+        }
+        let (width, height) = snapshot.display_dimensions();
+        let plane_bytes = width * height / 8;
+        if snapshot.stack.len() > self.stack.len()
+            || snapshot.display.len() != 2
+            || snapshot
+                .display
+                .iter()
+                .any(|plane| plane.len() != plane_bytes)
+            || snapshot
+                .waiting_for_key
+                .is_some_and(|register| register > 0xF)
+        {
+            return Err(Chip8Error::BadSnapshot("snapshot is corrupt".to_string()));
+        }
+        // The zone count is fixed by the type, the colors index into tables
+        let colors = &snapshot.chip8x_colors;
+        if colors.background as usize >= CHIP8X_BACKGROUNDS.len()
+            || colors
+                .zones
+                .iter()
+                .flatten()
+                .any(|&color| color as usize >= CHIP8X_COLORS.len())
+        {
+            return Err(Chip8Error::BadSnapshot("snapshot is corrupt".to_string()));
+        }
+
+        self.memory.copy_from_slice(&snapshot.memory);
+        self.registers = snapshot.registers;
+        self.pc = snapshot.pc;
+        self.i = snapshot.i;
+        self.stack = [0; 16];
+        self.stack[..snapshot.stack.len()].copy_from_slice(&snapshot.stack);
+        self.sp = snapshot.stack.len();
+        self.delay_timer = snapshot.delay_timer;
+        self.sound_timer = snapshot.sound_timer;
+        self.pitch = snapshot.pitch;
+        self.hires = snapshot.hires;
+        self.display = [[0; PLANE_BYTES]; 2];
+        for (plane, saved) in self.display.iter_mut().zip(&snapshot.display) {
+            plane[..saved.len()].copy_from_slice(saved);
+        }
+        self.frame_displays = [self.display; 2];
+        self.wrapped = [0; PLANE_BYTES];
+        self.display_dirty = true;
+        self.selected_planes = snapshot.selected_planes;
+        self.waiting_for_key = snapshot.waiting_for_key.is_some();
+        self.key_register = snapshot.waiting_for_key.unwrap_or(0);
+        // Keys aren't saved, so whatever is down now is treated as held
+        self.keys_held_at_wait = self.keys;
+        self.waiting_for_vblank = false;
+        self.stall_cycles = 0;
+        self.halted = snapshot.halted;
+        self.cycle_count = snapshot.cycle_count;
+        self.frame_start_cycle = snapshot.cycle_count;
+        self.rpl = snapshot.rpl;
+        self.chip8x_colors = snapshot.chip8x_colors;
+        self.fault = None;
+        self.unknown_opcodes = 0;
+        Ok(())
+    }
+
+    // Readable dump of the machine for bug reports and comparing runs.
+    // Memory is left out, see `dump_state_json_with_memory`.
+    pub fn dump_state_json(&self) -> String {
+        serde_json::to_string_pretty(&self.state_json(false)).unwrap()
+    }
+
+    pub fn dump_state_json_with_memory(&self) -> String {
+        serde_json::to_string_pretty(&self.state_json(true)).unwrap()
+    }
+
+    fn state_json(&self, include_memory: bool) -> serde_json::Value {
+        // One string per row: '.' off, '#' plane 1, '+' plane 2, '*' both
+        let display: Vec<String> = (0..self.display_height())
+            .map(|y| {
+                (0..self.display_width())
+                    .map(
+                        |x| match (self.get_pixel(0, x, y), self.get_pixel(1, x, y)) {
+                            (false, false) => '.',
+                            (true, false) => '#',
+                            (false, true) => '+',
+                            (true, true) => '*',
+                        },
+                    )
+                    .collect()
+            })
+            .collect();
+        let pressed: Vec<usize> = (0..16).filter(|&key| self.keys[key]).collect();
+
+        let mut state = serde_json::json!({
+            "pc": self.pc,
+            "i": self.i,
+            "sp": self.sp,
+            "cycle_count": self.cycle_count,
+            "rpl": self.rpl,
+            "stack": self.stack(),
+            "registers": self.registers,
+            "delay_timer": self.delay_timer,
+            "sound_timer": self.sound_timer,
+            "pitch": self.pitch,
+            "keys": pressed,
+            "waiting_for_key": self.waiting_for_key,
+            "halted": self.halted,
+            "fault": self.fault.as_ref().map(|fault| fault.to_string()),
+            "hires": self.hires,
+            "display": display,
+        });
+        if include_memory {
+            let memory: String = self.memory.iter().map(|b| format!("{:02X}", b)).collect();
+            state["memory"] = memory.into();
+        }
+        state
+    }
+
+    // Resolution and both planes, without the rest of the machine
+    pub fn display_snapshot(&self) -> DisplaySnapshot {
+        let (width, height) = self.display_dimensions();
+        DisplaySnapshot {
+            width,
+            height,
+            planes: self
+                .display
+                .iter()
+                .map(|plane| plane[..self.plane_bytes()].to_vec())
+                .collect(),
+        }
+    }
+
+    // Hash of the display for checking a ROM's output (--expect-hash), the
+    // same on every run and machine
+    pub fn framebuffer_hash(&self) -> u64 {
+        self.display_snapshot().fnv_hash()
+    }
+
+    // The display run-length encoded for test fixtures, see
+    // `DisplaySnapshot::to_rle`
+    pub fn export_display_rle(&self) -> Vec<u8> {
+        self.display_snapshot().to_rle()
+    }
+
+    // Replaces the display with one from `export_display_rle`, in the
+    // active mode. Data that doesn't cover the screen exactly leaves the
+    // display as it was.
+    pub fn import_display_rle(&mut self, rle: &[u8]) -> Result<(), Chip8Error> {
+        let (width, height) = self.display_dimensions();
+        let snapshot =
+            DisplaySnapshot::from_rle(rle, width, height, 2).map_err(Chip8Error::BadSnapshot)?;
+        for (plane, bits) in self.display.iter_mut().zip(&snapshot.planes) {
+            self.display_dirty |= plane[..bits.len()] != bits[..];
+            plane[..bits.len()].copy_from_slice(bits);
+        }
+        self.wrapped = [0; PLANE_BYTES];
+        Ok(())
+    }
+
+    // Replaces the display with a picture, one bool per pixel row by row, lit
+    // pixels on the first plane. It has to be the size of the active mode.
+    pub fn import_display_pixels(
+        &mut self,
+        pixels: &[bool],
+        width: usize,
+        height: usize,
+    ) -> Result<(), Chip8Error> {
+        let (display_width, display_height) = self.display_dimensions();
+        if (width, height) != (display_width, display_height) || pixels.len() != width * height {
+            return Err(Chip8Error::BadSnapshot(format!(
+                "the picture is {}x{}, the display is {}x{}",
+                width, height, display_width, display_height
+            )));
+        }
+        let mut display = [[0; PLANE_BYTES]; 2];
+        for (pixel, _) in pixels.iter().enumerate().filter(|&(_, &on)| on) {
+            display[0][pixel / 8] |= 0x80 >> (pixel % 8);
+        }
+        self.display_dirty |= self.display != display;
+        self.display = display;
+        self.wrapped = [0; PLANE_BYTES];
+        Ok(())
+    }
+
+    fn get_pixel(&self, plane: usize, x: usize, y: usize) -> bool {
+        self.display[plane][y * self.display_width() / 8 + x / 8] & (0x80 >> (x % 8)) != 0
+    }
+
+    fn set_pixel(&mut self, plane: usize, x: usize, y: usize, on: bool) {
+        let index = y * self.display_width() / 8 + x / 8;
+        let byte = &mut self.display[plane][index];
+        let old = *byte;
+        if on {
+            *byte |= 0x80 >> (x % 8);
+        } else {
+            *byte &= !(0x80 >> (x % 8));
+        }
+        self.display_dirty |= *byte != old;
+    }
+
+    // Only the low nibble of VX picks a key, like the VIP's keypad decoder,
+    // so a stray value can't index past the 16 keys
+    fn key_pressed(&self, value: u8) -> bool {
+        self.key_down((value & 0x0F) as usize)
+    }
+
+    // Steps over the instruction after the current one, all four bytes of
+    // XO-CHIP's F000 NNNN
+    fn skip_next(&mut self) {
+        let next = self.pc.wrapping_add(2);
+        let size = if self.opcode_at(next) == 0xF000 { 4 } else { 2 };
+        self.pc = self.pc.wrapping_add(size);
+    }
+
+    // VX for the logic opcodes, all three have to agree on the VF reset
+    fn set_logic_result(&mut self, x: usize, result: u8) {
+        self.registers[x] = result;
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
+    }
+
+    fn fetch(&self) -> u16 {
+        self.opcode_at(self.pc)
+    }
+
+    // The two bytes at `addr` as an opcode, without executing anything
+    pub fn opcode_at(&self, addr: u16) -> u16 {
+        let high_byte = self.read_byte(addr) as u16;
+        let low_byte = self.read_byte(addr.wrapping_add(1)) as u16;
+        (high_byte << 8) | low_byte
+    }
+
+    // Turns off every pixel in the selected planes, what 00E0 does
+    pub fn clear_display(&mut self) {
+        for plane in 0..2 {
+            if self.selected_planes & (1 << plane) != 0 {
+                let bits = &mut self.display[plane];
+                self.display_dirty |= bits.iter().any(|&byte| byte != 0);
+                bits.fill(0);
+            }
+        }
+        self.wrapped.fill(0);
+    }
+
+    // 00FE/00FF. The whole display is cleared, every plane and the frames
+    // kept for `persistence`, since the old picture doesn't fit the new mode.
+    fn set_hires(&mut self, hires: bool) {
+        if hires == self.hires {
+            return;
+        }
+        self.hires = hires;
+        self.display = [[0; PLANE_BYTES]; 2];
+        self.frame_displays = [self.display; 2];
+        self.wrapped = [0; PLANE_BYTES];
+        self.display_dirty = true;
+    }
+
+    // Stores an 8XYN result in VX and its flag in VF. The operands are read
+    // before either is written, so only X = F is affected by the order: the
+    // flag wins unless `vf_written_first`. DXYN has no result register and
+    // reads VX and VY before setting VF, so it's the same everywhere.
+    fn set_result_and_flag(&mut self, x: usize, result: u8, flag: u8) {
+        if self.quirks.vf_written_first {
+            self.registers[0xF] = flag;
+            self.registers[x] = result;
+        } else {
+            self.registers[x] = result;
+            self.registers[0xF] = flag;
+        }
+    }
+
+    // The register 8XY6/8XYE shift, read before anything is written
+    fn shift_source(&self, x: usize, y: usize) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.registers[y]
+        } else {
+            self.registers[x]
+        }
+    }
+
+    // How far a scroll opcode moves for its nominal distance, in pixels of
+    // the active mode
+    fn scroll_distance(&self, distance: usize) -> usize {
+        if self.quirks.half_lores_scroll && !self.hires {
+            distance / 2
+        } else {
+            distance
+        }
+    }
+
+    // Moves the selected planes down by `rows`, or up when negative. Rows
+    // scrolled in are blank.
+    fn scroll_vertical(&mut self, rows: isize) {
+        let size = self.plane_bytes();
+        let shift = rows.unsigned_abs().min(self.display_height()) * self.display_width() / 8;
+        for plane in 0..2 {
+            if self.selected_planes & (1 << plane) == 0 {
+                continue;
+            }
+            let bits = &mut self.display[plane][..size];
+            let before = bits.to_vec();
+            if rows > 0 {
+                bits.copy_within(..size - shift, shift);
+                bits[..shift].fill(0);
+            } else {
+                bits.copy_within(shift.., 0);
+                bits[size - shift..].fill(0);
+            }
+            self.display_dirty |= *bits != before[..];
+        }
+        self.wrapped = [0; PLANE_BYTES];
+    }
+
+    // Moves the selected planes right by `pixels`, or left when negative.
+    // Columns scrolled in are blank.
+    fn scroll_horizontal(&mut self, pixels: isize) {
+        let (size, stride) = (self.plane_bytes(), self.display_width() / 8);
+        let shift = pixels.unsigned_abs().min(self.display_width()) as u32;
+        for plane in 0..2 {
+            if self.selected_planes & (1 << plane) == 0 {
+                continue;
+            }
+            // Each row from the top of a u128, lores rows leave the low half
+            // empty and whatever is shifted into it drops off the right edge
+            for row in self.display[plane][..size].chunks_mut(stride) {
+                let mut bytes = [0; 16];
+                bytes[..stride].copy_from_slice(row);
+                let bits = u128::from_be_bytes(bytes);
+                let scrolled = if pixels > 0 {
+                    bits.checked_shr(shift).unwrap_or(0)
+                } else {
+                    bits.checked_shl(shift).unwrap_or(0)
+                };
+                let scrolled = &scrolled.to_be_bytes()[..stride];
+                self.display_dirty |= row != scrolled;
+                row.copy_from_slice(scrolled);
+            }
+        }
+        self.wrapped = [0; PLANE_BYTES];
+    }
+
+    fn execute(&mut self, opcode: u16) -> Result<(), Fault> {
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let y = ((opcode & 0x00F0) >> 4) as usize;
+        let n = (opcode & 0x000F) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+
+        match opcode & 0xF000 {
+            0x0000 => match opcode {
+                0x0000 => {
+                    // 0000: Not a real instruction, the PC ran into zeroed memory
+                    if self.cycle_count == 1 && self.pc == self.start_address {
+                        println!(
+                            "The program starts with 0x0000 at {:#05X}, halting. The ROM may be empty or need a different start address.",
+                            self.pc
+                        );
+                    } else {
+                        println!("Executed into empty memory at {:#05X}, halting", self.pc);
+                    }
+                    self.halted = true;
+                }
+                0x00C0..=0x00CF => {
+                    // 00CN: Scroll the selected planes down N rows
+                    let rows = self.scroll_distance(n as usize);
+                    self.scroll_vertical(rows as isize);
+                }
+                0x00D0..=0x00DF => {
+                    // 00DN: Scroll the selected planes up N rows
+                    let rows = self.scroll_distance(n as usize);
+                    self.scroll_vertical(-(rows as isize));
+                }
+                0x00FA => {
+                    // 00FA: SCHIP compatibility, flip whether FX55/FX65
+                    // move I past the registers. Beats the quirk until the
+                    // quirks are set again or the machine is reset.
+                    self.load_store_increments_i = !self.load_store_increments_i;
+                }
+                0x00FB => {
+                    // 00FB: Scroll the selected planes right 4 pixels
+                    let pixels = self.scroll_distance(4);
+                    self.scroll_horizontal(pixels as isize);
+                }
+                0x00FC => {
+                    // 00FC: Scroll the selected planes left 4 pixels
+                    let pixels = self.scroll_distance(4);
+                    self.scroll_horizontal(-(pixels as isize));
+                }
+                0x00FD => {
+                    // 00FD: SCHIP, exit the interpreter. Halting also
+                    // silences a beep that's still playing.
+                    self.halted = true;
+                }
+                0x00FE => {
+                    // 00FE: SCHIP, back to the 64x32 lores mode
+                    self.set_hires(false);
+                }
+                0x00FF => {
+                    // 00FF: SCHIP, 128x64 hires mode. DXY0's 16x16 sprites
+                    // aren't supported, it still draws nothing.
+                    self.set_hires(true);
+                }
+                0x00E0 => {
+                    // 00E0 Clear display (only the selected planes)
+                    self.clear_display();
+                }
+                0x00EE => {
+                    // 00EE: Return from subroutine
+                    if self.sp == 0 {
+                        return Err(Fault::StackUnderflow);
+                    }
+                    self.sp -= 1;
+                    self.pc = self.stack[self.sp];
+                }
+                0x02A0 if self.chip8x => {
+                    // 02A0: Chip-8X, step the background color
+                    let colors = &mut self.chip8x_colors;
+                    colors.background = (colors.background + 1) % CHIP8X_BACKGROUNDS.len() as u8;
+                    self.display_dirty = true;
+                }
+                // Anything above that isn't decoded: 0NNN, a machine code call
+                _ => {
+                    if self.sys_call_mode == SysCallMode::Trap {
+                        return Err(Fault::SysCall(nnn));
+                    }
+                }
+            },
+
+            0x1000 => {
+                // 1NNN jumps to address NNN
+                // println!("Jump to PC{:#05X}", nnn);
+                self.pc = nnn.wrapping_sub(2);
+            }
+            0x2000 => {
+                // 2NNN: Call subroutine at NNN
+                if self.sp == self.stack.len() {
+                    return Err(Fault::StackOverflow);
+                }
+                self.stack[self.sp] = self.pc;
+                self.sp += 1;
+                self.pc = nnn.wrapping_sub(2);
+            }
+
+            0x3000 => {
+                // 3XNN Skips the next instruction if VX equals NN
+                if self.registers[x] == nn {
+                    // println!("Skipping next instruction");
+                    self.skip_next();
+                } else {
+                    // println!("Continuing next instruction");
+                }
+            }
+
+            0x4000 => {
+                // 4XNN Skips the next instruction of VX does NOT equal NN
+                if self.registers[x] != nn {
+                    // println!("Skipping next instruction");
+                    self.skip_next();
+                } else {
+                    // println!("Continuing next instruction");
+                }
+            }
+
+            0x5000 if self.chip8x && n == 1 => {
+                // 5XY1: Chip-8X, add VY to VX a nibble at a time, each 0-7
+                let (vx, vy) = (self.registers[x], self.registers[y]);
+                let high = ((vx >> 4) + (vy >> 4)) & 0x7;
+                let low = ((vx & 0xF) + (vy & 0xF)) & 0x7;
+                self.registers[x] = high << 4 | low;
+            }
+            0x5000 if n == 0 => {
+                // 5XY0 Skips the next instruction of VX equals VY
+                if self.registers[x] == self.registers[y] {
+                    // println!("Skipping next instruction");
+                    self.skip_next();
+                } else {
+                    // println!("Continuing next instruction");
+                }
+            }
+
+            0x6000 => {
+                // 6XNN: Set register VX to NN
+                // println!("Set V{:X} = {:#04X}", x, nn);
+                self.registers[x] = nn;
+            }
+
+            0x7000 => {
+                // 7XNN: Add NN to register VX
+                // println!("Add {:#04X} to V{:X}", nn, x);
+                self.registers[x] = self.registers[x].wrapping_add(nn);
+            }
+
+            0x8000 => {
+                // 8XY_: Register operations
+                match opcode & 0x000F {
+                    0x0000 => {
+                        // 8XY0: VX = VY
+                        self.registers[x] = self.registers[y];
+                    }
+                    0x0001 => {
+                        // 8XY1: Bitwise VX OR VY
+                        let result = self.registers[x] | self.registers[y];
+                        self.set_logic_result(x, result);
+                    }
+                    0x0002 => {
+                        // 8XY2: Bitwise VX AND VY
+                        let result = self.registers[x] & self.registers[y];
+                        self.set_logic_result(x, result);
+                    }
+                    0x0003 => {
+                        // 8XY3: Bitwise VX XOR VY
+                        let result = self.registers[x] ^ self.registers[y];
+                        self.set_logic_result(x, result);
+                    }
+                    0x0004 => {
+                        // 8XY4: ADD VY to VX, set VF = carry
+                        // println!("V{:X} += V{:X}", x, y);
+                        let (result, overflow) =
+                            self.registers[x].overflowing_add(self.registers[y]);
+                        self.set_result_and_flag(x, result, overflow as u8);
+                    }
+                    0x0005 => {
+                        // 8XY5: Set VX = VX - VY, set VF = NOT borrow
+                        // println!("V{:X} -= V{:X}", x, y);
+                        let (result, underflow) =
+                            self.registers[x].overflowing_sub(self.registers[y]);
+                        self.set_result_and_flag(x, result, !underflow as u8);
+                    }
+                    0x0006 => {
+                        // 8XY6: Shift VX (or VY, by the quirk) right by 1 into VX,
+                        // VF = least significant bit before the shift
+                        let value = self.shift_source(x, y);
+                        self.set_result_and_flag(x, value >> 1, value & 0x1);
+                    }
+                    0x0007 => {
+                        // 8XY7: Set VX = VY - VX, set VF = NOT borrow
+                        let (result, underflow) =
+                            self.registers[y].overflowing_sub(self.registers[x]);
+                        self.set_result_and_flag(x, result, !underflow as u8);
+                    }
+                    0x000E => {
+                        // 8XYE: Shift VX (or VY, by the quirk) left by 1 into VX,
+                        // VF = most significant bit before the shift
+                        let value = self.shift_source(x, y);
+                        self.set_result_and_flag(x, value << 1, value >> 7);
+                    }
+
+                    _ => return Err(Fault::UnknownOpcode(opcode)),
+                }
+            }
+
+            0x9000 if n == 0 => {
+                // 9XY0 Skips next instruction of VX does NOT equal VY
+                if self.registers[x] != self.registers[y] {
+                    // println!("Skipping next instruction");
+                    self.skip_next();
+                } else {
+                    // println!("Continuing next instruction");
+                }
+            }
+
+            0xA000 => {
+                // ANNN: Set index register I to NNN
+                // println!("Set I = {:#05X}", nnn);
+                self.i = self.index_in_range(nnn as u32);
+            }
+            0xB000 if self.chip8x => {
+                // BXY0/BXYN: Chip-8X, set foreground colors
+                let color = self.registers[y] & 0x7;
+                let (vx, vx1) = (self.registers[x], self.registers[(x + 1) & 0xF]);
+                let zones = &mut self.chip8x_colors.zones;
+                if n == 0 {
+                    // 8x4 zones, clipped at the right and bottom edges
+                    let columns = (vx & 0xF) as usize..=((vx & 0xF) + (vx >> 4)) as usize;
+                    let blocks = (vx1 & 0xF) as usize..=((vx1 & 0xF) + (vx1 >> 4)) as usize;
+                    for block in blocks.filter(|&block| block < 8) {
+                        for row in &mut zones[block * 4..block * 4 + 4] {
+                            for column in columns.clone().filter(|&column| column < 8) {
+                                row[column] = color;
+                            }
+                        }
+                    }
+                } else {
+                    // N rows of one column, clipped at the bottom edge
+                    let column = (vx as usize % 64) / 8;
+                    let top = vx1 as usize % 32;
+                    for row in &mut zones[top..(top + n as usize).min(32)] {
+                        row[column] = color;
+                    }
+                }
+                self.display_dirty = true;
+            }
+            0xB000 => {
+                // BNNN: Jump to address NNN + V0
+                self.pc = (nnn + self.registers[0] as u16).wrapping_sub(2);
+            }
+            0xC000 => {
+                // CXNN: set VX to random byte AND NN
+                let random_byte: u8 = self.rng.gen_range(0..=255);
+                self.registers[x] = random_byte & nn;
+            }
+
+            0xD000 => {
+                // DXYN: Draw an 8xN sprite from I at (VX, VY), VF = 1 if any
+                // pixel was turned off (or how many rows did, by the quirk).
+                // The start position always wraps, pixels running off the
+                // edges wrap or clip by the quirks.
+                let (width, height) = (self.display_width(), self.display_height());
+                let start_x = self.registers[x] as usize % width;
+                let start_y = self.registers[y] as usize % height;
+
+                // Bit per sprite row, a row colliding on both planes counts once
+                let mut collided_rows: u16 = 0;
+
+                // With both planes selected, the second plane's sprite data
+                // follows the first's
+                let mut sprite_addr = self.i;
+
+                for plane in 0..2 {
+                    if self.selected_planes & (1 << plane) == 0 {
+                        continue;
+                    }
+
+                    for row in 0..n {
+                        let mut pixel_y = start_y + row as usize;
+                        let wrapped_y = pixel_y >= height;
+                        if wrapped_y {
+                            if !self.quirks.vertical_wrap {
+                                break;
+                            }
+                            pixel_y -= height;
+                        }
+                        // Sprite data running past the end of memory wraps
+                        // to the start, like every other read. It can be
+                        // the program's own code, see `read_byte`.
+                        let sprite_byte = self.read_byte(sprite_addr.wrapping_add(row as u16));
+
+                        for column in 0..8 {
+                            if sprite_byte & (0x80 >> column) == 0 {
+                                continue;
+                            }
+                            let mut pixel_x = start_x + column;
+                            let wrapped_x = pixel_x >= width;
+                            if wrapped_x {
+                                if !self.quirks.horizontal_wrap {
+                                    break;
+                                }
+                                pixel_x -= width;
+                            }
+
+                            let bit = 0x80 >> (pixel_x % 8);
+                            let wrapped = &mut self.wrapped[pixel_y * width / 8 + pixel_x / 8];
+                            if wrapped_x || wrapped_y {
+                                *wrapped |= bit;
+                            } else {
+                                *wrapped &= !bit;
+                            }
+
+                            let was_on = self.get_pixel(plane, pixel_x, pixel_y);
+                            if was_on {
+                                collided_rows |= 1 << row;
+                            }
+                            self.set_pixel(plane, pixel_x, pixel_y, !was_on);
+                        }
+                    }
+
+                    sprite_addr = sprite_addr.wrapping_add(n as u16);
+                }
+
+                self.registers[0xF] = if self.quirks.collision_row_count {
+                    collided_rows.count_ones() as u8
+                } else {
+                    (collided_rows != 0) as u8
+                };
+                if self.quirks.display_wait {
+                    self.waiting_for_vblank = true;
+                }
+                if let Some(mut snapshots) = self.draw_snapshots.take() {
+                    snapshots.push(self.snapshot());
+                    self.draw_snapshots = Some(snapshots);
+                }
+            }
+
+            0xE000 => {
+                match opcode & 0x00FF {
+                    0x009E => {
+                        // EX9E: Skip next instruction if key VX is pressed
+                        if self.key_pressed(self.registers[x]) {
+                            self.skip_next();
+                        }
+                    }
+                    0x00A1 => {
+                        // EXA1: Skip next instruction if key VX is NOT pressed
+                        if !self.key_pressed(self.registers[x]) {
+                            self.skip_next();
+                        }
+                    }
+                    _ => return Err(Fault::UnknownOpcode(opcode)),
+                }
+            }
+
+            0xF000 => {
+                match opcode & 0x00FF {
+                    0x00 if x == 0 => {
+                        // F000 NNNN: XO-CHIP, load I with the 16-bit address
+                        // in the next two bytes and step over them
+                        self.i =
+                            self.index_in_range(self.opcode_at(self.pc.wrapping_add(2)) as u32);
+                        self.pc = self.pc.wrapping_add(2);
+                    }
+                    0x01 => {
+                        // FN01: XO-CHIP, select the drawing planes (bitmask N)
+                        self.selected_planes = x as u8 & 0b11;
+                    }
+                    0x07 => {
+                        // FX07: Set VX to delay timer value
+                        self.registers[x] = self.delay_timer;
+                    }
+                    0x0A => {
+                        // FX0A: wait for key press
+                        self.waiting_for_key = true;
+                        self.key_register = x;
+                        self.keys_held_at_wait = std::array::from_fn(|key| self.key_down(key));
+                        // Stay on this instruction until a key comes in
+                        self.pc = self.pc.wrapping_sub(2);
+                    }
+                    0x15 => {
+                        // FX15: Set delay timer to VX
+                        self.delay_timer = self.registers[x];
+                    }
+                    0x18 => {
+                        // FX18: Set sound timer to VX
+                        self.set_sound_timer(self.registers[x]);
+                    }
+                    0x3A => {
+                        // FX3A: XO-CHIP, set the audio pitch to VX
+                        self.pitch = self.registers[x];
+                    }
+                    0x1E => {
+                        // FX1E: Add VX to I, past the end of memory by `index_mode`
+                        self.i = self.index_in_range(self.i as u32 + self.registers[x] as u32);
+                    }
+                    0x29 => {
+                        // FX29: Sets I to the location of the sprite for the character in VX
+                        self.i = self.index_in_range(
+                            self.font_address as u32 + self.registers[x] as u32 * 5,
+                        );
+                    }
+                    0x33 => {
+                        // FX33: Store decimal representation of VX with hundreds at I tens at I+1
+                        // and ones at I+2
+                        // (wrapping past the top of memory like every access through I)
+                        let hundreds = self.registers[x] / 100;
+                        let tens = (self.registers[x] % 100) / 10;
+                        let ones = (self.registers[x] % 100) % 10;
+                        self.write_byte(self.i, hundreds);
+                        self.write_byte(self.i.wrapping_add(1), tens);
+                        self.write_byte(self.i.wrapping_add(2), ones);
+                    }
+                    0x55 => {
+                        // FX55: Stores from V0 to VX in memory starting at address I
+                        for i in 0..=x {
+                            self.write_byte(self.i.wrapping_add(i as u16), self.registers[i]);
+                        }
+                        if self.load_store_increments_i {
+                            self.i = self.index_in_range(self.i as u32 + x as u32 + 1);
+                        }
+                    }
+                    0x65 => {
+                        // FX65: Fills from V0 to VX with values from memory starting at address I
+                        for i in 0..=x {
+                            self.registers[i] = self.read_byte(self.i.wrapping_add(i as u16));
+                        }
+                        if self.load_store_increments_i {
+                            self.i = self.index_in_range(self.i as u32 + x as u32 + 1);
+                        }
+                    }
+                    0x75 => {
+                        // FX75: SCHIP, store V0 to VX in the RPL flags
+                        self.rpl[..=x].copy_from_slice(&self.registers[..=x]);
+                    }
+                    0x85 => {
+                        // FX85: SCHIP, load V0 to VX from the RPL flags
+                        self.registers[..=x].copy_from_slice(&self.rpl[..=x]);
+                    }
+                    _ => return Err(Fault::UnknownOpcode(opcode)),
+                }
+            }
+
+            _ => return Err(Fault::UnknownOpcode(opcode)),
+        }
+
+        Ok(())
+    }
+
+    // Runs one instruction. Skipped unknown opcodes are still returned as an
+    // error so the frontend can see them, anything else also stops the machine.
+    pub fn cycle(&mut self) -> Result<(), Fault> {
+        if self.halted || self.fault.is_some() {
+            return Ok(());
+        }
+
+        // Spreads the delay timer's ticks over the cycles, FX0A waits
+        // included, so FX07 reads within one frame can see it change. Closer
+        // to a real timer on fast settings, but a ROM that reads it twice a
+        // frame expecting the same value (as on the VIP) can see them differ.
+        if self.smooth_delay_timer && !self.timers_frozen {
+            self.cycles_until_delay_tick = self.cycles_until_delay_tick.saturating_sub(1);
+            if self.cycles_until_delay_tick == 0 {
+                self.cycles_until_delay_tick = self.cycles_per_frame.max(1);
+                self.delay_timer = self.delay_timer.saturating_sub(1);
+            }
+        }
+
+        if self.waiting_for_vblank {
+            return Ok(());
+        }
+        if self.stall_cycles > 0 {
+            self.stall_cycles -= 1;
+            return Ok(());
+        }
+
+        // Only the CPU waits: both timers keep counting down at 60Hz in
+        // `update_timers` (the smooth delay timer above), as on the VIP,
+        // where they ran off an interrupt
+        if self.waiting_for_key {
+            for i in 0..16 {
+                if !self.key_down(i) {
+                    self.keys_held_at_wait[i] = false;
+                } else if !self.keys_held_at_wait[i] {
+                    self.finish_key_wait(i);
+                    break;
+                }
+            }
+            return Ok(());
+        }
+
+        // The opcode is still fetched from there, straddling two instructions
+        if self.warn_unaligned && !self.warned_unaligned && self.pc & 1 != 0 {
+            println!(
+                "Warning: PC is at odd address {:#05X}, probably a bad jump target",
+                self.pc
+            );
+            self.warned_unaligned = true;
+        }
+        // Still run, a few contrived ROMs do it on purpose
+        if self.warn_low_pc && !self.warned_low_pc && self.pc < INTERPRETER_AREA_END {
+            println!(
+                "Warning: PC is at {:#05X} in the font/interpreter area, the program may have jumped into data",
+                self.pc
+            );
+            self.warned_low_pc = true;
+        }
+
+        if let Some(limit) = self.max_cycles
+            && self.cycle_count >= limit
+        {
+            self.fault = Some(Fault::CycleLimit(limit));
+            return Err(Fault::CycleLimit(limit));
+        }
+
+        let opcode = self.fetch();
+        self.stall_cycles = self.instruction_cost(opcode) - 1;
+        self.execute_opcode(opcode)
+    }
+
+    // Makes the next instruction fail with `fault` instead of running, for
+    // testing how a frontend handles faults without a ROM that causes each
+    // one. It's handled like the real thing: an unknown opcode is skipped or
+    // stops the machine by `unknown_opcode_mode`, anything else stops it.
+    #[cfg(test)]
+    fn inject_fault(&mut self, fault: Fault) {
+        self.injected_fault = Some(fault);
+    }
+
+    #[cfg(test)]
+    fn take_injected_fault(&mut self) -> Option<Fault> {
+        self.injected_fault.take()
+    }
+
+    // Only tests can inject a fault
+    #[cfg(not(test))]
+    fn take_injected_fault(&mut self) -> Option<Fault> {
+        None
+    }
+
+    // For trying out new opcodes without changing the interpreter: `handler`
+    // gets every opcode it doesn't know, with the machine, and returns true
+    // if it ran it. Only what it turns down is skipped or faults by
+    // `unknown_opcode_mode`. Like any instruction the PC then moves on by 2,
+    // so a handled jump sets the PC 2 short of its target. Replaces any
+    // handler set before.
+    pub fn set_opcode_handler(
+        &mut self,
+        handler: impl FnMut(u16, &mut Chip8) -> bool + Send + 'static,
+    ) {
+        self.opcode_handler = Some(Box::new(handler));
+    }
+
+    pub fn clear_opcode_handler(&mut self) {
+        self.opcode_handler = None;
+    }
+
+    // The second half of `cycle`: runs `opcode` as if it had been fetched
+    // from the PC, then moves the PC on the way `cycle` does. Jumps, calls
+    // and skips end up at their real targets. For tests that set up the
+    // registers and memory and check what a single instruction does, without
+    // building a ROM around it.
+    pub fn execute_opcode(&mut self, opcode: u16) -> Result<(), Fault> {
+        self.cycle_count += 1;
+        if let Some(profile) = &mut self.profile {
+            profile.record(opcode);
+        }
+
+        let result = if let Some(fault) = self.take_injected_fault() {
+            Err(fault)
+        } else if self.robust {
+            // The panic hook still prints the message, the machine just stops
+            // instead of taking the host down with it
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| self.execute(opcode)))
+                .unwrap_or_else(|panic| {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|message| message.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "unknown panic".to_string());
+                    Err(Fault::Internal(message))
+                })
+        } else {
+            self.execute(opcode)
+        };
+        let result = match result {
+            Err(Fault::UnknownOpcode(unknown)) => self.run_opcode_handler(unknown),
+            result => result,
+        };
+
+        match result {
+            Ok(()) => {}
+            Err(Fault::UnknownOpcode(opcode))
+                if self.unknown_opcode_mode == UnknownOpcodeMode::Skip =>
+            {
+                println!(
+                    "Skipping unknown opcode {:#06X} at {:#05X}",
+                    opcode, self.pc
+                );
+                self.unknown_opcodes += 1;
+                if self.unknown_opcodes >= MAX_UNKNOWN_OPCODES {
+                    self.fault = Some(Fault::UnknownOpcodes(opcode));
+                    return Err(Fault::UnknownOpcodes(opcode));
+                }
+                self.pc = self.pc.wrapping_add(2);
+                return Err(Fault::UnknownOpcode(opcode));
+            }
+            Err(fault) => {
+                // Leave the PC on the instruction that stopped the machine
+                self.fault = Some(fault.clone());
+                return Err(fault);
+            }
+        }
+        if self.halted {
+            return Ok(());
+        }
+
+        // each instruction is 2 bytes
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    fn run_opcode_handler(&mut self, opcode: u16) -> Result<(), Fault> {
+        let Some(mut handler) = self.opcode_handler.take() else {
+            return Err(Fault::UnknownOpcode(opcode));
+        };
+        let handled = handler(opcode, self);
+        // Unless the handler put a new one in place
+        self.opcode_handler.get_or_insert(handler);
+        if handled {
+            Ok(())
+        } else {
+            Err(Fault::UnknownOpcode(opcode))
+        }
+    }
+
+    pub fn print_state(&self) {
+        println!("\n--- CPU State ---");
+        println!("PC: {:#05X}", self.pc);
+        println!("I: {:#05X}", self.i);
+        println!("Cycle: {}", self.cycle_count);
+        print!("Registers: ");
+        for (i, &val) in self.registers.iter().enumerate() {
+            print!("V{:X}={:#04X} ", i, val);
+            if i == 7 {
+                print!("\n           ");
+            }
+        }
+        println!("\n");
+    }
+
+    // One 60Hz frame: `cycles_per_frame` instructions, then exactly one timer
+    // tick. Nothing here looks at the wall clock, so the same number of
+    // frames always ends in the same state. Stops early on a fault or halt,
+    // or a DXYN waiting for the frame to end. Skipped opcodes are only logged.
+    pub fn run_frame(&mut self) {
+        for _ in 0..self.cycles_per_frame {
+            let _ = self.cycle();
+            if self.fault.is_some() || self.halted || self.waiting_for_vblank {
+                break;
+            }
+        }
+        self.update_timers();
+    }
+
+    pub fn run_frames(&mut self, frames: usize) {
+        for _ in 0..frames {
+            self.run_frame();
+        }
+    }
+
+    // The timer ticks `clock` says are owed, see `update_timers`. `clock`
+    // runs at 60Hz, faster or slower to change the speed. Returns how many
+    // ran.
+    pub fn update_timers_from<T: TimeSource>(&mut self, clock: &mut Clock<T>) -> u32 {
+        let ticks = clock.ticks_elapsed();
+        for _ in 0..ticks {
+            self.update_timers();
+        }
+        ticks
+    }
+
+    // One 60Hz tick of both timers. There are two ways to drive it: once per
+    // `run_frame` for reproducible runs (headless, tests), or from a real
+    // 60Hz `Clock` as the frontends do (see `update_timers_from`), where a
+    // slow host frame can owe more than one tick.
+    pub fn update_timers(&mut self) {
+        // A frame has gone by, taps from it have had their chance
+        self.latched_keys = [false; 16];
+        self.frame_displays = [self.display, self.frame_displays[0]];
+        self.waiting_for_vblank = false;
+        if let Some(histogram) = &mut self.frame_histogram {
+            histogram.record(self.cycle_count.saturating_sub(self.frame_start_cycle));
+        }
+        self.frame_start_cycle = self.cycle_count;
+        if self.timers_frozen {
+            return;
+        }
+        // Otherwise `cycle` ticks it
+        if self.delay_timer > 0 && !self.smooth_delay_timer {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcodes;
+    use crate::testutil::ProgramBuilder;
+
+    fn machine(chip8x: bool) -> Chip8 {
+        Chip8::with_config(Chip8Config {
+            seed: Some(0),
+            chip8x,
+            ..Chip8Config::default()
+        })
+    }
+
+    #[test]
+    fn opcode_table_matches_execute() {
+        for chip8x in [false, true] {
+            for opcode in 0x0000..=0xFFFF {
+                let known = !matches!(
+                    machine(chip8x).execute(opcode),
+                    Err(Fault::UnknownOpcode(_))
+                );
+                assert_eq!(
+                    known,
+                    opcodes::find(opcode, chip8x).is_some(),
+                    "opcodes::OPCODES disagrees with execute about {:#06X} (chip8x: {})",
+                    opcode,
+                    chip8x
+                );
+            }
+        }
+    }
+
+    // FX55 with V0-V2 from the start of the program, then where I ends up
+    fn index_after_store(quirks: Quirks) -> u16 {
+        let mut chip8 = machine(false);
+        chip8.set_quirks(quirks);
+        chip8.set_index(0x300);
+        chip8.execute_opcode(0xF255).unwrap();
+        chip8.index()
+    }
+
+    #[test]
+    fn load_store_increment_follows_index_mode() {
+        // F255 and F265 from 0xFFE leave I past the end of 4KB
+        for (index_mode, expected) in [
+            (IndexMode::Full, 0x1001),
+            (IndexMode::Wrap, 0x001),
+            (IndexMode::Clamp, 0xFFF),
+        ] {
+            let mut chip8 = Chip8::with_config(Chip8Config {
+                seed: Some(0),
+                index_mode,
+                ..Chip8Config::default()
+            });
+            chip8.set_quirks(Quirks::preset("chip8").unwrap());
+            for opcode in [0xF255, 0xF265] {
+                chip8.set_index(0xFFE);
+                chip8.execute_opcode(opcode).unwrap();
+                assert_eq!(chip8.index(), expected, "{:?} {:#06X}", index_mode, opcode);
+            }
+        }
+    }
+
+    #[test]
+    fn toggled_increment_follows_index_mode() {
+        // 00FA turns the chip8 preset's increment off, then on again, where
+        // it wraps like any other change to I
+        let mut chip8 = Chip8::with_config(Chip8Config {
+            seed: Some(0),
+            index_mode: IndexMode::Wrap,
+            ..Chip8Config::default()
+        });
+        chip8.set_quirks(Quirks::preset("chip8").unwrap());
+        let store_from = |chip8: &mut Chip8, index| {
+            chip8.set_index(index);
+            chip8.execute_opcode(0xF255).unwrap();
+            chip8.index()
+        };
+        chip8.execute_opcode(0x00FA).unwrap();
+        assert_eq!(store_from(&mut chip8, 0xFFE), 0xFFE);
+        chip8.execute_opcode(0x00FA).unwrap();
+        assert_eq!(store_from(&mut chip8, 0xFFE), 0x001);
+
+        // Setting the quirks again undoes a toggle
+        chip8.execute_opcode(0x00FA).unwrap();
+        chip8.set_quirks(Quirks::preset("chip8").unwrap());
+        assert_eq!(store_from(&mut chip8, 0xFFE), 0x001);
+    }
+
+    #[test]
+    fn chip8_preset_is_the_cosmac_vip() {
+        let quirks = Quirks::preset("chip8").unwrap();
+        assert!(quirks.shift_uses_vy);
+        assert!(quirks.vf_reset);
+        assert!(quirks.display_wait);
+        assert!(quirks.load_store_increments_i);
+        assert!(!quirks.horizontal_wrap && !quirks.vertical_wrap);
+        assert_eq!(index_after_store(quirks), 0x303);
+    }
+
+    #[test]
+    fn schip_preset_clips_and_leaves_i() {
+        let quirks = Quirks::preset("schip").unwrap();
+        assert!(!quirks.shift_uses_vy);
+        assert!(!quirks.vf_reset);
+        assert!(!quirks.display_wait);
+        assert!(quirks.half_lores_scroll);
+        assert!(!quirks.horizontal_wrap && !quirks.vertical_wrap);
+        assert_eq!(index_after_store(quirks), 0x300);
+    }
+
+    #[test]
+    fn xochip_preset_wraps_and_increments_i() {
+        let quirks = Quirks::preset("xochip").unwrap();
+        assert!(quirks.shift_uses_vy);
+        assert!(!quirks.vf_reset);
+        assert!(!quirks.display_wait);
+        assert!(quirks.load_store_increments_i);
+        assert!(quirks.horizontal_wrap && quirks.vertical_wrap);
+        assert_eq!(index_after_store(quirks), 0x303);
+    }
+
+    // The registers after `opcode` runs with `quirks` from `registers`
+    fn registers_after(quirks: Quirks, registers: &[(usize, u8)], opcode: u16) -> [u8; 16] {
+        let mut chip8 = machine(false);
+        chip8.set_quirks(quirks);
+        for &(register, value) in registers {
+            chip8.set_register(register, value);
+        }
+        chip8.execute_opcode(opcode).unwrap();
+        *chip8.registers()
+    }
+
+    #[test]
+    fn shift_matrix() {
+        let registers = [(2, 0x02), (3, 0x81), (0xF, 0x81)];
+        // (shift_uses_vy, opcode, VX after, VF after), from V2 = 02, V3 = 81
+        // and VF = 81
+        let cases = [
+            // X = Y, the same whichever register is the source
+            (true, 0x8336, 0x40, 1),
+            (false, 0x8336, 0x40, 1),
+            (true, 0x833E, 0x02, 1),
+            (false, 0x833E, 0x02, 1),
+            // X = F, the flag overwrites the result
+            (true, 0x8F26, 0, 0),
+            (false, 0x8F26, 1, 1),
+            (true, 0x8F2E, 0, 0),
+            (false, 0x8F2E, 1, 1),
+            // Y = F, VF is read as the source before the flag replaces it
+            (true, 0x82F6, 0x40, 1),
+            (false, 0x82F6, 0x01, 0),
+            (true, 0x82FE, 0x02, 1),
+            (false, 0x82FE, 0x04, 0),
+        ];
+        for (shift_uses_vy, opcode, vx, vf) in cases {
+            let quirks = Quirks {
+                shift_uses_vy,
+                ..Quirks::default()
+            };
+            let after = registers_after(quirks, &registers, opcode);
+            let x = (opcode as usize >> 8) & 0xF;
+            assert_eq!(
+                (after[x], after[0xF]),
+                (vx, vf),
+                "{:04X} with shift_uses_vy {}",
+                opcode,
+                shift_uses_vy
+            );
+        }
+    }
+
+    #[test]
+    fn arithmetic_into_vf() {
+        let registers = [(2, 0x20), (0xF, 0xF0)];
+        // (opcode, VF after with the flag last, VF after with it first), from
+        // V2 = 20 and VF = F0
+        let cases = [
+            (0x8F24, 1, 0x10),
+            (0x8F25, 1, 0xD0),
+            (0x8F26, 0, 0x78),
+            (0x8F27, 0, 0x30),
+            (0x8F2E, 1, 0xE0),
+        ];
+        for (opcode, flag_last, flag_first) in cases {
+            for (vf_written_first, vf) in [(false, flag_last), (true, flag_first)] {
+                let quirks = Quirks {
+                    vf_written_first,
+                    ..Quirks::default()
+                };
+                assert_eq!(
+                    registers_after(quirks, &registers, opcode)[0xF],
+                    vf,
+                    "{:04X} with vf_written_first {}",
+                    opcode,
+                    vf_written_first
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn logic_ops_reset_vf() {
+        let registers = [(1, 0x0C), (2, 0x0A), (0xF, 0x55)];
+        for opcode in [0x8121, 0x8122, 0x8123] {
+            for (vf_reset, vf) in [(true, 0), (false, 0x55)] {
+                let quirks = Quirks {
+                    vf_reset,
+                    ..Quirks::default()
+                };
+                assert_eq!(
+                    registers_after(quirks, &registers, opcode)[0xF],
+                    vf,
+                    "{:04X} with vf_reset {}",
+                    opcode,
+                    vf_reset
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sprite_drawn_from_code() {
+        let mut chip8 = Chip8::with_config(Chip8Config {
+            seed: Some(0),
+            unknown_opcode_mode: UnknownOpcodeMode::Halt,
+            warn_self_modifying: true,
+            warn_unaligned: true,
+            warn_low_pc: true,
+            ..Chip8Config::default()
+        });
+        // I = 200, draw its 8 bytes at 0,0, then loop
+        let program = ProgramBuilder::new().ops(&[0xA200, 0xD018, 0x1204]).build();
+        chip8.load_program(&program).unwrap();
+        for addr in 0x200..0x210 {
+            chip8.add_watchpoint(addr);
+        }
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert!(chip8.fault().is_none() && !chip8.is_halted());
+        assert!(chip8.take_watch_hit().is_none());
+        assert!(!chip8.warned_unaligned() && !chip8.warned_low_pc());
+        let grid = chip8.display_grid();
+        for (row, &byte) in chip8.memory_slice(0x200, 8).iter().enumerate() {
+            let lit: Vec<bool> = (0..8).map(|column| byte & (0x80 >> column) != 0).collect();
+            assert_eq!(grid[row][..8], lit[..], "row {}", row);
+        }
+    }
+
+    #[test]
+    fn display_rle_round_trip() {
+        let mut chip8 = machine(false);
+        let program = ProgramBuilder::new().ops(&[0xA050, 0xD015]).build();
+        chip8.load_program(&program).unwrap();
+        chip8.run_frame();
+        let rle = chip8.export_display_rle();
+        let mut copy = machine(false);
+        copy.import_display_rle(&rle).unwrap();
+        assert_eq!(copy.display_grid(), chip8.display_grid());
+
+        // Half a screen of RLE is refused and changes nothing
+        let error = copy.import_display_rle(&[255, 0, 255, 0, 255, 0, 255, 0]);
+        assert!(matches!(error, Err(Chip8Error::BadSnapshot(_))));
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "bad snapshot: RLE data covers 1020 pixels, the screen has 2048"
+        );
+        assert_eq!(copy.display_grid(), chip8.display_grid());
+    }
+
+    #[test]
+    fn checkerboard_picture() {
+        let pixels: Vec<bool> = (0..64 * 32)
+            .map(|pixel| (pixel % 64 + pixel / 64) % 2 == 0)
+            .collect();
+        let mut chip8 = machine(false);
+        chip8.import_display_pixels(&pixels, 64, 32).unwrap();
+        let grid: Vec<bool> = chip8.display_grid().concat();
+        assert_eq!(grid, pixels);
+
+        let error = chip8.import_display_pixels(&pixels[..32 * 32], 32, 32);
+        assert!(matches!(error, Err(Chip8Error::BadSnapshot(_))));
+        assert_eq!(chip8.display_grid().concat(), pixels);
+    }
+
+    #[test]
+    fn chip8x_color_zones() {
+        let mut chip8 = machine(true);
+        // A 0 at the top left, then blue over the zone at column 0, rows 0-3
+        chip8.set_register(3, 2);
+        for opcode in [0xA050, 0xD015, 0xB130] {
+            chip8.execute_opcode(opcode).unwrap();
+        }
+        let buffer = chip8.get_display_buffer();
+        assert_eq!(buffer[0], CHIP8X_COLORS[2]);
+        // Lit below the zone, still the starting red
+        assert_eq!(buffer[4 * 64], CHIP8X_COLORS[1]);
+        // Unlit, the background
+        assert_eq!(buffer[8], CHIP8X_BACKGROUNDS[0]);
+    }
+
+    #[test]
+    fn restore_refuses_chip8x_colors_out_of_range() {
+        let mut chip8 = machine(true);
+        let mut snapshot = chip8.snapshot();
+        snapshot.chip8x_colors.background = CHIP8X_BACKGROUNDS.len() as u8;
+        assert!(matches!(
+            chip8.restore(&snapshot),
+            Err(Chip8Error::BadSnapshot(_))
+        ));
+
+        let mut snapshot = chip8.snapshot();
+        snapshot.chip8x_colors.zones[31][7] = CHIP8X_COLORS.len() as u8;
+        assert!(matches!(
+            chip8.restore(&snapshot),
+            Err(Chip8Error::BadSnapshot(_))
+        ));
+        assert_eq!(chip8.snapshot().chip8x_colors, Chip8xColors::default());
+    }
+
+    #[test]
+    fn zeroed_memory_halts_at_its_address() {
+        // A jump past the end of the program into memory that's never set
+        let mut chip8 = machine(false);
+        chip8
+            .load_program(&ProgramBuilder::new().op(0x1210).build())
+            .unwrap();
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert!(chip8.is_halted());
+        assert!(chip8.fault().is_none());
+        assert_eq!(chip8.pc(), 0x210);
+
+        // Stays there rather than running on through the zeros
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.pc(), 0x210);
+    }
+
+    #[test]
+    fn injected_stack_overflow_stops_the_machine() {
+        let mut chip8 = machine(false);
+        // 6005 would set V0 if it ran
+        chip8
+            .load_program(&ProgramBuilder::new().op(0x6005).build())
+            .unwrap();
+        chip8.inject_fault(Fault::StackOverflow);
+        assert_eq!(chip8.cycle(), Err(Fault::StackOverflow));
+        assert_eq!(chip8.fault(), Some(&Fault::StackOverflow));
+        assert_eq!((chip8.pc(), chip8.registers()[0]), (0x200, 0));
+        // Stopped, nothing more runs
+        assert_eq!(chip8.cycle(), Ok(()));
+        assert_eq!((chip8.pc(), chip8.registers()[0]), (0x200, 0));
+    }
+
+    #[test]
+    fn hires_mode_switch() {
+        let mut chip8 = machine(false);
+        assert_eq!(chip8.display_dimensions(), (64, 32));
+        chip8.execute_opcode(0x00FF).unwrap();
+        assert_eq!(chip8.display_dimensions(), (128, 64));
+        assert_eq!(chip8.get_display_buffer().len(), 128 * 64);
+
+        // The font's 0 at 120, 60, then back to lores clears it
+        chip8.set_register(0, 120);
+        chip8.set_register(1, 60);
+        chip8.set_index(0x050);
+        chip8.execute_opcode(0xD015).unwrap();
+        assert!(chip8.display_grid()[60][120]);
+        chip8.execute_opcode(0x00FE).unwrap();
+        assert_eq!(chip8.display_dimensions(), (64, 32));
+        assert!(chip8.display_grid().concat().iter().all(|&lit| !lit));
+    }
+
+    #[test]
+    fn hires_snapshot_round_trip() {
+        let mut chip8 = machine(false);
+        chip8.execute_opcode(0x00FF).unwrap();
+        chip8.set_register(0, 100);
+        chip8.set_register(1, 50);
+        chip8.set_index(0x050);
+        chip8.execute_opcode(0xD015).unwrap();
+        let snapshot = Snapshot::from_json(&chip8.snapshot().to_json()).unwrap();
+
+        let mut copy = machine(false);
+        copy.restore(&snapshot).unwrap();
+        assert_eq!(copy.display_dimensions(), (128, 64));
+        assert_eq!(copy.display_grid(), chip8.display_grid());
+        assert_eq!(copy.framebuffer_hash(), chip8.framebuffer_hash());
+    }
+
+    #[test]
+    fn hires_scrolls_full_pixels() {
+        let mut chip8 = machine(false);
+        chip8.set_quirks(Quirks {
+            half_lores_scroll: true,
+            ..Quirks::default()
+        });
+        chip8.execute_opcode(0x00FF).unwrap();
+        // The top left pixel of the font's 0, then right 4 and down 2
+        chip8.set_index(0x050);
+        chip8.execute_opcode(0xD011).unwrap();
+        chip8.execute_opcode(0x00FB).unwrap();
+        chip8.execute_opcode(0x00C2).unwrap();
+        let grid = chip8.display_grid();
+        assert!(grid[2][4] && !grid[0][0]);
+    }
+
+    #[test]
+    fn lores_scrolls_by_half_with_the_quirk() {
+        for (half_lores_scroll, step) in [(false, 4), (true, 2)] {
+            let mut chip8 = machine(false);
+            chip8.set_quirks(Quirks {
+                half_lores_scroll,
+                ..Quirks::default()
+            });
+            // One pixel at 10, 10 down 4 rows, right 4 then left 4 pixels
+            // on the full grid, or half that with the quirk
+            chip8.set_pixel(0, 10, 10, true);
+            chip8.execute_opcode(0x00C4).unwrap();
+            assert!(chip8.get_pixel(0, 10, 10 + step));
+            chip8.execute_opcode(0x00FB).unwrap();
+            assert!(chip8.get_pixel(0, 10 + step, 10 + step));
+            chip8.execute_opcode(0x00FC).unwrap();
+            assert!(chip8.get_pixel(0, 10, 10 + step));
+            let lit = chip8
+                .display_grid()
+                .concat()
+                .iter()
+                .filter(|&&on| on)
+                .count();
+            assert_eq!(lit, 1, "half_lores_scroll: {}", half_lores_scroll);
+        }
+    }
+
+    #[test]
+    fn hires_draw_start_wraps() {
+        let mut chip8 = machine(false);
+        chip8.execute_opcode(0x00FF).unwrap();
+        // The top row of the font's 0, 1111 0000, at 130, 70
+        chip8.set_register(0, 130);
+        chip8.set_register(1, 70);
+        chip8.set_index(0x050);
+        chip8.execute_opcode(0xD011).unwrap();
+        let grid = chip8.display_grid();
+        assert_eq!(
+            grid[6][..8],
+            [false, false, true, true, true, true, false, false]
+        );
+        assert_eq!(grid.concat().iter().filter(|&&lit| lit).count(), 4);
+    }
+
+    #[test]
+    fn timers_follow_the_clock() {
+        use crate::clock::ManualTime;
+        use std::time::Duration;
+
+        let mut chip8 = machine(false);
+        chip8.set_delay_timer(100);
+        chip8.set_sound_timer(100);
+        let time = ManualTime::new();
+        let mut clock = Clock::with_time_source(60.0, time.clone());
+        clock.set_catch_up_limit(None);
+        time.advance(Duration::from_secs(1));
+        assert_eq!(chip8.update_timers_from(&mut clock), 60);
+        assert_eq!((chip8.delay_timer(), chip8.sound_timer()), (40, 40));
+
+        // Nothing owed until time moves again
+        assert_eq!(chip8.update_timers_from(&mut clock), 0);
+        assert_eq!(chip8.delay_timer(), 40);
+    }
+
+    #[test]
+    fn beep_stops_on_exit_and_reset() {
+        // FX18 with 30 starts a beep, then 00FD exits mid-beep
+        let mut chip8 = machine(false);
+        let program = ProgramBuilder::new().ops(&[0x601E, 0xF018, 0x00FD]).build();
+        chip8.load_program(&program).unwrap();
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert!(chip8.is_beeping());
+        chip8.cycle().unwrap();
+        assert!(chip8.is_halted());
+        assert!(!chip8.is_beeping());
+
+        chip8.reset();
+        chip8.set_sound_timer(30);
+        assert!(chip8.is_beeping());
+        chip8.reset();
+        assert!(!chip8.is_beeping());
+    }
+
+    #[test]
+    fn display_bytes_are_the_packed_rows() {
+        // 0xA5 from after the code drawn at (8, 1) on plane 0
+        let mut chip8 = machine(false);
+        let program = ProgramBuilder::new()
+            .ops(&[0xA204, 0xD011])
+            .data(&[0xA5])
+            .build();
+        chip8.load_program(&program).unwrap();
+        chip8.set_register(0, 8);
+        chip8.set_register(1, 1);
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+
+        let bytes = chip8.display_bytes(0);
+        assert_eq!(bytes.len(), 64 * 32 / 8);
+        let mut expected = [0; 64 * 32 / 8];
+        expected[8 + 1] = 0xA5;
+        assert_eq!(bytes, expected);
+        assert!(chip8.display_bytes(1).iter().all(|&byte| byte == 0));
+
+        chip8.execute_opcode(0x00FF).unwrap();
+        assert_eq!(chip8.display_bytes(0).len(), 128 * 64 / 8);
+    }
+}
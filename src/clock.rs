@@ -0,0 +1,144 @@
+// Pacing for frontends that run in real time. `Clock` turns a rate (CPU
+// speed, 60Hz timer ticks) into whole ticks to run, reading the time from a
+// `TimeSource` so tests and embedders can move time by hand instead of
+// sleeping.
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+pub trait TimeSource {
+    // Time since some fixed starting point
+    fn now(&self) -> Duration;
+}
+
+// The wall clock
+pub struct RealTime {
+    start: Instant,
+}
+
+impl RealTime {
+    pub fn new() -> Self {
+        RealTime {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for RealTime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeSource for RealTime {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+// Time that only moves when told to. Clones share the same time, so keep
+// one to call `advance` on and hand another to the clock.
+#[derive(Clone, Default)]
+pub struct ManualTime {
+    now: Rc<Cell<Duration>>,
+}
+
+impl ManualTime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+}
+
+impl TimeSource for ManualTime {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+}
+
+// Long gaps (a debugger prompt, a dragged window) aren't made up for, at
+// most this much counts, see `Clock::set_catch_up_limit`
+const DEFAULT_CATCH_UP_LIMIT: Duration = Duration::from_millis(100);
+
+// Carries the fraction of a tick over so the average rate comes out right
+pub struct Clock<T: TimeSource = RealTime> {
+    rate: f64,
+    pending: f64,
+    time: T,
+    last: Duration,
+    catch_up_limit: Option<Duration>,
+}
+
+impl Clock {
+    pub fn new(rate: f64) -> Self {
+        Self::with_time_source(rate, RealTime::new())
+    }
+}
+
+impl<T: TimeSource> Clock<T> {
+    pub fn with_time_source(rate: f64, time: T) -> Self {
+        let last = time.now();
+        Clock {
+            rate,
+            pending: 0.0,
+            time,
+            last,
+            catch_up_limit: Some(DEFAULT_CATCH_UP_LIMIT),
+        }
+    }
+
+    // For a new speed from the next tick on
+    pub fn set_rate(&mut self, rate: f64) {
+        self.rate = rate;
+    }
+
+    // The most time one `ticks_elapsed` counts, 0.1s unless set. None makes
+    // up for any gap, for time moved by hand in big steps.
+    pub fn set_catch_up_limit(&mut self, limit: Option<Duration>) {
+        self.catch_up_limit = limit;
+    }
+
+    // Ticks owed for the time since the last call
+    pub fn ticks_elapsed(&mut self) -> u32 {
+        let now = self.time.now();
+        let mut elapsed = now - self.last;
+        if let Some(limit) = self.catch_up_limit {
+            elapsed = elapsed.min(limit);
+        }
+        self.last = now;
+        self.take(self.rate * elapsed.as_secs_f64())
+    }
+
+    // Ticks owed for one 60Hz frame, so headless runs are reproducible. At
+    // normal speed that's one timer tick per frame, whatever the time.
+    pub fn ticks_per_frame(&mut self) -> u32 {
+        self.take(self.rate / 60.0)
+    }
+
+    fn take(&mut self, ticks: f64) -> u32 {
+        self.pending += ticks;
+        let whole = self.pending.floor();
+        self.pending -= whole;
+        whole as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catch_up_limit() {
+        let time = ManualTime::new();
+        let mut clock = Clock::with_time_source(60.0, time.clone());
+        time.advance(Duration::from_secs(1));
+        assert_eq!(clock.ticks_elapsed(), 6);
+
+        clock.set_catch_up_limit(None);
+        time.advance(Duration::from_secs(1));
+        assert_eq!(clock.ticks_elapsed(), 60);
+    }
+}
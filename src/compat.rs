@@ -0,0 +1,66 @@
+// Per-game quirk settings looked up by ROM hash, for ROM collections that
+// ship a small compatibility database (--compat-db). The file is a JSON
+// object keyed by `rom_hash` in hex:
+//
+//     {
+//         "8d5b2a...": { "name": "Blinky", "preset": "schip" }
+//     }
+//
+// with the preset being one of the names `Quirks::preset` knows.
+use crate::chip8::Quirks;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct CompatEntry {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub preset: String,
+}
+
+pub struct CompatDatabase {
+    entries: HashMap<u64, CompatEntry>,
+}
+
+impl CompatDatabase {
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let raw: HashMap<String, CompatEntry> = serde_json::from_str(json)
+            .map_err(|e| format!("invalid compatibility database: {}", e))?;
+
+        let mut entries = HashMap::new();
+        for (hash, entry) in raw {
+            let hex = hash.strip_prefix("0x").unwrap_or(&hash);
+            let hash = u64::from_str_radix(hex, 16)
+                .map_err(|_| format!("invalid ROM hash '{}' in compatibility database", hash))?;
+            if Quirks::preset(&entry.preset).is_none() {
+                return Err(format!(
+                    "unknown quirks preset '{}' in compatibility database",
+                    entry.preset
+                ));
+            }
+            entries.insert(hash, entry);
+        }
+        Ok(CompatDatabase { entries })
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<&CompatEntry> {
+        self.entries.get(&rom_hash(rom))
+    }
+
+    // The quirks a ROM needs, the default ones if it isn't listed
+    pub fn quirks_for(&self, rom: &[u8]) -> Quirks {
+        self.lookup(rom)
+            .and_then(|entry| Quirks::preset(&entry.preset))
+            .unwrap_or_default()
+    }
+}
+
+// FNV-1a over the ROM file, the same hash as `Chip8::framebuffer_hash`
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    for &byte in rom {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01B3);
+    }
+    hash
+}
@@ -0,0 +1,93 @@
+// --threaded: runs the CPU and timers on a thread of their own instead of
+// between frames in the window loop, so at very high speeds a slow frame
+// doesn't hold up emulation and a long batch of instructions doesn't hold up
+// the display. The window thread still owns the machine between its frames:
+// it locks it once a frame to pass in the keys, take the display and beep
+// state and handle hotkeys, and the CPU thread locks it for each short batch
+// of instructions in between.
+use chip8::Chip8;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// How long the CPU thread sleeps between batches, which is also about how
+// often the window thread gets a turn with the machine
+const BATCH_INTERVAL: Duration = Duration::from_millis(1);
+
+// What the window thread and CPU thread tell each other outside the lock
+#[derive(Default)]
+pub struct Control {
+    stop: AtomicBool,
+    paused: AtomicBool,
+    // Counted since the window thread last took them
+    executed: AtomicU64,
+    timer_ticks: AtomicU32,
+    // Instructions per second, changed when another ROM's settings apply
+    hz: AtomicU32,
+}
+
+impl Control {
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn set_hz(&self, hz: u32) {
+        self.hz.store(hz, Ordering::Relaxed);
+    }
+
+    // Instructions run and timer ticks since the last call, for the IPS
+    // overlay and the watchdog
+    pub fn take_counts(&self) -> (u64, u32) {
+        (
+            self.executed.swap(0, Ordering::Relaxed),
+            self.timer_ticks.swap(0, Ordering::Relaxed),
+        )
+    }
+}
+
+// Stops the CPU thread when dropped, so every way out of the window loop
+// lets the thread scope finish
+pub struct StopOnDrop<'a>(pub &'a Control);
+
+impl Drop for StopOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+pub fn run(chip8: &Mutex<&mut Chip8>, config: &crate::Config, control: &Control) {
+    let mut hz = control.hz.load(Ordering::Relaxed);
+    let (mut clock, mut timer_clock) = crate::clocks_for(config, hz);
+    while !control.stop.load(Ordering::Relaxed) {
+        if control.hz.load(Ordering::Relaxed) != hz {
+            hz = control.hz.load(Ordering::Relaxed);
+            clock.set_rate(hz as f64 * config.speed);
+        }
+        // Taken even while paused so time spent paused isn't made up afterwards
+        let cycles = clock.ticks_elapsed();
+        let timer_ticks = timer_clock.ticks_elapsed();
+
+        if !control.paused.load(Ordering::Relaxed) {
+            let mut chip8 = chip8.lock().unwrap();
+            // A fault stays for the window thread to report
+            if chip8.fault().is_none() {
+                // No debugger with --threaded, so this can't be a quit
+                let executed =
+                    crate::run_cycles(&mut chip8, cycles, config.skip_idle, &mut None, &mut None)
+                        .unwrap_or(0);
+                for _ in 0..timer_ticks {
+                    chip8.update_timers();
+                }
+                control
+                    .executed
+                    .fetch_add(executed as u64, Ordering::Relaxed);
+                control
+                    .timer_ticks
+                    .fetch_add(timer_ticks, Ordering::Relaxed);
+            }
+        }
+
+        thread::sleep(BATCH_INTERVAL);
+    }
+}
@@ -0,0 +1,745 @@
+// Terminal debugger. The run loop asks it before every instruction whether
+// to stop, and the prompt reads commands from stdin until told to go on.
+use chip8::{Chip8, Chip8Error, Fault, Snapshot, disasm};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn parse(text: &str) -> Option<Self> {
+        match text {
+            "==" => Some(Comparison::Equal),
+            "!=" => Some(Comparison::NotEqual),
+            "<" => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessOrEqual),
+            ">" => Some(Comparison::Greater),
+            ">=" => Some(Comparison::GreaterOrEqual),
+            _ => None,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+
+    fn test(self, left: u8, right: u8) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+// Breaks when a register comparison becomes true, e.g. `VF != 0`
+struct Condition {
+    register: usize,
+    comparison: Comparison,
+    value: u8,
+    // Only the instruction that makes it true stops, not every one after
+    was_true: bool,
+}
+
+impl Condition {
+    // `V5 == 0xFF`, with or without spaces around the operator
+    fn parse(text: &str) -> Option<Self> {
+        let text: String = text.split_whitespace().collect();
+        let split = text.find(['=', '!', '<', '>'])?;
+        let (register, rest) = text.split_at(split);
+        let operator_len = rest.find(|c: char| !"=!<>".contains(c))?;
+        let (operator, value) = rest.split_at(operator_len);
+
+        let register = register
+            .strip_prefix(['V', 'v'])
+            .filter(|r| r.len() == 1)
+            .and_then(|r| usize::from_str_radix(r, 16).ok())?;
+        let value = crate::parse_address(value).and_then(|v| u8::try_from(v).ok())?;
+
+        Some(Condition {
+            register,
+            comparison: Comparison::parse(operator)?,
+            value,
+            was_true: false,
+        })
+    }
+
+    // True only on the instruction that made the condition hold
+    fn triggered(&mut self, chip8: &Chip8) -> bool {
+        let now_true = self
+            .comparison
+            .test(chip8.registers()[self.register], self.value);
+        let triggered = now_true && !self.was_true;
+        self.was_true = now_true;
+        triggered
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "V{:X} {} {:#04X}",
+            self.register,
+            self.comparison.symbol(),
+            self.value
+        )
+    }
+}
+
+// What the run loop should do after the prompt returns
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Continue,
+    Quit,
+}
+
+pub struct Debugger {
+    breakpoints: Vec<u16>,
+    conditions: Vec<Condition>,
+    // Also set on the core, which does the checking in its write path
+    watchpoints: Vec<u16>,
+    // Cycle counts to stop at, for "it breaks at cycle N" reports
+    cycle_breaks: Vec<u64>,
+    // Stop before the next instruction, used for stepping and break requests
+    paused: bool,
+    // Cycle count to stop at for `step <count>`
+    step_until: Option<u64>,
+    // Stop once the display changes, for `ud`
+    until_draw: bool,
+    // Stack depth to get below and the cycle count to give up at, for `finish`
+    finish: Option<(usize, u64)>,
+    // Address of the instruction being run, for saying which one drew
+    last_pc: u16,
+    // PC the prompt was left at, so a breakpoint there doesn't fire again
+    // straight away (FX0A sits on the same PC until a key is pressed)
+    resumed_at: Option<u16>,
+    // Path of a ROM swapped in with `load` that the caller hasn't taken yet
+    loaded_rom: Option<String>,
+    // N of the last DXYN run, the default height for `sprite`
+    last_sprite_height: Option<u16>,
+    // State before the instruction being run, kept once it has run
+    before: Option<Snapshot>,
+    // State before each of the last instructions run, newest last, for `back`
+    history: VecDeque<Snapshot>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: Vec::new(),
+            conditions: Vec::new(),
+            watchpoints: Vec::new(),
+            cycle_breaks: Vec::new(),
+            paused: false,
+            step_until: None,
+            until_draw: false,
+            finish: None,
+            last_pc: 0,
+            resumed_at: None,
+            loaded_rom: None,
+            last_sprite_height: None,
+            before: None,
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    // A ROM swapped in with `load` since the last call, so the caller can
+    // follow it before it runs
+    pub fn take_loaded_rom(&mut self) -> Option<String> {
+        self.loaded_rom.take()
+    }
+
+    pub fn has_loaded_rom(&self) -> bool {
+        self.loaded_rom.is_some()
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        if !self.breakpoints.contains(&address) {
+            self.breakpoints.push(address);
+        }
+    }
+
+    pub fn add_cycle_break(&mut self, cycle: u64) {
+        if !self.cycle_breaks.contains(&cycle) {
+            self.cycle_breaks.push(cycle);
+        }
+    }
+
+    pub fn add_condition(&mut self, text: &str) -> Result<(), String> {
+        let condition = Condition::parse(text)
+            .ok_or_else(|| format!("Invalid condition '{}', expected e.g. 'V5 == 0xFF'", text))?;
+        self.conditions.push(condition);
+        Ok(())
+    }
+
+    // Checked before each instruction, shows the prompt if it should stop
+    pub fn before_cycle(&mut self, chip8: &mut Chip8) -> Action {
+        let pc = chip8.pc();
+        self.last_pc = pc;
+        if self.resumed_at.is_some_and(|resumed| resumed != pc) {
+            self.resumed_at = None;
+        }
+
+        if self.paused {
+            self.paused = false;
+        } else if self.breakpoints.contains(&pc) && self.resumed_at.is_none() {
+            println!("Breakpoint at {:#05X}", pc);
+        } else {
+            self.before = Some(chip8.snapshot());
+            return Action::Continue;
+        }
+
+        let action = self.prompt(chip8);
+        self.resumed_at = Some(chip8.pc());
+        self.before = Some(chip8.snapshot());
+        action
+    }
+
+    // Checked after each instruction so conditions see its result
+    pub fn after_cycle(&mut self, chip8: &mut Chip8, result: &Result<(), Fault>) {
+        // Waiting on FX0A doesn't run anything, so there's nothing to undo
+        let before = self
+            .before
+            .take()
+            .filter(|before| chip8.cycle_count() != before.cycle_count);
+        let ran = before.is_some();
+        if let Some(before) = before {
+            if self.history.len() == HISTORY_DEPTH {
+                self.history.pop_front();
+            }
+            self.history.push_back(before);
+        }
+        if ran && self.cycle_breaks.contains(&chip8.cycle_count()) {
+            println!("Reached cycle {}", chip8.cycle_count());
+            self.paused = true;
+        }
+        let opcode = chip8.opcode_at(self.last_pc);
+        if opcode & 0xF000 == 0xD000 {
+            self.last_sprite_height = Some(opcode & 0x000F);
+        }
+        // The core has already stepped over it, continuing carries on past it
+        if let Err(Fault::UnknownOpcode(opcode)) = result {
+            println!(
+                "Stopped on unknown opcode {:04X} at {:#05X}, 'c' carries on past it",
+                opcode, self.last_pc
+            );
+            self.paused = true;
+        }
+        // Counted in executed instructions, so waiting on FX0A doesn't use steps up
+        if self
+            .step_until
+            .is_some_and(|until| chip8.cycle_count() >= until)
+        {
+            self.step_until = None;
+            self.paused = true;
+        }
+        if let Some((depth, give_up_at)) = self.finish {
+            if chip8.stack().len() < depth {
+                println!("Returned from {:#05X}", self.last_pc);
+                self.finish = None;
+                self.paused = true;
+            } else if chip8.cycle_count() >= give_up_at {
+                println!(
+                    "No return after {} instructions, stopping",
+                    FINISH_MAX_CYCLES
+                );
+                self.finish = None;
+                self.paused = true;
+            }
+        }
+        if self.until_draw && chip8.display_dirty() {
+            let opcode = chip8.opcode_at(self.last_pc);
+            println!(
+                "Display changed by {:04X} ({}) at {:#05X}",
+                opcode,
+                disasm::decode(opcode),
+                self.last_pc
+            );
+            self.until_draw = false;
+            self.paused = true;
+        }
+        if let Some(hit) = chip8.take_watch_hit() {
+            println!(
+                "Watchpoint: instruction at {:#05X} wrote {:#04X} to {:#05X} (was {:#04X})",
+                hit.pc, hit.new, hit.address, hit.old
+            );
+            self.paused = true;
+        }
+        for condition in &mut self.conditions {
+            if condition.triggered(chip8) {
+                println!("Condition {} hit", condition.describe());
+                self.paused = true;
+            }
+        }
+    }
+
+    fn prompt(&mut self, chip8: &mut Chip8) -> Action {
+        // Whatever stopped it, a step, `ud` or `finish` in progress is over
+        self.step_until = None;
+        self.until_draw = false;
+        self.finish = None;
+        print!("{}", listing(chip8, LISTING_BEFORE, LISTING_AFTER));
+        println!("cycle {}", chip8.cycle_count());
+
+        let stdin = io::stdin();
+        loop {
+            print!("(debug) ");
+            io::stdout().flush().ok();
+
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                // stdin closed, nothing more can be asked
+                return Action::Quit;
+            }
+            let line = line.trim();
+            let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let rest = rest.trim();
+
+            match command {
+                "c" | "continue" => return Action::Continue,
+                "s" | "step" => match rest {
+                    "" => {
+                        self.paused = true;
+                        return Action::Continue;
+                    }
+                    _ => match rest.parse::<u64>() {
+                        Ok(0) => {}
+                        Ok(count) => {
+                            self.step_until = Some(chip8.cycle_count() + count);
+                            return Action::Continue;
+                        }
+                        Err(_) => println!("Usage: s [count]"),
+                    },
+                },
+                "ud" => {
+                    chip8.clear_display_dirty();
+                    self.until_draw = true;
+                    return Action::Continue;
+                }
+                "back" => match self.history.pop_back() {
+                    Some(snapshot) => {
+                        // Same memory size, so this can't fail
+                        chip8.restore(&snapshot).unwrap();
+                        print!("{}", listing(chip8, LISTING_BEFORE, LISTING_AFTER));
+                        println!("cycle {}", chip8.cycle_count());
+                    }
+                    None => println!("No earlier instruction to go back to"),
+                },
+                "finish" => match chip8.stack().len() {
+                    0 => println!("Not in a subroutine"),
+                    depth => {
+                        self.finish = Some((depth, chip8.cycle_count() + FINISH_MAX_CYCLES));
+                        return Action::Continue;
+                    }
+                },
+                "q" | "quit" => return Action::Quit,
+                "r" | "regs" => chip8.print_state(),
+                "b" | "break" => match crate::parse_address(rest) {
+                    Some(address) => {
+                        self.add_breakpoint(address);
+                        println!("Breakpoint at {:#05X}", address);
+                    }
+                    None => println!("Usage: b <address>"),
+                },
+                "bcy" => match rest.parse::<u64>() {
+                    Ok(cycle) => {
+                        self.add_cycle_break(cycle);
+                        println!("Break at cycle {}", cycle);
+                    }
+                    Err(_) => println!("Usage: bcy <cycle>"),
+                },
+                "bc" => match self.add_condition(rest) {
+                    Ok(()) => println!("Break when {}", rest),
+                    Err(e) => println!("{}", e),
+                },
+                "w" | "watch" => match crate::parse_address(rest) {
+                    Some(address) => {
+                        if !self.watchpoints.contains(&address) {
+                            self.watchpoints.push(address);
+                            chip8.add_watchpoint(address);
+                        }
+                        println!("Watching writes to {:#05X}", address);
+                    }
+                    None => println!("Usage: w <address>"),
+                },
+                "u" | "dis" => match parse_window(rest) {
+                    Some((before, after)) => print!("{}", listing(chip8, before, after)),
+                    None => println!("Usage: u [before after]"),
+                },
+                "goto" => goto(chip8, rest),
+                "sprite" => match rest {
+                    "" => match self.last_sprite_height {
+                        Some(height) => sprite(chip8, height),
+                        None => println!("No sprite drawn yet, give a height: sprite <height>"),
+                    },
+                    _ => match crate::parse_address(rest) {
+                        Some(height) => sprite(chip8, height),
+                        None => println!("Usage: sprite [height]"),
+                    },
+                },
+                "timer" => timer(chip8, rest),
+                "fill" => fill(chip8, rest),
+                "load" => {
+                    if load(chip8, rest) {
+                        self.loaded_rom = Some(rest.to_string());
+                        self.history.clear();
+                    }
+                }
+                "dump" => dump(chip8, rest),
+                "save" => save(chip8, rest),
+                "restore" => restore(chip8, rest),
+                "l" | "list" => self.list(),
+                "d" | "delete" => self.delete(rest, chip8),
+                "h" | "help" => print_help(),
+                "" => {}
+                _ => println!("Unknown command '{}', try 'help'", command),
+            }
+        }
+    }
+
+    // Breakpoints first, then conditions, watchpoints and cycles, numbered
+    // for `delete`
+    fn list(&self) {
+        if self.breakpoints.is_empty()
+            && self.conditions.is_empty()
+            && self.watchpoints.is_empty()
+            && self.cycle_breaks.is_empty()
+        {
+            println!("No breakpoints");
+        }
+        for (n, address) in self.breakpoints.iter().enumerate() {
+            println!("{}: at {:#05X}", n, address);
+        }
+        for (n, condition) in self.conditions.iter().enumerate() {
+            println!(
+                "{}: when {}",
+                self.breakpoints.len() + n,
+                condition.describe()
+            );
+        }
+        let first_watch = self.breakpoints.len() + self.conditions.len();
+        for (n, address) in self.watchpoints.iter().enumerate() {
+            println!("{}: write to {:#05X}", first_watch + n, address);
+        }
+        let first_cycle = first_watch + self.watchpoints.len();
+        for (n, cycle) in self.cycle_breaks.iter().enumerate() {
+            println!("{}: at cycle {}", first_cycle + n, cycle);
+        }
+    }
+
+    fn delete(&mut self, which: &str, chip8: &mut Chip8) {
+        let first_watch = self.breakpoints.len() + self.conditions.len();
+        let first_cycle = first_watch + self.watchpoints.len();
+
+        if which.is_empty() {
+            self.breakpoints.clear();
+            self.conditions.clear();
+            self.cycle_breaks.clear();
+            for address in self.watchpoints.drain(..) {
+                chip8.remove_watchpoint(address);
+            }
+            println!("Deleted all breakpoints");
+            return;
+        }
+
+        match which.parse::<usize>() {
+            Ok(n) if n < self.breakpoints.len() => {
+                self.breakpoints.remove(n);
+            }
+            Ok(n) if n < first_watch => {
+                self.conditions.remove(n - self.breakpoints.len());
+            }
+            Ok(n) if n < first_cycle => {
+                chip8.remove_watchpoint(self.watchpoints.remove(n - first_watch));
+            }
+            Ok(n) if n < first_cycle + self.cycle_breaks.len() => {
+                self.cycle_breaks.remove(n - first_cycle);
+            }
+            _ => println!("No breakpoint '{}', see 'list'", which),
+        }
+    }
+}
+
+// Instructions shown either side of the PC when the debugger stops
+const LISTING_BEFORE: u16 = 5;
+const LISTING_AFTER: u16 = 10;
+
+// How long `finish` waits for a return before stopping anyway
+const FINISH_MAX_CYCLES: u64 = 1_000_000;
+
+// Instructions `back` can undo, each one a full snapshot
+const HISTORY_DEPTH: usize = 256;
+
+// `before` and `after` for `u`, the defaults when there aren't any
+fn parse_window(args: &str) -> Option<(u16, u16)> {
+    let mut parts = args.split_whitespace();
+    match (parts.next(), parts.next(), parts.next()) {
+        (None, _, _) => Some((LISTING_BEFORE, LISTING_AFTER)),
+        (Some(before), Some(after), None) => Some((before.parse().ok()?, after.parse().ok()?)),
+        _ => None,
+    }
+}
+
+// Disassembly of the instructions around the PC, with an arrow on the
+// current one and register values on it and the next. Steps in 2 bytes from
+// the PC, so an odd PC lists the same misaligned opcodes the machine would
+// fetch, and stops at the ends of memory.
+fn listing(chip8: &Chip8, before: u16, after: u16) -> String {
+    let pc = chip8.pc();
+    let last = chip8.memory().len() as u32 - 2;
+    let start = pc as u32 - (pc as u32 / 2).min(before as u32) * 2;
+    let end = (pc as u32 + after as u32 * 2).min(last.max(pc as u32));
+
+    let mut text = String::new();
+    for address in (start..=end).step_by(2) {
+        let address = address as u16;
+        let opcode = chip8.opcode_at(address);
+        let mut mnemonic = disasm::decode(opcode);
+        // Further on, the registers may have changed by the time it runs
+        if address == pc || address == pc.wrapping_add(2) {
+            mnemonic = disasm::annotate_registers(&mnemonic, chip8.registers());
+        }
+        text.push_str(&format!(
+            "{} {:#05X}  {:04X}  {}\n",
+            if address == pc { "->" } else { "  " },
+            address,
+            opcode,
+            mnemonic
+        ));
+    }
+    text
+}
+
+fn goto(chip8: &mut Chip8, address: &str) {
+    let Some(address) = crate::parse_address(address) else {
+        println!("Usage: goto <address>");
+        return;
+    };
+    if address as usize >= chip8.memory().len() {
+        println!(
+            "{:#05X} is outside {} bytes of memory",
+            address,
+            chip8.memory().len()
+        );
+        return;
+    }
+    if address % 2 != 0 {
+        println!("Warning: {:#05X} is odd, instructions are 2 bytes", address);
+    }
+
+    chip8.set_pc(address);
+    let opcode = chip8.opcode_at(address);
+    println!(
+        "{:#05X}  {:04X}  {}",
+        address,
+        opcode,
+        disasm::decode(opcode)
+    );
+}
+
+// The `height` bytes at I as a grid, one row per byte, the way DXYN would
+// draw them
+fn sprite(chip8: &Chip8, height: u16) {
+    let memory = chip8.memory();
+    println!("{} rows at I = {:#05X}", height, chip8.index());
+    for row in 0..height {
+        let address = chip8.index().wrapping_add(row) as usize & (memory.len() - 1);
+        let bits: String = (0..8)
+            .map(|bit| {
+                if memory[address] & (0x80 >> bit) != 0 {
+                    '#'
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        println!("{:#05X}  {:02X}  {}", address, memory[address], bits);
+    }
+}
+
+// `fill <start> <end> <byte>`, both ends included. Goes through `load_at`,
+// so the interpreter area and the font are refused like for any other load.
+fn fill(chip8: &mut Chip8, args: &str) {
+    let parts: Vec<Option<u16>> = args.split_whitespace().map(crate::parse_address).collect();
+    let [Some(start), Some(end), Some(byte)] = parts[..] else {
+        println!("Usage: fill <start> <end> <byte>");
+        return;
+    };
+    let Ok(byte) = u8::try_from(byte) else {
+        println!("{:#X} doesn't fit in a byte", byte);
+        return;
+    };
+    if end < start {
+        println!("The range ends at {:#05X}, before it starts", end);
+        return;
+    }
+    if end as usize >= chip8.memory().len() {
+        println!(
+            "{:#05X} is outside {} bytes of memory",
+            end,
+            chip8.memory().len()
+        );
+        return;
+    }
+
+    let bytes = vec![byte; (end - start) as usize + 1];
+    match chip8.load_at(start, &bytes) {
+        Ok(()) => println!("Filled {:#05X}-{:#05X} with {:#04X}", start, end, byte),
+        Err(e) => println!("Can't fill {:#05X}-{:#05X} : {}", start, end, e),
+    }
+}
+
+// `timer <dt|st> <value>`, `timer freeze|run`, or just `timer` to print both
+fn timer(chip8: &mut Chip8, args: &str) {
+    let mut parts = args.split_whitespace();
+    let (which, value) = (parts.next(), parts.next());
+    let value = value.map(|value| crate::parse_address(value).and_then(|v| u8::try_from(v).ok()));
+    match (which, value) {
+        (None, _) => {}
+        (Some("freeze"), None) => chip8.set_timers_frozen(true),
+        (Some("run"), None) => chip8.set_timers_frozen(false),
+        (Some("dt"), Some(Some(value))) => chip8.set_delay_timer(value),
+        (Some("st"), Some(Some(value))) => chip8.set_sound_timer(value),
+        _ => {
+            println!("Usage: timer [dt|st <value> | freeze | run]");
+            return;
+        }
+    }
+    println!(
+        "DT={} ST={}{}",
+        chip8.delay_timer(),
+        chip8.sound_timer(),
+        if chip8.timers_frozen() {
+            " (frozen)"
+        } else {
+            ""
+        }
+    );
+}
+
+// Swap in another ROM without restarting. Breakpoints and watchpoints stay.
+// Returns whether it loaded.
+fn load(chip8: &mut Chip8, path: &str) -> bool {
+    if path.is_empty() {
+        println!("Usage: load <rom_file>");
+        return false;
+    }
+    let rom = match fs::read(path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            println!("Failed to read ROM file '{}' : {}", path, e);
+            return false;
+        }
+    };
+
+    match chip8.load_rom(&rom) {
+        Ok(()) => {
+            println!("Loaded {} ({} bytes)", path, rom.len());
+            true
+        }
+        Err(e) => {
+            println!("Failed to load ROM file '{}' : {}", path, e);
+            false
+        }
+    }
+}
+
+// `dump [-m] [file]`, JSON state to stdout or a file, -m adds memory
+fn dump(chip8: &Chip8, args: &str) {
+    let (with_memory, path) = match args.strip_prefix("-m") {
+        Some(rest) => (true, rest.trim()),
+        None => (false, args),
+    };
+    let json = if with_memory {
+        chip8.dump_state_json_with_memory()
+    } else {
+        chip8.dump_state_json()
+    };
+
+    if path.is_empty() {
+        println!("{}", json);
+    } else {
+        match fs::write(path, json) {
+            Ok(()) => println!("Wrote state to {}", path),
+            Err(e) => println!("Failed to write '{}' : {}", path, e),
+        }
+    }
+}
+
+fn save(chip8: &Chip8, path: &str) {
+    if path.is_empty() {
+        println!("Usage: save <file>");
+        return;
+    }
+    match fs::write(path, chip8.snapshot().to_json()) {
+        Ok(()) => println!("Saved state to {}", path),
+        Err(e) => println!("Failed to write '{}' : {}", path, e),
+    }
+}
+
+fn restore(chip8: &mut Chip8, path: &str) {
+    if path.is_empty() {
+        println!("Usage: restore <file>");
+        return;
+    }
+    let result = fs::read_to_string(path)
+        .map_err(Chip8Error::from)
+        .and_then(|json| Snapshot::from_json(&json))
+        .and_then(|snapshot| chip8.restore(&snapshot));
+    match result {
+        Ok(()) => println!("Restored state from {}", path),
+        Err(e) => println!("Failed to restore '{}' : {}", path, e),
+    }
+}
+
+fn print_help() {
+    println!("c, continue        Run until the next break");
+    println!("s, step [n]        Run one instruction, or n of them");
+    println!("ud                 Run until the display changes");
+    println!("finish             Run until the current subroutine returns");
+    println!(
+        "back               Undo the last instruction run (up to {})",
+        HISTORY_DEPTH
+    );
+    println!("b, break <addr>    Break when PC reaches <addr>");
+    println!("bc <cond>          Break when a register comparison becomes true,");
+    println!("                   e.g. 'bc V5 == 0xFF' or 'bc VF != 0'");
+    println!("w, watch <addr>    Break after an instruction writes to <addr>");
+    println!("bcy <n>            Break once the cycle counter reaches <n>");
+    println!("l, list            List breakpoints");
+    println!("d, delete [n]      Delete breakpoint n, or all of them");
+    println!("r, regs            Print the registers");
+    println!("sprite [height]    Show the sprite at I (default: the last DXYN's height)");
+    println!("u [before after]   Disassemble around the PC (default 5 before, 10 after)");
+    println!("goto <addr>        Move PC to <addr> without running anything");
+    println!("fill <a> <b> <n>   Set memory from <a> to <b> (inclusive) to byte <n>");
+    println!("timer [dt|st <n>]  Print the timers, or set the delay or sound timer");
+    println!("timer freeze|run   Stop the timers counting down, or let them go again");
+    println!("load <file>        Reset the machine and load another ROM");
+    println!("dump [-m] [file]   Print the state as JSON, or write it to a file");
+    println!("                   (-m includes memory)");
+    println!("save <file>        Save a snapshot of the machine");
+    println!("restore <file>     Go back to a saved snapshot");
+    println!("q, quit            Exit the emulator");
+}
@@ -0,0 +1,260 @@
+// CHIP-8 disassembler producing the same mnemonics the assembler accepts.
+use crate::opcodes::{self, OperandFormat};
+use std::collections::{HashMap, HashSet};
+
+pub struct Instruction {
+    pub address: u16,
+    // Symbol for this address, when disassembling with symbols
+    pub label: Option<String>,
+    // None for a trailing odd byte, which can't form a full opcode, or a
+    // data byte in a control flow listing
+    pub opcode: Option<u16>,
+    pub mnemonic: String,
+}
+
+// Decode a single opcode from `opcodes::OPCODES`. Anything the emulator
+// doesn't implement becomes a `DW` so the listing can still be reassembled.
+pub fn decode(opcode: u16) -> String {
+    let Some(info) = opcodes::find(opcode, false) else {
+        return format!("DW #{:04X}", opcode);
+    };
+    let x = (opcode & 0x0F00) >> 8;
+    let y = (opcode & 0x00F0) >> 4;
+
+    let operands: Vec<String> = info
+        .operands
+        .iter()
+        .map(|&operand| match operand {
+            OperandFormat::Vx => format!("V{:X}", x),
+            OperandFormat::Vy => format!("V{:X}", y),
+            OperandFormat::Address => format!("#{:03X}", opcode & 0x0FFF),
+            OperandFormat::Byte => format!("#{:02X}", opcode & 0x00FF),
+            OperandFormat::Nibble => (opcode & 0x000F).to_string(),
+            OperandFormat::XNibble => x.to_string(),
+            keyword => keyword.keyword().unwrap().to_string(),
+        })
+        .collect();
+    if operands.is_empty() {
+        info.mnemonic.to_string()
+    } else {
+        format!("{} {}", info.mnemonic, operands.join(", "))
+    }
+}
+
+// Like `decode`, with jump, call and LD I targets named where a symbol
+// matches
+pub fn decode_with_symbols(opcode: u16, symbols: &HashMap<u16, String>) -> String {
+    let nnn = opcode & 0x0FFF;
+    let name = match opcode & 0xF000 {
+        0x1000 | 0x2000 | 0xA000 | 0xB000 => symbols.get(&nnn),
+        _ => None,
+    };
+    match name {
+        Some(name) => decode(opcode).replace(&format!("#{:03X}", nnn), name),
+        None => decode(opcode),
+    }
+}
+
+// A decoded instruction with the current value after each register
+// operand, "ADD V5(=0x0A), #03", for reading code against the live state
+pub fn annotate_registers(mnemonic: &str, registers: &[u8; 16]) -> String {
+    mnemonic
+        .split(' ')
+        .map(|word| {
+            let (operand, comma) = match word.strip_suffix(',') {
+                Some(operand) => (operand, ","),
+                None => (word, ""),
+            };
+            let register = operand
+                .strip_prefix('V')
+                .filter(|digit| digit.len() == 1)
+                .and_then(|digit| usize::from_str_radix(digit, 16).ok());
+            match register {
+                Some(register) => format!("{}(={:#04X}){}", operand, registers[register], comma),
+                None => word.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Reads a symbol file from the assembler, `address = name` per line with
+// the address in hex (0x or #) or decimal. `;` starts a comment.
+pub fn parse_symbols(text: &str) -> Result<HashMap<u16, String>, String> {
+    let mut symbols = HashMap::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split(';').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let parsed = line.split_once('=').and_then(|(address, name)| {
+            let address = address.trim();
+            let address = match address
+                .strip_prefix("0x")
+                .or_else(|| address.strip_prefix("0X"))
+                .or_else(|| address.strip_prefix('#'))
+            {
+                Some(hex) => u16::from_str_radix(hex, 16).ok()?,
+                None => address.parse().ok()?,
+            };
+            let name = name.trim();
+            (!name.is_empty()).then(|| (address, name.to_string()))
+        });
+        let (address, name) = parsed.ok_or_else(|| {
+            format!(
+                "line {}: expected 'address = name', got '{}'",
+                index + 1,
+                line
+            )
+        })?;
+        symbols.insert(address, name);
+    }
+    Ok(symbols)
+}
+
+// Linear sweep over a ROM loaded at `start`
+pub fn disassemble(rom: &[u8], start: u16) -> Vec<Instruction> {
+    disassemble_with_symbols(rom, start, &HashMap::new())
+}
+
+pub fn disassemble_with_symbols(
+    rom: &[u8],
+    start: u16,
+    symbols: &HashMap<u16, String>,
+) -> Vec<Instruction> {
+    let mut listing = Vec::with_capacity(rom.len() / 2 + 1);
+
+    for (index, chunk) in rom.chunks(2).enumerate() {
+        let address = start.wrapping_add(index as u16 * 2);
+        let instruction = match *chunk {
+            [high, low] => {
+                let opcode = u16::from_be_bytes([high, low]);
+                Instruction {
+                    address,
+                    label: symbols.get(&address).cloned(),
+                    opcode: Some(opcode),
+                    mnemonic: decode_with_symbols(opcode, symbols),
+                }
+            }
+            [byte] => Instruction {
+                address,
+                label: symbols.get(&address).cloned(),
+                opcode: None,
+                mnemonic: format!("DB #{:02X}", byte),
+            },
+            _ => unreachable!(),
+        };
+        listing.push(instruction);
+    }
+
+    listing
+}
+
+// Where execution can go after the instruction at `address`, or None when
+// it can't be worked out from the opcode alone (BNNN)
+fn successors(opcode: u16, address: u16) -> Option<Vec<u16>> {
+    let next = address.wrapping_add(2);
+    let nnn = opcode & 0x0FFF;
+    Some(match opcode & 0xF000 {
+        _ if opcode == 0x00EE => vec![],
+        // F000 NNNN, over the address
+        _ if opcode == 0xF000 => vec![next.wrapping_add(2)],
+        0x1000 => vec![nnn],
+        0x2000 => vec![nnn, next],
+        0xB000 => return None,
+        // Skips
+        0x3000 | 0x4000 | 0x5000 | 0x9000 | 0xE000 => vec![next, next.wrapping_add(2)],
+        _ => vec![next],
+    })
+}
+
+// Follows jumps, calls and skips from `start` instead of sweeping linearly,
+// so data tables in the ROM come out as `DB` bytes rather than nonsense
+// instructions. Only instructions reachable from the entry point count as
+// code. Also returns the addresses of computed jumps (BNNN), whose targets
+// can't be known, so code only they reach shows up as data.
+pub fn disassemble_flow(
+    rom: &[u8],
+    start: u16,
+    symbols: &HashMap<u16, String>,
+) -> (Vec<Instruction>, Vec<u16>) {
+    let end = start as usize + rom.len();
+    let opcode_at = |address: u16| {
+        let offset = (address - start) as usize;
+        u16::from_be_bytes([rom[offset], rom[offset + 1]])
+    };
+
+    let mut code = HashSet::new();
+    let mut unresolved = Vec::new();
+    let mut pending = vec![start];
+    while let Some(address) = pending.pop() {
+        // Jumps outside the ROM (into the interpreter, or past the end)
+        // can't be followed
+        if (address as usize) < start as usize
+            || address as usize + 2 > end
+            || !code.insert(address)
+        {
+            continue;
+        }
+        let opcode = opcode_at(address);
+        // Something the emulator can't run, or a machine code call, which in
+        // practice is zeroed memory: most likely the flow has run into data
+        match opcodes::find(opcode, false) {
+            Some(info) if info.pattern != "0NNN" => {}
+            _ => continue,
+        }
+        match successors(opcode, address) {
+            Some(next) => pending.extend(next),
+            None => unresolved.push(address),
+        }
+    }
+    unresolved.sort();
+
+    let mut listing = Vec::new();
+    let mut address = start;
+    while (address as usize) < end {
+        let label = symbols.get(&address).cloned();
+        if code.contains(&address) {
+            let opcode = opcode_at(address);
+            listing.push(Instruction {
+                address,
+                label,
+                opcode: Some(opcode),
+                mnemonic: decode_with_symbols(opcode, symbols),
+            });
+            address = address.wrapping_add(2);
+        } else {
+            let byte = rom[(address - start) as usize];
+            listing.push(Instruction {
+                address,
+                label,
+                opcode: None,
+                mnemonic: format!("DB #{:02X}", byte),
+            });
+            address = address.wrapping_add(1);
+        }
+    }
+
+    (listing, unresolved)
+}
+
+// A listing as assembler source: labels on their own line, each instruction
+// indented with its address and raw bytes in a comment. Assembles back to
+// the same bytes.
+pub fn to_source(listing: &[Instruction]) -> String {
+    let mut source = String::new();
+    for instruction in listing {
+        if let Some(label) = &instruction.label {
+            source.push_str(&format!("{}:\n", label));
+        }
+        let raw = match instruction.opcode {
+            Some(opcode) => format!("{:04X}", opcode),
+            None => instruction.mnemonic.trim_start_matches("DB #").to_string(),
+        };
+        source.push_str(&format!(
+            "    {:<20}; {:#05X}  {}\n",
+            instruction.mnemonic, instruction.address, raw
+        ));
+    }
+    source
+}
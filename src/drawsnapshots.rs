@@ -0,0 +1,54 @@
+// --snapshot-on-draw: the machine state after every DXYN, one file per draw
+// numbered from 1 (draw-000001.state, ...), in the same JSON as save states
+// so `chip8 diff` can compare one with a reference emulator's. Every file
+// holds all of memory, so this is only for short debugging runs.
+use chip8::Chip8;
+use std::fs;
+use std::path::PathBuf;
+
+pub struct DrawSnapshots {
+    dir: PathBuf,
+    written: u64,
+    // Set after a write failed, the run carries on without more snapshots
+    failed: bool,
+}
+
+impl DrawSnapshots {
+    pub fn create(dir: &str) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create '{}' : {}", dir, e))?;
+        Ok(DrawSnapshots {
+            dir: PathBuf::from(dir),
+            written: 0,
+            failed: false,
+        })
+    }
+
+    // Call after running instructions to write out their draws
+    pub fn record(&mut self, chip8: &mut Chip8) {
+        for snapshot in chip8.take_draw_snapshots() {
+            if self.failed {
+                continue;
+            }
+            let path = self.dir.join(format!("draw-{:06}.state", self.written + 1));
+            match fs::write(&path, snapshot.to_json()) {
+                Ok(()) => self.written += 1,
+                Err(e) => {
+                    eprintln!(
+                        "Failed to write '{}' : {}, no more draw snapshots",
+                        path.display(),
+                        e
+                    );
+                    self.failed = true;
+                }
+            }
+        }
+    }
+
+    pub fn report(&self) {
+        println!(
+            "Wrote {} draw snapshots to {}",
+            self.written,
+            self.dir.display()
+        );
+    }
+}
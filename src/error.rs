@@ -0,0 +1,99 @@
+// Errors from the public API, for embedders to match on. Faults that stop a
+// running machine are also `Fault`s, which the machine keeps (see
+// `Chip8::fault`); anything else that can go wrong getting a program or a
+// snapshot into it is only here.
+use crate::chip8::Fault;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Chip8Error {
+    // ROM size and the space available for it
+    #[error("ROM is {0} bytes but only {1} fit in memory")]
+    RomTooLarge(usize, usize),
+    // A load that would overwrite the interpreter area or font, holds the
+    // first address it would have hit
+    #[error("{0:#05X} is reserved for the interpreter or font")]
+    ReservedMemory(u16),
+    #[error("stack overflow")]
+    StackOverflow,
+    #[error("return with an empty stack")]
+    StackUnderflow,
+    #[error("unknown opcode {0:#06X}")]
+    UnknownOpcode(u16),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    // A snapshot that doesn't parse or doesn't fit this machine
+    #[error("bad snapshot: {0}")]
+    BadSnapshot(String),
+    // Any other fault that stopped the machine
+    #[error("{0}")]
+    Fault(Fault),
+}
+
+impl From<Fault> for Chip8Error {
+    fn from(fault: Fault) -> Self {
+        match fault {
+            Fault::StackOverflow => Chip8Error::StackOverflow,
+            Fault::StackUnderflow => Chip8Error::StackUnderflow,
+            Fault::UnknownOpcode(opcode) => Chip8Error::UnknownOpcode(opcode),
+            fault => Chip8Error::Fault(fault),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages() {
+        let cases = [
+            (
+                Chip8Error::RomTooLarge(4000, 3584),
+                "ROM is 4000 bytes but only 3584 fit in memory",
+            ),
+            (
+                Chip8Error::ReservedMemory(0x50),
+                "0x050 is reserved for the interpreter or font",
+            ),
+            (Chip8Error::StackOverflow, "stack overflow"),
+            (Chip8Error::StackUnderflow, "return with an empty stack"),
+            (Chip8Error::UnknownOpcode(0xE0FF), "unknown opcode 0xE0FF"),
+            (
+                Chip8Error::Io(std::io::Error::other("disk on fire")),
+                "disk on fire",
+            ),
+            (
+                Chip8Error::BadSnapshot("version 9".to_string()),
+                "bad snapshot: version 9",
+            ),
+            (
+                Chip8Error::Fault(Fault::CycleLimit(100)),
+                "reached the limit of 100 instructions",
+            ),
+        ];
+        for (error, message) in cases {
+            assert_eq!(error.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn faults_with_their_own_variant_use_it() {
+        assert!(matches!(
+            Chip8Error::from(Fault::StackOverflow),
+            Chip8Error::StackOverflow
+        ));
+        assert!(matches!(
+            Chip8Error::from(Fault::StackUnderflow),
+            Chip8Error::StackUnderflow
+        ));
+        assert!(matches!(
+            Chip8Error::from(Fault::UnknownOpcode(0x5AB3)),
+            Chip8Error::UnknownOpcode(0x5AB3)
+        ));
+        assert!(matches!(
+            Chip8Error::from(Fault::SysCall(0x123)),
+            Chip8Error::Fault(Fault::SysCall(0x123))
+        ));
+    }
+}
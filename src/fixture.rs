@@ -0,0 +1,130 @@
+// Starting state for --fixture, applied after the ROM loads. Simpler than a
+// full snapshot to write by hand when handing over the state that shows a
+// bug. One setting per line, anything not given keeps its default:
+//
+//     V3 = 0x07
+//     I = 0x300
+//     PC = 0x204
+//     DT = 60
+//     ST = 0
+//     0x300 = F0 90 F0   ; memory from 0x300, bytes in hex
+//
+// Numbers are hex with 0x or #, else decimal. `;` starts a comment.
+use chip8::Chip8;
+use std::fs;
+
+enum Setting {
+    Register(usize, u8),
+    Index(u16),
+    Pc(u16),
+    DelayTimer(u8),
+    SoundTimer(u8),
+    Memory(u16, Vec<u8>),
+}
+
+pub struct Fixture {
+    settings: Vec<Setting>,
+}
+
+impl Fixture {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+        let mut settings = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let setting = parse_setting(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+            settings.push(setting);
+        }
+        Ok(Fixture { settings })
+    }
+
+    // Addresses are checked against this machine's memory size here, so a
+    // bad fixture stops the run before anything is changed
+    pub fn apply(&self, chip8: &mut Chip8) -> Result<(), String> {
+        let memory_size = chip8.memory().len();
+        for setting in &self.settings {
+            match setting {
+                Setting::Index(address) | Setting::Pc(address)
+                    if *address as usize >= memory_size =>
+                {
+                    return Err(format!(
+                        "address {:#05X} is outside {} bytes of memory",
+                        address, memory_size
+                    ));
+                }
+                Setting::Memory(address, bytes)
+                    if *address as usize + bytes.len() > memory_size =>
+                {
+                    return Err(format!(
+                        "{} bytes at {:#05X} run past the end of {} bytes of memory",
+                        bytes.len(),
+                        address,
+                        memory_size
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        for setting in &self.settings {
+            match setting {
+                Setting::Register(register, value) => chip8.set_register(*register, *value),
+                Setting::Index(address) => chip8.set_index(*address),
+                Setting::Pc(address) => chip8.set_pc(*address),
+                Setting::DelayTimer(value) => chip8.set_delay_timer(*value),
+                Setting::SoundTimer(value) => chip8.set_sound_timer(*value),
+                // The font and interpreter area too, a bug may need them changed
+                Setting::Memory(address, bytes) => chip8
+                    .force_load_at(*address, bytes)
+                    .map_err(|e| e.to_string())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_setting(line: &str) -> Result<Setting, String> {
+    let (name, value) = line
+        .split_once('=')
+        .map(|(name, value)| (name.trim(), value.trim()))
+        .ok_or_else(|| format!("expected 'name = value', got '{}'", line))?;
+
+    let number =
+        || crate::parse_address(value).ok_or_else(|| format!("invalid number '{}'", value));
+    let byte = || {
+        number()
+            .and_then(|n| u8::try_from(n).map_err(|_| format!("{} doesn't fit in a byte", value)))
+    };
+
+    let upper = name.to_uppercase();
+    match upper.as_str() {
+        "I" => Ok(Setting::Index(number()?)),
+        "PC" => Ok(Setting::Pc(number()?)),
+        "DT" => Ok(Setting::DelayTimer(byte()?)),
+        "ST" => Ok(Setting::SoundTimer(byte()?)),
+        _ => {
+            if let Some(register) = upper.strip_prefix('V')
+                && register.len() == 1
+                && let Ok(register) = usize::from_str_radix(register, 16)
+            {
+                return Ok(Setting::Register(register, byte()?));
+            }
+            let address =
+                crate::parse_address(name).ok_or_else(|| format!("unknown setting '{}'", name))?;
+            let bytes = value
+                .split_whitespace()
+                .map(|byte| {
+                    u8::from_str_radix(byte, 16).map_err(|_| format!("invalid byte '{}'", byte))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if bytes.is_empty() {
+                return Err(format!("no bytes given for {}", name));
+            }
+            Ok(Setting::Memory(address, bytes))
+        }
+    }
+}
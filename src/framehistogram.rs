@@ -0,0 +1,60 @@
+// How many instructions ran in each 60Hz frame, to see why a game runs too
+// fast or slow. Frames cut short by a DXYN display wait or spent in an FX0A
+// key wait show up as frames with fewer instructions. Off unless
+// `Chip8::enable_frame_histogram` is called.
+use std::collections::BTreeMap;
+
+// Longest bar in the report, for the most common count
+const BAR_WIDTH: u64 = 40;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameHistogram {
+    // Frames seen for each number of instructions run in a frame
+    frames: BTreeMap<u64, u64>,
+}
+
+impl FrameHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, instructions: u64) {
+        *self.frames.entry(instructions).or_default() += 1;
+    }
+
+    // Frames in which exactly `instructions` instructions ran
+    pub fn frames_with(&self, instructions: u64) -> u64 {
+        self.frames.get(&instructions).copied().unwrap_or(0)
+    }
+
+    pub fn total_frames(&self) -> u64 {
+        self.frames.values().sum()
+    }
+
+    // Table for printing on exit, one line per count seen
+    pub fn report(&self) -> String {
+        let total = self.total_frames();
+        if total == 0 {
+            return String::from("No frames ran\n");
+        }
+        let most = self.frames.values().copied().max().unwrap_or(1);
+        let instructions: u64 = self.frames.iter().map(|(&n, &frames)| n * frames).sum();
+
+        let mut report = String::from("Instructions/frame   Frames      %\n");
+        for (&count, &frames) in &self.frames {
+            report.push_str(&format!(
+                "{:>18} {:>8} {:>6.2} {}\n",
+                count,
+                frames,
+                frames as f64 * 100.0 / total as f64,
+                "#".repeat((frames * BAR_WIDTH).div_ceil(most) as usize)
+            ));
+        }
+        report.push_str(&format!(
+            "{} frames, {:.2} instructions per frame on average\n",
+            total,
+            instructions as f64 / total as f64
+        ));
+        report
+    }
+}
@@ -0,0 +1,71 @@
+// --input-profile: named key layouts for games whose movement keys sit
+// awkwardly on the default layout. A profile moves some Chip-8 keys to other
+// keyboard keys; keyboard keys it takes over stop doing what they did in the
+// default layout, everything else stays where it was.
+use chip8::{Chip8, KEYPAD_LAYOUT};
+use minifb::{Key, Window};
+
+#[derive(Debug)]
+pub struct InputProfile {
+    pub name: &'static str,
+    pub description: &'static str,
+    bindings: &'static [(Key, u8)],
+}
+
+pub const PROFILES: [InputProfile; 4] = [
+    InputProfile {
+        name: "default",
+        description: "the usual 1234/QWER/ASDF/ZXCV layout",
+        bindings: &[],
+    },
+    InputProfile {
+        name: "arrows-as-2468",
+        description: "arrow keys are 2, 4, 6 and 8 (Brix, Tetris, most mazes)",
+        bindings: &[
+            (Key::Up, 0x2),
+            (Key::Left, 0x4),
+            (Key::Right, 0x6),
+            (Key::Down, 0x8),
+        ],
+    },
+    InputProfile {
+        name: "wasd-as-2468",
+        description: "W, A, D and S are 2, 4, 6 and 8",
+        bindings: &[(Key::W, 0x2), (Key::A, 0x4), (Key::D, 0x6), (Key::S, 0x8)],
+    },
+    InputProfile {
+        name: "pong",
+        description: "W/S and Up/Down move the left and right paddles (1/4, C/D)",
+        bindings: &[
+            (Key::W, 0x1),
+            (Key::S, 0x4),
+            (Key::Up, 0xC),
+            (Key::Down, 0xD),
+        ],
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static InputProfile> {
+    PROFILES.iter().find(|profile| profile.name == name)
+}
+
+impl InputProfile {
+    // Same as `Chip8::set_keys` with this profile's keys swapped in
+    pub fn set_keys(&self, chip8: &mut Chip8, window: &Window) {
+        let mut pressed = [false; 16];
+        for (host_key, key) in KEYPAD_LAYOUT {
+            let taken = self.bindings.iter().any(|&(bound, _)| bound == host_key);
+            if !taken && window.is_key_down(host_key) {
+                pressed[key as usize] = true;
+            }
+        }
+        for &(host_key, key) in self.bindings {
+            if window.is_key_down(host_key) {
+                pressed[key as usize] = true;
+            }
+        }
+        for (key, pressed) in pressed.into_iter().enumerate() {
+            chip8.set_key(key as u8, pressed);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+// A directory given instead of a ROM: a menu in the window listing the
+// .ch8 files in it. Up/Down (or W/S) move, Enter runs the one picked, and
+// the rest become the playlist for Page Up/Down.
+use crate::render;
+use minifb::{Key, KeyRepeat};
+use std::fs;
+use std::path::Path;
+
+const TEXT_SCALE: usize = 2;
+// Label glyphs are 5 pixels tall, plus a gap
+const LINE_HEIGHT: usize = 7 * TEXT_SCALE;
+const SELECTED_COLOR: u32 = 0xFFFF00;
+const TEXT_COLOR: u32 = 0x808080;
+
+// Paths of the .ch8 files in `dir`, sorted by name
+pub fn rom_files(dir: &str) -> Result<Vec<String>, String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read directory '{}' : {}", dir, e))?;
+    let mut roms: Vec<String> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("ch8"))
+        })
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    if roms.is_empty() {
+        return Err(format!("No .ch8 files in '{}'", dir));
+    }
+    roms.sort();
+    Ok(roms)
+}
+
+// Index into `roms` of the one picked, None when the menu was closed
+pub fn choose(roms: &[String]) -> Option<usize> {
+    let mut window = crate::open_window(640, 320, false, 1);
+    let mut selected: usize = 0;
+
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        for key in window.get_keys_pressed(KeyRepeat::Yes) {
+            match key {
+                Key::Up | Key::W => selected = selected.saturating_sub(1),
+                Key::Down | Key::S => selected = (selected + 1).min(roms.len() - 1),
+                Key::Enter | Key::NumPadEnter => return Some(selected),
+                _ => {}
+            }
+        }
+
+        let (width, height) = window.get_size();
+        if width == 0 || height == 0 {
+            window.update();
+            continue;
+        }
+        let mut buffer = vec![0; width * height];
+        render::draw_label(
+            &mut buffer,
+            width,
+            TEXT_SCALE * 2,
+            TEXT_SCALE * 2,
+            "PICK A ROM - ENTER TO RUN",
+            TEXT_SCALE,
+            TEXT_COLOR,
+        );
+        // Below the title, scrolled to keep the selection in view
+        let top = TEXT_SCALE * 2 + LINE_HEIGHT * 2;
+        let visible = (height.saturating_sub(top) / LINE_HEIGHT).max(1);
+        let first = selected.saturating_sub(visible - 1);
+        for (line, index) in (first..roms.len()).take(visible).enumerate() {
+            let name = Path::new(&roms[index]).file_name().map_or_else(
+                || roms[index].clone(),
+                |name| name.to_string_lossy().into_owned(),
+            );
+            render::draw_label(
+                &mut buffer,
+                width,
+                TEXT_SCALE * 2,
+                top + line * LINE_HEIGHT,
+                &name,
+                TEXT_SCALE,
+                if index == selected {
+                    SELECTED_COLOR
+                } else {
+                    TEXT_COLOR
+                },
+            );
+        }
+        window.update_with_buffer(&buffer, width, height).unwrap();
+    }
+    None
+}
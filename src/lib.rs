@@ -0,0 +1,21 @@
+pub mod asm;
+mod chip8;
+pub mod clock;
+pub mod compat;
+pub mod disasm;
+mod error;
+mod framehistogram;
+pub mod opcodes;
+mod profile;
+mod snapshot;
+pub mod testutil;
+
+pub use chip8::{
+    CHIP8X_BACKGROUNDS, CHIP8X_COLORS, Chip8, Chip8Config, Chip8xColors, DEFAULT_PALETTE, FONT,
+    Fault, IndexMode, KEYPAD_LAYOUT, MemoryWrite, OpcodeHandler, PALETTE_PRESETS, QUIRK_PRESETS,
+    Quirks, SysCallMode, TimingModel, UnknownOpcodeMode, WatchHit,
+};
+pub use error::Chip8Error;
+pub use framehistogram::FrameHistogram;
+pub use profile::{FAMILIES, Profile};
+pub use snapshot::{DisplaySnapshot, Snapshot, diff_states};
@@ -1,516 +1,2488 @@
-use minifb::{Key, Window, WindowOptions};
-use rand::Rng;
+use chip8::clock::Clock;
+use chip8::opcodes::OperandFormat;
+use chip8::{
+    Chip8, Chip8Config, Chip8Error, DEFAULT_PALETTE, IndexMode, PALETTE_PRESETS, QUIRK_PRESETS,
+    Quirks, Snapshot, SysCallMode, TimingModel, UnknownOpcodeMode, asm, compat, diff_states,
+    disasm, opcodes,
+};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
 use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
-struct Chip8 {
-    // Memory: 4096 bytes (4KB)
-    memory: [u8; 4096],
-
-    // 16 general-purpose 8-bit registers (V0 to VF)
-    // VF is often used as a flag register so should be avoided.
-    registers: [u8; 16],
-
-    // Program counter
-    pc: u16,
-
-    // Index register
-    i: u16,
-
-    // 64 x 32 display, 8 pixels per byte
-    display: [u8; 256],
-
-    // Keypad input
-    keys: [bool; 16],      // Current key states
-    waiting_for_key: bool, // Is CPU waiting for input?
-    key_register: usize,   // Which register to store key in
-
-    // Timers
-    delay_timer: u8,
-    sound_timer: u8,
-
-    // Stack
-    stack: [u16; 16],
-    sp: usize,
-}
-
-impl Chip8 {
-    fn new() -> Self {
-        let mut chip8 = Chip8 {
-            memory: [0; 4096],
-            registers: [0; 16],
-            pc: 0x200,
-            i: 0,
-            display: [0; 256],
-            keys: [false; 16],
-            waiting_for_key: false,
-            key_register: 0,
-            delay_timer: 0,
-            sound_timer: 0,
-            stack: [0; 16],
-            sp: 0,
-        };
+mod activerom;
+mod audio;
+mod cputhread;
+mod debugger;
+mod drawsnapshots;
+mod fixture;
+mod inputprofile;
+mod launcher;
+mod picture;
+mod record;
+mod render;
+mod replay;
+mod romsettings;
+mod romwatch;
+mod rpl;
+mod selftest;
+mod svg;
+mod terminal;
+mod tracecheck;
+mod watchdog;
+mod writelog;
 
-        // Load font into memory starting at 0x050
-        let font: [u8; 80] = [
-            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-            0x20, 0x60, 0x20, 0x20, 0x70, // 1
-            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-        ];
-        chip8.memory[0x050..0x0A0].copy_from_slice(&font);
-
-        chip8
-    }
-    fn get_display_buffer(&self) -> Vec<u32> {
-        let mut buffer = vec![0u32; 64 * 32];
-
-        for y in 0..32 {
-            for x in 0..64 {
-                let byte_index = (y * 8) + (x / 8);
-                let bit_position = 7 - (x % 8);
-                let pixel_on = (self.display[byte_index] & (1 << bit_position)) != 0;
-
-                buffer[y * 64 + x] = if pixel_on { 0xFFFFFF } else { 0x000000 };
-            }
-        }
-        buffer
-    }
-
-    fn print_display(&self) {
-        for row in 0..32 {
-            for byte_in_row in 0..8 {
-                let byte_index = row * 8 + byte_in_row;
-                let byte = self.display[byte_index];
-
-                for bit in 0..8 {
-                    let mask = 1 << (7 - bit);
-                    if (byte & mask) != 0 {
-                        print!("#");
-                    } else {
-                        print!(".");
-                    }
+// How much to print while starting up
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Verbosity {
+    // Nothing
+    Quiet,
+    // The ROM and the settings that most change how it runs
+    Normal,
+    // Every setting
+    Verbose,
+}
+
+#[derive(Debug)]
+struct Config {
+    rom_path: String,
+    verbosity: Verbosity,
+    // Every ROM given, the first is `rom_path`. Page Up/Down switch between them.
+    playlist: Vec<String>,
+    // Stop running the CPU and timers while the window is in the background
+    pause_on_focus_loss: bool,
+    // minifb can't query the monitor, so fullscreen uses this size
+    screen_size: (usize, usize),
+    // Beep volume from 0.0 to 1.0
+    volume: f32,
+    waveform: audio::Waveform,
+    // Beep pitch in Hz
+    frequency: f32,
+    // Run without a window for a fixed number of frames
+    headless: bool,
+    frames: u32,
+    // Run headless for this long in real time instead of for `frames`
+    duration: Option<Duration>,
+    // Keypad events to play back in headless mode
+    input: Option<String>,
+    // Instruction trace from another emulator to run in lockstep with
+    compare_trace: Option<String>,
+    // Directory that gets the machine state after every DXYN
+    snapshot_on_draw: Option<String>,
+    // CPU speed in instructions per second, timers always run at 60Hz
+    hz: u32,
+    // Multiplies both the CPU speed and the timers, below 1 for slow motion
+    speed: f64,
+    // Draw in the terminal instead of a window
+    terminal: bool,
+    // Fail unless the final framebuffer hash matches
+    expect_hash: Option<u64>,
+    // Print the registers, PC and I once a headless run is over
+    print_regs: bool,
+    // Stop with a fault after this many instructions
+    max_cycles: Option<u64>,
+    // Seconds without a display change before the watchdog fires
+    watchdog: Option<u32>,
+    // Times a frame the window's keys are read and a share of the frame's
+    // instructions run, only every this many is drawn
+    key_polls: u32,
+    memory_size: usize,
+    start_address: u16,
+    seed: Option<u64>,
+    unknown_opcode_mode: UnknownOpcodeMode,
+    sys_call_mode: SysCallMode,
+    // Decode the Chip-8X color opcodes
+    chip8x: bool,
+    index_mode: IndexMode,
+    timing: TimingModel,
+    latch_keys: bool,
+    quirks: Quirks,
+    // Set when a quirk was chosen on the command line, which beats --compat-db
+    quirks_from_args: bool,
+    // JSON database of the quirks each known ROM needs
+    compat_db: Option<String>,
+    warn_self_modifying: bool,
+    warn_unaligned: bool,
+    warn_low_pc: bool,
+    // Tick the delay timer between instructions rather than once a frame
+    smooth_delay_timer: bool,
+    // Keep SCHIP RPL flags (high scores) between runs
+    persist_rpl: bool,
+    // Settings given on the command line, remembered for the ROM
+    rom_settings: romsettings::RomSettings,
+    // Use and update the remembered settings, see romsettings
+    persist_rom_settings: bool,
+    // Stop a frame early when the ROM is only waiting on the delay timer
+    skip_idle: bool,
+    // Run the CPU on its own thread instead of between window frames
+    threaded: bool,
+    // Reload the ROM when its file changes
+    watch: bool,
+    // Only sound the beeper for a sound timer still running at the end of a
+    // frame, so a ROM's one-tick clicks stay silent as on frame-sampling
+    // interpreters
+    exact_beep: bool,
+    // Print how often each opcode family ran when the emulator exits
+    profile: bool,
+    // Print which opcode families ran and which didn't on exit
+    coverage: bool,
+    // Print how many instructions ran in each frame on exit
+    frame_histogram: bool,
+    // Colors for off, plane 1, plane 2 and both planes
+    palette: [u32; 4],
+    // Where the window's keys go for a ROM without a profile of its own, None
+    // for the default layout
+    input_profile: Option<&'static inputprofile::InputProfile>,
+    // Start with the CRT scanline effect on (F7 toggles it)
+    scanlines: bool,
+    // Start with lines between the Chip-8 pixels on (G toggles it)
+    grid: bool,
+    grid_color: u32,
+    // How strongly the grid color covers the pixels, 0.0 to 1.0
+    grid_opacity: f32,
+    // Start with bilinear instead of nearest-neighbor scaling (F12 toggles it)
+    smooth_scaling: bool,
+    // Start with the display inverted (F10 toggles it)
+    invert: bool,
+    // Keep pixels from the previous frame lit for one more, against flicker
+    persistence: bool,
+    // Animated GIF output, captured at `gif_fps` and cut off after `gif_max_seconds`
+    record_gif: Option<String>,
+    // Text file that gets every memory write
+    log_writes: Option<String>,
+    // JSON state dump written when the emulator stops, however it stops
+    dump_on_exit: Option<String>,
+    // SVG picture of the display written when the emulator stops
+    svg_on_exit: Option<String>,
+    // Picture put on the display before the ROM starts
+    display_png: Option<String>,
+    // Registers and memory to set up before the first instruction
+    fixture: Option<String>,
+    gif_fps: u32,
+    gif_scale: usize,
+    gif_max_seconds: u32,
+    // Terminal debugger, with breakpoints and conditions set up front
+    debug: bool,
+    breakpoints: Vec<u16>,
+    break_conditions: Vec<String>,
+    break_cycles: Vec<u64>,
+    // Stop in the debugger before the first instruction runs
+    break_at_start: bool,
+}
+
+impl Config {
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut playlist = Vec::new();
+        let mut verbosity = Verbosity::Normal;
+        let mut pause_on_focus_loss = true;
+        let mut screen_size = (1920, 1080);
+        let mut volume = 0.25;
+        let mut waveform = audio::Waveform::Square;
+        let mut frequency = 440.0;
+        let mut headless = false;
+        let mut deterministic = false;
+        let mut terminal = false;
+        let mut frames = 600;
+        let mut duration = None;
+        let mut input = None;
+        let mut compare_trace = None;
+        let mut snapshot_on_draw = None;
+        let mut hz = 660;
+        let mut speed = 1.0;
+        let mut expect_hash = None;
+        let mut print_regs = false;
+        let mut max_cycles = None;
+        let mut watchdog = None;
+        let mut key_polls = 1;
+        let mut memory_size = 4096;
+        let mut start_address = 0x200;
+        let mut seed = None;
+        let mut unknown_opcode_mode = UnknownOpcodeMode::Skip;
+        let mut sys_call_mode = SysCallMode::Ignore;
+        let mut chip8x = false;
+        let mut index_mode = IndexMode::Full;
+        let mut timing = TimingModel::Flat;
+        let mut latch_keys = true;
+        let mut quirks = Quirks::default();
+        let mut quirks_from_args = false;
+        let mut compat_db = None;
+        let mut warn_self_modifying = false;
+        let mut warn_unaligned = false;
+        let mut warn_low_pc = false;
+        let mut smooth_delay_timer = false;
+        let mut skip_idle = false;
+        let mut threaded = false;
+        let mut watch = false;
+        let mut exact_beep = false;
+        let mut profile = false;
+        let mut coverage = false;
+        let mut frame_histogram = false;
+        let mut scanlines = false;
+        let mut grid = false;
+        let mut grid_color = DEFAULT_GRID_COLOR;
+        let mut grid_opacity = DEFAULT_GRID_OPACITY;
+        let mut smooth_scaling = false;
+        let mut invert = false;
+        let mut persistence = false;
+        let mut persist_rpl = true;
+        let mut rom_settings = romsettings::RomSettings::default();
+        let mut persist_rom_settings = true;
+        let mut palette = DEFAULT_PALETTE;
+        let mut input_profile = None;
+        let mut record_gif = None;
+        let mut log_writes = None;
+        let mut dump_on_exit = None;
+        let mut svg_on_exit = None;
+        let mut display_png = None;
+        let mut fixture = None;
+        let mut gif_fps = 30;
+        let mut gif_scale = 4;
+        let mut gif_max_seconds = 60;
+        let mut debug = false;
+        let mut breakpoints = Vec::new();
+        let mut break_conditions = Vec::new();
+        let mut break_cycles = Vec::new();
+        let mut break_at_start = false;
+
+        let mut args = args[1..].iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--background" => pause_on_focus_loss = false,
+                "--screen" => {
+                    let value = args.next().ok_or("--screen needs a WIDTHxHEIGHT value")?;
+                    screen_size = parse_size(value)
+                        .ok_or_else(|| format!("Invalid screen size '{}'", value))?;
+                }
+                "--volume" => {
+                    let value = args.next().ok_or("--volume needs a value")?;
+                    volume = value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|v| (0.0..=1.0).contains(v))
+                        .ok_or_else(|| {
+                            format!("Volume must be between 0.0 and 1.0, got '{}'", value)
+                        })?;
+                }
+                "--waveform" => {
+                    let value = args.next().ok_or("--waveform needs a value")?;
+                    waveform = audio::Waveform::parse(value)
+                        .ok_or_else(|| format!("Unknown waveform '{}'", value))?;
+                }
+                "--frequency" => {
+                    let value = args.next().ok_or("--frequency needs a value")?;
+                    frequency = value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|&f| f > 0.0)
+                        .ok_or_else(|| format!("Invalid frequency '{}'", value))?;
+                }
+                "--headless" => headless = true,
+                "--deterministic" => deterministic = true,
+                "--quiet" => verbosity = Verbosity::Quiet,
+                "--verbose" => verbosity = Verbosity::Verbose,
+                "--terminal" => terminal = true,
+                "--print-regs" => print_regs = true,
+                "--strict" => unknown_opcode_mode = UnknownOpcodeMode::Halt,
+                "--trap-sys" => sys_call_mode = SysCallMode::Trap,
+                "--chip8x" => chip8x = true,
+                "--vip-timing" => timing = TimingModel::Vip,
+                "--index-mode" => {
+                    let value = args.next().ok_or("--index-mode needs a mode")?;
+                    index_mode = match value.as_str() {
+                        "full" => IndexMode::Full,
+                        "wrap" => IndexMode::Wrap,
+                        "clamp" => IndexMode::Clamp,
+                        _ => {
+                            return Err(format!(
+                                "Invalid index mode '{}', expected full, wrap or clamp",
+                                value
+                            ));
+                        }
+                    };
+                }
+                "--raw-keys" => latch_keys = false,
+                "--warn-smc" => warn_self_modifying = true,
+                "--warn-unaligned" => warn_unaligned = true,
+                "--warn-low-pc" => warn_low_pc = true,
+                "--smooth-delay" => smooth_delay_timer = true,
+                "--skip-idle" => skip_idle = true,
+                "--threaded" => threaded = true,
+                "--watch" => watch = true,
+                "--exact-beep" => exact_beep = true,
+                "--profile" => profile = true,
+                "--coverage" => coverage = true,
+                "--frame-histogram" => frame_histogram = true,
+                "--scanlines" => scanlines = true,
+                "--grid" => grid = true,
+                "--grid-color" => {
+                    let value = args.next().ok_or("--grid-color needs a color")?;
+                    grid_color = u32::from_str_radix(value.trim_start_matches('#'), 16)
+                        .ok()
+                        .filter(|&color| color <= 0xFFFFFF)
+                        .ok_or_else(|| format!("Invalid grid color '{}'", value))?;
+                }
+                "--grid-opacity" => {
+                    let value = args.next().ok_or("--grid-opacity needs a value")?;
+                    grid_opacity = value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|v| (0.0..=1.0).contains(v))
+                        .ok_or_else(|| {
+                            format!("Grid opacity must be between 0.0 and 1.0, got '{}'", value)
+                        })?;
+                }
+                "--smooth-scaling" => smooth_scaling = true,
+                "--persistence" => persistence = true,
+                "--invert" => {
+                    invert = true;
+                    rom_settings.invert = Some(true);
+                }
+                "--no-rpl-save" => persist_rpl = false,
+                "--no-rom-settings" => persist_rom_settings = false,
+                "--clip-sprites" => {
+                    quirks.vertical_wrap = false;
+                    quirks_from_args = true;
+                }
+                "--wrap-sprites" => {
+                    quirks.horizontal_wrap = true;
+                    quirks_from_args = true;
+                }
+                "--shift-vy" => {
+                    quirks.shift_uses_vy = true;
+                    quirks_from_args = true;
+                }
+                "--vf-first" => {
+                    quirks.vf_written_first = true;
+                    quirks_from_args = true;
+                }
+                "--vf-reset" => {
+                    quirks.vf_reset = true;
+                    quirks_from_args = true;
+                }
+                "--increment-i" => {
+                    quirks.load_store_increments_i = true;
+                    quirks_from_args = true;
+                }
+                "--display-wait" => {
+                    quirks.display_wait = true;
+                    quirks_from_args = true;
+                }
+                "--collision-rows" => {
+                    quirks.collision_row_count = true;
+                    quirks_from_args = true;
+                }
+                "--half-scroll" => {
+                    quirks.half_lores_scroll = true;
+                    quirks_from_args = true;
+                }
+                "--compat-db" => {
+                    let value = args.next().ok_or("--compat-db needs a file")?;
+                    compat_db = Some(value.clone());
+                }
+                "--palette" => {
+                    let value = args.next().ok_or("--palette needs four colors")?;
+                    palette = parse_palette(value)
+                        .ok_or_else(|| format!("Invalid palette '{}'", value))?;
+                    rom_settings.palette = Some(palette);
+                }
+                "--input-profile" => {
+                    let value = args.next().ok_or("--input-profile needs a name")?;
+                    input_profile = Some(
+                        inputprofile::find(value)
+                            .ok_or_else(|| format!("Unknown input profile '{}'", value))?,
+                    );
+                    rom_settings.input_profile = Some(value.clone());
+                }
+                "--record-gif" => {
+                    let value = args.next().ok_or("--record-gif needs an output file")?;
+                    record_gif = Some(value.clone());
+                }
+                "--log-writes" => {
+                    let value = args.next().ok_or("--log-writes needs an output file")?;
+                    log_writes = Some(value.clone());
+                }
+                "--svg" => {
+                    let value = args.next().ok_or("--svg needs an output file")?;
+                    svg_on_exit = Some(value.clone());
+                }
+                "--dump-on-exit" => {
+                    let value = args.next().ok_or("--dump-on-exit needs an output file")?;
+                    dump_on_exit = Some(value.clone());
+                }
+                "--display-png" => {
+                    let value = args.next().ok_or("--display-png needs a PNG file")?;
+                    display_png = Some(value.clone());
+                }
+                "--fixture" => {
+                    let value = args.next().ok_or("--fixture needs a file")?;
+                    fixture = Some(value.clone());
+                }
+                "--gif-fps" => {
+                    let value = args.next().ok_or("--gif-fps needs a value")?;
+                    gif_fps = value
+                        .parse()
+                        .ok()
+                        .filter(|fps| (1..=30).contains(fps))
+                        .ok_or_else(|| {
+                            format!("GIF frame rate must be from 1 to 30, got '{}'", value)
+                        })?;
+                }
+                "--gif-scale" => {
+                    let value = args.next().ok_or("--gif-scale needs a value")?;
+                    gif_scale = value
+                        .parse()
+                        .ok()
+                        .filter(|scale| (1..=16).contains(scale))
+                        .ok_or_else(|| {
+                            format!("GIF scale must be from 1 to 16, got '{}'", value)
+                        })?;
+                }
+                "--gif-max-seconds" => {
+                    let value = args.next().ok_or("--gif-max-seconds needs a value")?;
+                    gif_max_seconds = value
+                        .parse()
+                        .ok()
+                        .filter(|&seconds| seconds > 0)
+                        .ok_or_else(|| format!("Invalid GIF length '{}'", value))?;
+                }
+                "--debug" => debug = true,
+                "--break-at-start" => {
+                    break_at_start = true;
+                    debug = true;
+                }
+                "--break" => {
+                    let value = args.next().ok_or("--break needs an address")?;
+                    breakpoints.push(
+                        parse_address(value)
+                            .ok_or_else(|| format!("Invalid breakpoint address '{}'", value))?,
+                    );
+                    debug = true;
+                }
+                "--break-if" => {
+                    let value = args.next().ok_or("--break-if needs a condition")?;
+                    break_conditions.push(value.clone());
+                    debug = true;
+                }
+                "--break-cycle" => {
+                    let value = args.next().ok_or("--break-cycle needs a cycle count")?;
+                    break_cycles.push(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid cycle count '{}'", value))?,
+                    );
+                    debug = true;
+                }
+                "--hz" => {
+                    let value = args.next().ok_or("--hz needs a value")?;
+                    hz = value
+                        .parse()
+                        .ok()
+                        .filter(|&hz| hz > 0)
+                        .ok_or_else(|| format!("Invalid CPU speed '{}'", value))?;
+                    rom_settings.hz = Some(hz);
+                }
+                "--speed" => {
+                    let value = args.next().ok_or("--speed needs a value")?;
+                    speed = value
+                        .parse()
+                        .ok()
+                        .filter(|speed| (0.01..=10.0).contains(speed))
+                        .ok_or_else(|| format!("Invalid speed '{}', expected 0.01 to 10", value))?;
+                }
+                "--input" => {
+                    let value = args.next().ok_or("--input needs a file")?;
+                    input = Some(value.clone());
+                }
+                "--compare-trace" => {
+                    let value = args.next().ok_or("--compare-trace needs a file")?;
+                    compare_trace = Some(value.clone());
+                }
+                "--snapshot-on-draw" => {
+                    let value = args.next().ok_or("--snapshot-on-draw needs a directory")?;
+                    snapshot_on_draw = Some(value.clone());
+                }
+                "--frames" => {
+                    let value = args.next().ok_or("--frames needs a value")?;
+                    frames = value
+                        .parse()
+                        .map_err(|_| format!("Invalid frame count '{}'", value))?;
+                }
+                "--duration" => {
+                    let value = args.next().ok_or("--duration needs a time")?;
+                    duration = Some(
+                        parse_duration(value)
+                            .ok_or_else(|| format!("Invalid duration '{}'", value))?,
+                    );
+                }
+                "--expect-hash" => {
+                    let value = args.next().ok_or("--expect-hash needs a value")?;
+                    let hex = value.strip_prefix("0x").unwrap_or(value);
+                    expect_hash = Some(
+                        u64::from_str_radix(hex, 16)
+                            .map_err(|_| format!("Invalid hash '{}'", value))?,
+                    );
+                }
+                "--max-cycles" => {
+                    let value = args.next().ok_or("--max-cycles needs a value")?;
+                    max_cycles = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid instruction limit '{}'", value))?,
+                    );
+                }
+                "--watchdog" => {
+                    let value = args.next().ok_or("--watchdog needs a number of seconds")?;
+                    watchdog = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|&seconds| seconds > 0)
+                            .ok_or_else(|| format!("Invalid watchdog timeout '{}'", value))?,
+                    );
                 }
+                "--key-polls" => {
+                    let value = args.next().ok_or("--key-polls needs a number")?;
+                    key_polls = value
+                        .parse()
+                        .ok()
+                        .filter(|polls| (1..=MAX_KEY_POLLS).contains(polls))
+                        .ok_or_else(|| {
+                            format!(
+                                "Invalid key poll count '{}', expected 1 to {}",
+                                value, MAX_KEY_POLLS
+                            )
+                        })?;
+                }
+                "--memory" => {
+                    let value = args.next().ok_or("--memory needs a size in bytes")?;
+                    memory_size = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&size| size.is_power_of_two() && (0x400..=0x10000).contains(&size))
+                        .ok_or_else(|| {
+                            format!(
+                                "Memory size must be a power of two from 1024 to 65536, got '{}'",
+                                value
+                            )
+                        })?;
+                }
+                "--start" => {
+                    let value = args.next().ok_or("--start needs an address")?;
+                    start_address = parse_address(value)
+                        .ok_or_else(|| format!("Invalid start address '{}'", value))?;
+                }
+                "--seed" => {
+                    let value = args.next().ok_or("--seed needs a value")?;
+                    seed = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid seed '{}'", value))?,
+                    );
+                }
+                _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'", arg)),
+                _ => playlist.push(arg.clone()),
+            }
+        }
+
+        // Nothing that depends on the host: headless runs count timers in
+        // frames and have no audio, the RNG is seeded, and nothing saved by
+        // earlier runs is read back. Input comes only from --input.
+        if deterministic {
+            if terminal {
+                return Err("--deterministic runs headless, not in the terminal".to_string());
+            }
+            if duration.is_some() {
+                return Err(
+                    "--duration runs in real time, so it can't be --deterministic".to_string(),
+                );
+            }
+            headless = true;
+            seed = seed.or(Some(DETERMINISTIC_SEED));
+            persist_rpl = false;
+            persist_rom_settings = false;
+        }
+
+        #[cfg(feature = "embed")]
+        if playlist.is_empty() {
+            playlist.push(EMBEDDED_ROM_PATH.to_string());
+        }
+        let rom_path = playlist.first().cloned().ok_or("Missing ROM file")?;
+        if playlist.len() > 1 {
+            if playlist.iter().any(|path| path == "-") {
+                return Err("stdin can only be read once, so - can't be in a playlist".to_string());
+            }
+            if headless || terminal {
+                return Err("Switching between ROMs only works in the window".to_string());
             }
-            print!("\n");
         }
+        if rom_path == "-" && debug {
+            return Err(
+                "The debugger reads commands from stdin, so the ROM can't come from there"
+                    .to_string(),
+            );
+        }
+        if input.is_some() && !headless {
+            return Err("--input only works with --headless".to_string());
+        }
+        if compare_trace.is_some() && !headless {
+            return Err("--compare-trace only works with --headless".to_string());
+        }
+        if snapshot_on_draw.is_some() && !headless {
+            return Err("--snapshot-on-draw only works with --headless".to_string());
+        }
+        if duration.is_some() && !headless {
+            return Err("--duration only works with --headless".to_string());
+        }
+        if print_regs && !headless {
+            return Err("--print-regs only works with --headless".to_string());
+        }
+        if terminal && (headless || debug) {
+            return Err("--terminal can't be combined with --headless or the debugger".to_string());
+        }
+        if key_polls > 1 && (headless || terminal) {
+            return Err("--key-polls only works in the window".to_string());
+        }
+        if threaded && (headless || terminal) {
+            return Err("--threaded only works in the window".to_string());
+        }
+        if watch && (headless || terminal) {
+            return Err("--watch only works in the window".to_string());
+        }
+        if exact_beep && (headless || terminal) {
+            return Err("--exact-beep only works in the window".to_string());
+        }
+        if watch && playlist.iter().any(|path| path == "-") {
+            return Err("--watch needs a ROM file to watch, not stdin".to_string());
+        }
+        if threaded && debug {
+            return Err("The debugger steps the CPU itself, so it can't be --threaded".to_string());
+        }
+        if start_address as usize >= memory_size {
+            return Err(format!(
+                "Start address {:#05X} is outside {} bytes of memory",
+                start_address, memory_size
+            ));
+        }
+
+        if quirks_from_args {
+            rom_settings.quirks = Some(quirks);
+        }
+
+        Ok(Config {
+            rom_path,
+            verbosity,
+            playlist,
+            pause_on_focus_loss,
+            screen_size,
+            volume,
+            waveform,
+            frequency,
+            headless,
+            frames,
+            duration,
+            input,
+            compare_trace,
+            snapshot_on_draw,
+            hz,
+            speed,
+            terminal,
+            expect_hash,
+            print_regs,
+            max_cycles,
+            watchdog,
+            key_polls,
+            memory_size,
+            start_address,
+            seed,
+            unknown_opcode_mode,
+            sys_call_mode,
+            chip8x,
+            index_mode,
+            timing,
+            latch_keys,
+            quirks,
+            quirks_from_args,
+            compat_db,
+            warn_self_modifying,
+            warn_unaligned,
+            warn_low_pc,
+            smooth_delay_timer,
+            persist_rpl,
+            rom_settings,
+            persist_rom_settings,
+            skip_idle,
+            threaded,
+            watch,
+            exact_beep,
+            profile,
+            coverage,
+            frame_histogram,
+            scanlines,
+            grid,
+            grid_color,
+            grid_opacity,
+            smooth_scaling,
+            invert,
+            persistence,
+            palette,
+            input_profile,
+            record_gif,
+            log_writes,
+            dump_on_exit,
+            svg_on_exit,
+            display_png,
+            fixture,
+            gif_fps,
+            gif_scale,
+            gif_max_seconds,
+            debug,
+            breakpoints,
+            break_conditions,
+            break_cycles,
+            break_at_start,
+        })
     }
+}
+
+// Four comma separated hex colors, e.g. 000000,FFFFFF,AA4400,FFAA00
+fn parse_palette(value: &str) -> Option<[u32; 4]> {
+    let colors = value
+        .split(',')
+        .map(|color| u32::from_str_radix(color.trim().trim_start_matches('#'), 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+    colors.try_into().ok()
+}
 
-    fn set_keys(&mut self, window: &Window) {
-        // Map keyboard keys to Chip-8 keys
-        // Original Chip-8 keyboard layout:
-        // 1 2 3 C
-        // 4 5 6 D
-        // 7 8 9 E
-        // A 0 B F
-        //
-        // Mapped to normal keyboard:
-        // 1 2 3 4
-        // Q W E R
-        // A S D F
-        // Z X C V
+// "2s", "500ms" or plain seconds like "1.5"
+fn parse_duration(value: &str) -> Option<Duration> {
+    let (number, unit) = if let Some(millis) = value.strip_suffix("ms") {
+        (millis, 0.001)
+    } else {
+        (value.strip_suffix('s').unwrap_or(value), 1.0)
+    };
+    let seconds = number.parse::<f64>().ok()? * unit;
+    (seconds > 0.0 && seconds.is_finite()).then(|| Duration::from_secs_f64(seconds))
+}
+
+fn parse_size(value: &str) -> Option<(usize, usize)> {
+    let (width, height) = value.split_once('x')?;
+    let width = width.parse().ok().filter(|&w| w > 0)?;
+    let height = height.parse().ok().filter(|&h| h > 0)?;
+    Some((width, height))
+}
+
+fn print_usage(program: &str) {
+    eprintln!("Usage: {} [options] <rom_file>...", program);
+    eprintln!(
+        "       {} asm <source.asm> [-o <out.ch8>] [--symbols <out.sym>]",
+        program
+    );
+    eprintln!(
+        "       {} disasm <rom_file> [--start <address>] [--symbols <file.sym>] [-o <out.asm>] [--flow]",
+        program
+    );
+    eprintln!("       {} diff <a.state> <b.state>", program);
+    eprintln!("       {} info <rom_file> [--start <address>]", program);
+    eprintln!("       {} opcodes [--json]", program);
+    eprintln!("       {} --self-test", program);
+    eprintln!("Example: {} roms/pong.ch8", program);
+    eprintln!("Use - as <rom_file> to read the ROM from stdin");
+    eprintln!("Give a directory as <rom_file> to pick one of its .ch8 ROMs from a menu");
+    eprintln!("With several ROMs, Page Up/Page Down switch between them");
+    eprintln!("Gzipped ROMs are unpacked when built with the gzip feature");
+    eprintln!("Built with the embed feature, the embedded ROM runs when none is given");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("  --quiet         Print nothing while starting up");
+    eprintln!("  --verbose       Print every setting while starting up");
+    eprintln!("  --background    Keep running while the window is not focused");
+    eprintln!("  --screen WxH    Size used for fullscreen (default: 1920x1080)");
+    eprintln!("  --volume V      Beep volume from 0.0 to 1.0 (default: 0.25)");
+    eprintln!("  --waveform W    Beep waveform: square, sine or triangle (default: square)");
+    eprintln!("  --frequency HZ  Beep pitch in Hz (default: 440)");
+    eprintln!("  --headless      Run without a window and print the framebuffer hash");
+    eprintln!(
+        "  --terminal      Draw in the terminal instead of a window (needs the terminal feature)"
+    );
+    eprintln!("  --hz N          CPU speed in instructions per second (default: 660)");
+    eprintln!("  --speed F       Run everything F times as fast, e.g. 0.1 for slow motion");
+    eprintln!("  --input FILE    Keypad events for headless mode, '<frame> <key> down|up' lines");
+    eprintln!("  --deterministic Headless with a fixed seed (unless --seed) and no saved RPL");
+    eprintln!("                  flags or ROM settings, so the same ROM and --input give the");
+    eprintln!("                  same framebuffer hash on any machine");
+    eprintln!("  --compare-trace FILE");
+    eprintln!("                  Stop a --headless run where it stops matching FILE, an");
+    eprintln!("                  instruction trace from another emulator ('<pc> <opcode>' lines)");
+    eprintln!("  --snapshot-on-draw DIR");
+    eprintln!("                  Write the state after every DXYN of a --headless run to DIR,");
+    eprintln!("                  numbered files for `diff` (large, for short runs)");
+    eprintln!("  --frames N      Frames to run in headless mode (default: 600)");
+    eprintln!("  --duration T    Run headless for T of real time (e.g. 2s, 500ms) instead of");
+    eprintln!("                  --frames, with the timers on the real 60Hz clock");
+    eprintln!("  --expect-hash H Exit with an error unless the final hash is H (hex)");
+    eprintln!("  --max-cycles N  Stop with a fault after N instructions");
+    eprintln!("  --print-regs    Print the final registers, PC and I after a --headless run");
+    eprintln!("  --watchdog SECS Report a display that hasn't changed for SECS seconds,");
+    eprintln!("                  and stop a --headless run");
+    eprintln!("  --key-polls N   Read the keys N times a frame for less input lag (default: 1)");
+    eprintln!("  --memory BYTES  Memory size, a power of two up to 65536 (default: 4096)");
+    eprintln!("  --start ADDR    Load and start the program at ADDR (default: 0x200)");
+    eprintln!("  --seed N        Seed the random number generator for reproducible runs");
+    eprintln!("  --strict        Stop on the first unknown opcode instead of skipping it");
+    eprintln!("  --index-mode M  What I does past the end of memory: full (any 16-bit value,");
+    eprintln!("                  the default), wrap (to the memory size) or clamp");
+    eprintln!("  --vip-timing    Charge instructions roughly what they took on the COSMAC VIP,");
+    eprintln!("                  so --hz counts cycles and drawing costs more than arithmetic");
+    eprintln!("  --trap-sys      Stop on 0NNN machine code calls instead of ignoring them");
+    eprintln!("  --chip8x        Chip-8X color opcodes 02A0, 5XY1, BXY0 and BXYN (no BNNN);");
+    eprintln!("                  its programs usually need --start 0x300");
+    eprintln!("  --raw-keys      Don't hold short key taps until the end of the frame");
+    eprintln!("  --warn-smc      Warn when a ROM writes over the code it's running");
+    eprintln!("  --warn-unaligned");
+    eprintln!("                  Warn when the PC lands on an odd address");
+    eprintln!("  --warn-low-pc   Warn when the PC goes below 0x200 into the font/interpreter area");
+    eprintln!("  --smooth-delay  Count the delay timer down between instructions instead of");
+    eprintln!("                  once a frame, finer but not how the original hardware did it");
+    eprintln!("  --no-rpl-save   Don't keep SCHIP RPL flags (high scores) between runs");
+    eprintln!("  --no-rom-settings");
+    eprintln!(
+        "                  Don't use or remember per-ROM speed, quirks, colors and input profile"
+    );
+    eprintln!("  --skip-idle     Save host CPU by not running delay timer wait loops");
+    eprintln!("  --threaded      Run the CPU on its own thread, smoother at very high speeds");
+    eprintln!("  --watch         Start the ROM over whenever its file changes, for development");
+    eprintln!("  --exact-beep    Only beep for a sound timer still running at the end of a frame,");
+    eprintln!("                  leaving out clicks shorter than a frame");
+    eprintln!("  --profile       Print how often each opcode family ran on exit");
+    eprintln!("  --coverage      Print which opcode families ran and which never did on exit");
+    eprintln!("  --frame-histogram");
+    eprintln!("                  Print how many instructions ran per frame on exit, showing");
+    eprintln!("                  frames lost to display and key waits");
+    eprintln!("  --scanlines     Darken every other line of the window like a CRT");
+    eprintln!("  --grid          Draw faint lines between the Chip-8 pixels");
+    eprintln!("  --grid-color C  Hex color of the grid lines (default: 808080)");
+    eprintln!("  --grid-opacity F");
+    eprintln!("                  How strongly the grid lines show, 0.0 to 1.0 (default: 0.25)");
+    eprintln!("  --smooth-scaling");
+    eprintln!("                  Blend pixels when scaling up instead of keeping them sharp");
+    eprintln!("  --invert        Draw dark pixels on a light background");
+    eprintln!("  --persistence   Keep pixels lit for an extra frame, hiding sprite redraw flicker");
+    eprintln!("  --clip-sprites  Clip sprites at the bottom of the screen instead of wrapping");
+    eprintln!("  --increment-i   FX55/FX65 leave I past the last register, as on the COSMAC VIP");
+    eprintln!("  --wrap-sprites  Wrap sprites at the right edge of the screen instead of clipping");
+    eprintln!("  --shift-vy      8XY6/8XYE shift VY into VX, like the COSMAC VIP");
+    eprintln!("  --vf-first      With VF as the target of 8XYN, keep the result over the flag");
+    eprintln!("  --vf-reset      8XY1/8XY2/8XY3 set VF to 0, as on the COSMAC VIP");
+    eprintln!("  --display-wait  DXYN waits for the next frame, as on the COSMAC VIP");
+    eprintln!("  --collision-rows");
+    eprintln!("                  DXYN sets VF to the number of sprite rows that collided");
+    eprintln!("  --half-scroll   Scroll half as far in lores, like SCHIP 1.1");
+    eprintln!("  --compat-db FILE");
+    eprintln!("                  Pick quirks for known ROMs from a JSON database");
+    eprintln!("  --palette C,C,C,C");
+    eprintln!("                  Hex colors for off, plane 1, plane 2 and both planes");
+    eprintln!("  --input-profile NAME");
+    eprintln!("                  Move the window's keys around for a game, remembered per ROM:");
+    for profile in &inputprofile::PROFILES {
+        eprintln!(
+            "                    {}: {}",
+            profile.name, profile.description
+        );
+    }
+    eprintln!("  --log-writes FILE");
+    eprintln!("                  Write every memory write (pc, address, value) to FILE");
+    eprintln!("  --dump-on-exit FILE");
+    eprintln!("                  Write the final machine state to FILE as JSON");
+    eprintln!("  --svg FILE      Write the final display to FILE as an SVG picture");
+    eprintln!("                  (needs the svg feature)");
+    eprintln!("  --display-png FILE");
+    eprintln!("                  Start with a 64x32 picture on the display, light pixels lit");
+    eprintln!(
+        "  --fixture FILE  Set registers, I, PC, timers and memory from FILE before starting,"
+    );
+    eprintln!("                  'V3 = 0x07' or '0x300 = F0 90 F0' lines");
+    eprintln!("  --record-gif FILE");
+    eprintln!("                  Record the display to an animated GIF (needs the gif feature)");
+    eprintln!("  --gif-fps N     GIF frame rate from 1 to 30 (default: 30)");
+    eprintln!("  --gif-scale N   GIF pixels per CHIP-8 pixel (default: 4)");
+    eprintln!("  --gif-max-seconds N");
+    eprintln!("                  Stop recording after N seconds of captured frames (default: 60)");
+    eprintln!("  --debug         Enable the terminal debugger (F5 breaks into it)");
+    eprintln!("  --break ADDR    Break into the debugger when PC reaches ADDR");
+    eprintln!("  --break-at-start");
+    eprintln!("                  Break into the debugger before the first instruction");
+    eprintln!("  --break-cycle N Break into the debugger once N instructions have run");
+    eprintln!("  --break-if COND Break when a register condition becomes true, e.g. \"VF != 0\"");
+    eprintln!();
+    eprintln!("Keys:");
+    eprintln!("  F11             Toggle fullscreen");
+    eprintln!("  F2              Print the registers without pausing");
+    eprintln!("  F1              Print the display as a #/. grid");
+    eprintln!("  F3              Toggle FPS (top line) / IPS (bottom line) overlay");
+    eprintln!("  M               Toggle mute");
+    eprintln!("  F9              Pause/resume GIF recording");
+    eprintln!("  F5              Break into the debugger (with --debug)");
+    eprintln!("  F4              Show the ROM name and speed");
+    eprintln!("  K               Show which Chip-8 keys are held down");
+    eprintln!("  PgUp/PgDn       Previous/next ROM from the command line");
+    eprintln!("  F6              Outline the screen and tint pixels drawn by wrapping sprites");
+    eprintln!("  F7              Toggle the CRT scanline effect");
+    eprintln!("  G               Toggle the pixel grid");
+    eprintln!("  F12             Toggle smooth scaling");
+    eprintln!("  F8              Cycle through the color palette presets");
+    eprintln!("  P               Cycle through the quirk presets (chip8, schip, xochip)");
+    eprintln!("  F10             Invert the display");
+    eprintln!("  Insert          Poke memory: type \"ADDR VALUE\" in hex, Enter to write");
+    eprintln!();
+    eprintln!("Exit codes:");
+    eprintln!("  1               Bad arguments or unreadable/oversized ROM");
+    eprintln!("  2               The emulator hit an unrecoverable fault");
+    eprintln!("  3               Framebuffer hash didn't match --expect-hash");
+    eprintln!("  4               The --watchdog fired in a --headless run");
+    eprintln!("  5               The run diverged from the --compare-trace reference");
+}
 
-        self.keys[0x1] = window.is_key_down(Key::Key1);
-        self.keys[0x2] = window.is_key_down(Key::Key2);
-        self.keys[0x3] = window.is_key_down(Key::Key3);
-        self.keys[0xC] = window.is_key_down(Key::Key4);
+const EXIT_FAULT: u8 = 2;
+const EXIT_HASH_MISMATCH: u8 = 3;
+const EXIT_WATCHDOG: u8 = 4;
+const EXIT_TRACE_MISMATCH: u8 = 5;
 
-        self.keys[0x4] = window.is_key_down(Key::Q);
-        self.keys[0x5] = window.is_key_down(Key::W);
-        self.keys[0x6] = window.is_key_down(Key::E);
-        self.keys[0xD] = window.is_key_down(Key::R);
+// RNG seed for --deterministic when no --seed is given
+const DETERMINISTIC_SEED: u64 = 0;
 
-        self.keys[0x7] = window.is_key_down(Key::A);
-        self.keys[0x8] = window.is_key_down(Key::S);
-        self.keys[0x9] = window.is_key_down(Key::D);
-        self.keys[0xE] = window.is_key_down(Key::F);
+// Highlight for the F6 wrap overlay
+const WRAP_TINT: u32 = 0xFF00FF;
+const DEFAULT_GRID_COLOR: u32 = 0x808080;
+const DEFAULT_GRID_OPACITY: f32 = 0.25;
 
-        self.keys[0xA] = window.is_key_down(Key::Z);
-        self.keys[0x0] = window.is_key_down(Key::X);
-        self.keys[0xB] = window.is_key_down(Key::C);
-        self.keys[0xF] = window.is_key_down(Key::V);
+// How long a --duration run sleeps between batches of instructions
+const REAL_TIME_STEP: Duration = Duration::from_millis(1);
+
+// Upper limit for --key-polls, beyond it the window loop is mostly sleeping
+const MAX_KEY_POLLS: u32 = 8;
+
+// The window loop runs `key_polls` times per 60Hz frame
+fn open_window(width: usize, height: usize, fullscreen: bool, key_polls: u32) -> Window {
+    let mut window = Window::new(
+        "Chip-8 Emulator",
+        width,
+        height,
+        WindowOptions {
+            resize: !fullscreen,
+            borderless: fullscreen,
+            title: !fullscreen,
+            topmost: fullscreen,
+            ..WindowOptions::default()
+        },
+    )
+    .expect("Failed to create window");
+
+    if fullscreen {
+        window.set_position(0, 0);
     }
+    window.set_target_fps(60 * key_polls as usize);
+    window
+}
+
+// `chip8 asm <source> [-o <output>] [--symbols <symbol file>]`
+fn run_assembler(args: &[String]) {
+    let mut source_path = None;
+    let mut output_path = None;
+    let mut symbols_path = None;
 
-    fn load_program(&mut self, program: &[u8]) {
-        for (i, &byte) in program.iter().enumerate() {
-            self.memory[0x200 + i] = byte;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output_path = args.next().cloned(),
+            "--symbols" => symbols_path = args.next().cloned(),
+            _ => source_path = Some(arg.clone()),
         }
     }
 
-    fn fetch(&self) -> u16 {
-        let high_byte = self.memory[self.pc as usize] as u16;
-        let low_byte = self.memory[(self.pc + 1) as usize] as u16;
-        (high_byte << 8) | low_byte
+    let Some(source_path) = source_path else {
+        eprintln!("Usage: chip8 asm <source.asm> [-o <out.ch8>] [--symbols <out.sym>]");
+        std::process::exit(1);
+    };
+    let output_path = output_path.unwrap_or_else(|| {
+        std::path::Path::new(&source_path)
+            .with_extension("ch8")
+            .to_string_lossy()
+            .into_owned()
+    });
+
+    let source = fs::read_to_string(&source_path).unwrap_or_else(|e| {
+        eprintln!("Failed to read source file '{}' : {}", source_path, e);
+        std::process::exit(1);
+    });
+
+    let (program, symbols, warnings) = asm::assemble_with_warnings(&source).unwrap_or_else(|e| {
+        eprintln!("{}: {}", source_path, e);
+        std::process::exit(1);
+    });
+    for warning in warnings {
+        eprintln!("{}: warning: {}", source_path, warning);
     }
 
-    fn execute(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
-        let n = (opcode & 0x000F) as u8;
-        let nn = (opcode & 0x00FF) as u8;
-        let nnn = opcode & 0x0FFF;
+    fs::write(&output_path, &program).unwrap_or_else(|e| {
+        eprintln!("Failed to write '{}' : {}", output_path, e);
+        std::process::exit(1);
+    });
+    println!("Wrote {} bytes to {}", program.len(), output_path);
 
-        match opcode & 0xF000 {
-            0x0000 => match opcode {
-                0x00E0 => {
-                    // 00E0 Clear display
-                    self.display = [0; 256];
-                }
-                0x00EE => {
-                    // 00EE: Return from subroutine
-                    self.sp -= 1;
-                    self.pc = self.stack[self.sp];
-                }
-                _ => println!("Unknown 0x0... opcode: {:#06X}", opcode),
-            },
+    if let Some(symbols_path) = symbols_path {
+        fs::write(&symbols_path, asm::format_symbols(&symbols)).unwrap_or_else(|e| {
+            eprintln!("Failed to write '{}' : {}", symbols_path, e);
+            std::process::exit(1);
+        });
+        println!("Wrote {} symbols to {}", symbols.len(), symbols_path);
+    }
+}
 
-            0x1000 => {
-                // 1NNN jumps to address NNN
-                // println!("Jump to PC{:#05X}", nnn);
-                self.pc = nnn - 2;
-            }
-            0x2000 => {
-                // 2NNN: Call subroutine at NNN
-                self.stack[self.sp] = self.pc;
-                self.sp += 1;
-                self.pc = nnn - 2;
-            }
+// Parses an address written as 0x200, #200 or 512
+fn parse_address(value: &str) -> Option<u16> {
+    if let Some(hex) = value.strip_prefix("0x").or_else(|| value.strip_prefix('#')) {
+        u16::from_str_radix(hex, 16).ok()
+    } else {
+        value.parse().ok()
+    }
+}
 
-            0x3000 => {
-                // 3XNN Skips the next instruction if VX equals NN
-                if self.registers[x] == nn {
-                    // println!("Skipping next instruction");
-                    self.pc += 2;
-                } else {
-                    // println!("Continuing next instruction");
-                }
-            }
+// `chip8 disasm <rom> [--start <address>] [--symbols <symbol file>] [-o <out.asm>]`
+fn run_disassembler(args: &[String]) {
+    let mut rom_path = None;
+    let mut symbols_path = None;
+    // Assembler source instead of the listing on stdout
+    let mut output_path = None;
+    // ETI-660 programs start at 0x600
+    let mut start = 0x200;
+    // Follow the control flow to tell code from data
+    let mut flow = false;
 
-            0x4000 => {
-                // 4XNN Skips the next instruction of VX does NOT equal NN
-                if self.registers[x] != nn {
-                    // println!("Skipping next instruction");
-                    self.pc += 2;
-                } else {
-                    // println!("Continuing next instruction");
-                }
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--start" => {
+                let value = args.next().map(String::as_str).unwrap_or("");
+                start = parse_address(value).unwrap_or_else(|| {
+                    eprintln!("Invalid start address '{}'", value);
+                    std::process::exit(1);
+                });
             }
+            "--symbols" => symbols_path = args.next().cloned(),
+            "-o" => output_path = args.next().cloned(),
+            "--flow" => flow = true,
+            _ => rom_path = Some(arg.clone()),
+        }
+    }
 
-            0x5000 => {
-                // 5XY0 Skips the next instruction of VX equals VY
-                if self.registers[x] == self.registers[y] {
-                    // println!("Skipping next instruction");
-                    self.pc += 2;
-                } else {
-                    // println!("Continuing next instruction");
-                }
-            }
+    let Some(rom_path) = rom_path else {
+        eprintln!(
+            "Usage: chip8 disasm <rom_file> [--start <address>] [--symbols <file.sym>] [-o <out.asm>] [--flow]"
+        );
+        std::process::exit(1);
+    };
 
-            0x6000 => {
-                // 6XNN: Set register VX to NN
-                // println!("Set V{:X} = {:#04X}", x, nn);
-                self.registers[x] = nn;
+    let rom = fs::read(&rom_path).unwrap_or_else(|e| {
+        eprintln!("Failed to read ROM file '{}' : {}", rom_path, e);
+        std::process::exit(1);
+    });
+
+    let symbols = match symbols_path {
+        Some(path) => fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| disasm::parse_symbols(&text))
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to read symbol file '{}' : {}", path, e);
+                std::process::exit(1);
+            }),
+        None => Default::default(),
+    };
+
+    let listing = if flow {
+        let (listing, unresolved) = disasm::disassemble_flow(&rom, start, &symbols);
+        for address in unresolved {
+            eprintln!(
+                "Computed jump at {:#05X} not followed, code only it reaches is shown as data",
+                address
+            );
+        }
+        listing
+    } else {
+        disasm::disassemble_with_symbols(&rom, start, &symbols)
+    };
+    if let Some(path) = output_path {
+        fs::write(&path, disasm::to_source(&listing)).unwrap_or_else(|e| {
+            eprintln!("Failed to write '{}' : {}", path, e);
+            std::process::exit(1);
+        });
+        println!("Wrote {} instructions to {}", listing.len(), path);
+        return;
+    }
+
+    for instruction in listing {
+        if let Some(label) = &instruction.label {
+            println!("{}:", label);
+        }
+        let raw = match instruction.opcode {
+            Some(opcode) => format!("{:04X}", opcode),
+            None => format!("{:02X}  ", rom[(instruction.address - start) as usize]),
+        };
+        println!(
+            "{:#05X}  {}  {}",
+            instruction.address, raw, instruction.mnemonic
+        );
+    }
+}
+
+// Interpreter extension an opcode belongs to and its family, for `info`.
+// Covers SCHIP and XO-CHIP opcodes the emulator doesn't run as well.
+fn extension_opcode(opcode: u16) -> Option<(&'static str, &'static str)> {
+    let family = match opcode {
+        0x00C0..=0x00CF => ("SCHIP", "00CN"),
+        0x00FB => ("SCHIP", "00FB"),
+        0x00FC => ("SCHIP", "00FC"),
+        0x00FD => ("SCHIP", "00FD"),
+        0x00FE => ("SCHIP", "00FE"),
+        0x00FF => ("SCHIP", "00FF"),
+        0x00D0..=0x00DF => ("XO-CHIP", "00DN"),
+        0xF000 => ("XO-CHIP", "F000"),
+        _ => match (opcode & 0xF00F, opcode & 0xF0FF) {
+            (0xD000, _) => ("SCHIP", "DXY0"),
+            (0x5002, _) => ("XO-CHIP", "5XY2"),
+            (0x5003, _) => ("XO-CHIP", "5XY3"),
+            (_, 0xF030) => ("SCHIP", "FX30"),
+            (_, 0xF075) => ("SCHIP", "FX75"),
+            (_, 0xF085) => ("SCHIP", "FX85"),
+            (_, 0xF001) => ("XO-CHIP", "FN01"),
+            (_, 0xF002) => ("XO-CHIP", "F002"),
+            (_, 0xF03A) => ("XO-CHIP", "FX3A"),
+            _ => return None,
+        },
+    };
+    Some(family)
+}
+
+// `chip8 info <rom> [--start <address>]`: size, entry point, the opcode
+// families found by a linear sweep, and which extensions they need. Sprite
+// data gets counted as opcodes too, so it's a hint rather than a verdict.
+fn run_info(args: &[String]) -> ExitCode {
+    let mut rom_path = None;
+    let mut start = 0x200;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--start" => {
+                let value = args.next().map(String::as_str).unwrap_or("");
+                let Some(address) = parse_address(value) else {
+                    eprintln!("Invalid start address '{}'", value);
+                    return ExitCode::FAILURE;
+                };
+                start = address;
             }
+            _ => rom_path = Some(arg.clone()),
+        }
+    }
+
+    let Some(rom_path) = rom_path else {
+        eprintln!("Usage: chip8 info <rom_file> [--start <address>]");
+        return ExitCode::FAILURE;
+    };
+    let rom = match read_rom(&rom_path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    println!("ROM: {}", rom_path);
+    println!("Size: {} bytes", rom.len());
+    println!(
+        "Loads at: {:#05X}-{:#05X}",
+        start,
+        start as usize + rom.len().max(1) - 1
+    );
+    println!("Entry point: {:#05X}", start);
+    if !rom.len().is_multiple_of(2) {
+        println!("Odd length, the last byte can't be a whole instruction");
+    }
+
+    let mut families = chip8::Profile::new();
+    let mut extensions: Vec<(&str, &str)> = Vec::new();
+    for instruction in disasm::disassemble(&rom, start) {
+        let Some(opcode) = instruction.opcode else {
+            continue;
+        };
+        families.record(opcode);
+        if let Some(extension) = extension_opcode(opcode)
+            && !extensions.contains(&extension)
+        {
+            extensions.push(extension);
+        }
+    }
 
-            0x7000 => {
-                // 7XNN: Add NN to register VX
-                // println!("Add {:#04X} to V{:X}", nn, x);
-                self.registers[x] = self.registers[x].wrapping_add(nn);
+    println!();
+    print!("{}", families.report());
+    println!();
+    if extensions.is_empty() {
+        println!("Only CHIP-8 opcodes");
+    }
+    for name in ["SCHIP", "XO-CHIP"] {
+        let mut found: Vec<&str> = extensions
+            .iter()
+            .filter(|&&(extension, _)| extension == name)
+            .map(|&(_, family)| family)
+            .collect();
+        if !found.is_empty() {
+            found.sort();
+            println!("{} opcodes: {}", name, found.join(", "));
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+// `chip8 opcodes [--json]`: the instruction set the emulator, assembler and
+// disassembler share, as a table or as JSON for documentation tools
+fn run_opcodes(args: &[String]) -> ExitCode {
+    match args {
+        [] => {
+            for info in &opcodes::OPCODES {
+                let syntax = format!("{} {}", info.mnemonic, operand_syntax(info.operands));
+                println!(
+                    "{}  {:<8} {:<18} {}",
+                    info.pattern,
+                    format!("{:?}", info.extension),
+                    syntax.trim_end(),
+                    info.description
+                );
             }
+            ExitCode::SUCCESS
+        }
+        [flag] if flag == "--json" => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&opcodes::OPCODES[..]).unwrap()
+            );
+            ExitCode::SUCCESS
+        }
+        _ => {
+            eprintln!("Usage: chip8 opcodes [--json]");
+            ExitCode::FAILURE
+        }
+    }
+}
 
-            0x8000 => {
-                // 8XY_: Register operations
-                match opcode & 0x000F {
-                    0x0000 => {
-                        // 8XY0: VX = VY
-                        self.registers[x] = self.registers[y];
-                    }
-                    0x0001 => {
-                        // 8XY1: Bitwise VX OR VY
-                        let result = self.registers[x] | self.registers[y];
-                        self.registers[x] = result;
-                    }
-                    0x0002 => {
-                        // 8XY2: Bitwise VX AND VY
-                        let result = self.registers[x] & self.registers[y];
-                        self.registers[x] = result;
-                    }
-                    0x0003 => {
-                        // 8XY3: Bitwise VX XOR VY
-                        let result = self.registers[x] ^ self.registers[y];
-                        self.registers[x] = result;
-                    }
-                    0x0004 => {
-                        // 8XY4: ADD VY to VX, set VF = carry
-                        // println!("V{:X} += V{:X}", x, y);
-                        let (result, overflow) =
-                            self.registers[x].overflowing_add(self.registers[y]);
-                        self.registers[x] = result;
-                        self.registers[0xF] = if overflow { 1 } else { 0 };
-                    }
-                    0x0005 => {
-                        // println!("V{:X} -= V{:X}", x, y);
-                        let (result, underflow) =
-                            self.registers[x].overflowing_sub(self.registers[y]);
-                        self.registers[x] = result;
-                        self.registers[0xF] = if underflow { 0 } else { 1 };
-                    }
-                    0x0006 => {
-                        // 8XY6: Shift VX right by 1, VF = least significant bit before
-                        // shift
-                        self.registers[0xF] = self.registers[x] & 0x1;
-                        self.registers[x] >>= 1;
-                    }
-                    0x0007 => {
-                        // 8XY7: Set VX = VY - VX, set VF = NOT borrow
-                        let (result, underflow) =
-                            self.registers[y].overflowing_sub(self.registers[x]);
-                        self.registers[x] = result;
-                        self.registers[0xF] = if underflow { 0 } else { 1 };
-                    }
-                    0x000E => {
-                        // 8XYE: Shift VX left by 1, VF = most significant bit before
-                        // shift
-                        self.registers[0xF] = (self.registers[x] & 0x80) >> 7;
-                        self.registers[x] <<= 1;
-                    }
+// Operands as the table in `chip8 opcodes` shows them, "VX, #NN"
+fn operand_syntax(operands: &[OperandFormat]) -> String {
+    operands
+        .iter()
+        .map(|&operand| match operand {
+            OperandFormat::Vx => "VX",
+            OperandFormat::Vy => "VY",
+            OperandFormat::Address => "#NNN",
+            OperandFormat::Byte => "#NN",
+            OperandFormat::Nibble => "N",
+            OperandFormat::XNibble => "X",
+            keyword => keyword.keyword().unwrap(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
 
-                    _ => println!("Unknown 8XY_ opcode: {:#06X}", opcode),
-                }
+// `chip8 diff <a.state> <b.state>`, exits with 1 if they differ
+fn run_diff(args: &[String]) -> ExitCode {
+    let [a_path, b_path] = args else {
+        eprintln!("Usage: chip8 diff <a.state> <b.state>");
+        return ExitCode::FAILURE;
+    };
+
+    let load = |path: &String| {
+        fs::read_to_string(path)
+            .map_err(Chip8Error::from)
+            .and_then(|json| Snapshot::from_json(&json))
+            .map_err(|e| eprintln!("Failed to read snapshot '{}' : {}", path, e))
+    };
+    let (Ok(a), Ok(b)) = (load(a_path), load(b_path)) else {
+        return ExitCode::FAILURE;
+    };
+
+    let differences = diff_states(&a, &b);
+    if differences.is_empty() {
+        println!("Snapshots are identical");
+        return ExitCode::SUCCESS;
+    }
+    for difference in &differences {
+        println!("{}", difference);
+    }
+    ExitCode::FAILURE
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+
+    match args.get(1).map(String::as_str) {
+        Some("asm") => {
+            run_assembler(&args[2..]);
+            return ExitCode::SUCCESS;
+        }
+        Some("disasm") => {
+            run_disassembler(&args[2..]);
+            return ExitCode::SUCCESS;
+        }
+        Some("diff") => return run_diff(&args[2..]),
+        Some("info") => return run_info(&args[2..]),
+        Some("opcodes") => return run_opcodes(&args[2..]),
+        Some("--self-test") => return selftest::run(),
+        _ => {}
+    }
+
+    let mut config = match Config::from_args(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}\n", e);
+            print_usage(&args[0]);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // A directory instead of a ROM: pick one of its ROMs from a menu, the
+    // others follow it in the playlist
+    if config.playlist.len() == 1 && Path::new(&config.rom_path).is_dir() {
+        if config.headless || config.terminal {
+            eprintln!("Picking a ROM from a directory only works in the window");
+            return ExitCode::FAILURE;
+        }
+        let mut roms = match launcher::rom_files(&config.rom_path) {
+            Ok(roms) => roms,
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
             }
+        };
+        let Some(index) = launcher::choose(&roms) else {
+            return ExitCode::SUCCESS;
+        };
+        roms.rotate_left(index);
+        config.rom_path = roms[0].clone();
+        config.playlist = roms;
+    }
 
-            0x9000 => {
-                // 9XY0 Skips next instruction of VX does NOT equal VY
-                if self.registers[x] != self.registers[y] {
-                    // println!("Skipping next instruction");
-                    self.pc += 2;
-                } else {
-                    // println!("Continuing next instruction");
-                }
+    // Settings remembered from earlier runs of this ROM, with anything on the
+    // command line this time replacing them. A ROM from stdin has no name.
+    let mut active_rom = activerom::ActiveRom::new(&config);
+    let config = config;
+    let rom_path = &config.rom_path;
+
+    if config.verbosity != Verbosity::Quiet {
+        println!("=== Chip-8 Emulator - Starting ===\n");
+    }
+
+    let mut chip8 = Chip8::with_config(Chip8Config {
+        memory_size: config.memory_size,
+        start_address: config.start_address,
+        seed: config.seed,
+        quirks: active_rom.settings().quirks,
+        unknown_opcode_mode: config.unknown_opcode_mode,
+        sys_call_mode: config.sys_call_mode,
+        chip8x: config.chip8x,
+        index_mode: config.index_mode,
+        timing: config.timing,
+        latch_keys: config.latch_keys,
+        warn_self_modifying: config.warn_self_modifying,
+        warn_unaligned: config.warn_unaligned,
+        warn_low_pc: config.warn_low_pc,
+        smooth_delay_timer: config.smooth_delay_timer,
+        // The instructions between 60Hz ticks, which `--smooth-delay` spreads
+        // the delay timer over
+        cycles_per_frame: (active_rom.settings().hz / 60).max(1),
+        palette: active_rom.settings().palette,
+        invert_display: active_rom.settings().invert,
+        persistence: config.persistence,
+        max_cycles: config.max_cycles,
+        ..Chip8Config::default()
+    });
+
+    // Read the ROM file, or stdin for `-`
+    let rom = match read_rom(rom_path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Some(path) = &config.compat_db
+        && !active_rom.own_quirks()
+    {
+        let database = match fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| compat::CompatDatabase::from_json(&json))
+        {
+            Ok(database) => database,
+            Err(e) => {
+                eprintln!("Failed to read compatibility database '{}' : {}", path, e);
+                return ExitCode::FAILURE;
             }
+        };
+        if let Some(entry) = database.lookup(&rom)
+            && config.verbosity != Verbosity::Quiet
+        {
+            println!(
+                "Using {} quirks for {}",
+                entry.preset,
+                entry.name.as_deref().unwrap_or("this ROM")
+            );
+        }
+        chip8.set_quirks(database.quirks_for(&rom));
+    }
+    // Load it into memory
+    if let Err(e) = chip8.load_program(&rom) {
+        eprintln!("Failed to load ROM file '{}' : {}", rom_path, e);
+        return ExitCode::FAILURE;
+    }
+    print_startup(&config, &active_rom, &chip8, rom.len());
+    if let Some(path) = &config.display_png {
+        let result = picture::load_png(path).and_then(|(pixels, width, height)| {
+            chip8
+                .import_display_pixels(&pixels, width, height)
+                .map_err(|e| e.to_string())
+        });
+        if let Err(e) = result {
+            eprintln!("Failed to put '{}' on the display : {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    }
+    if let Some(path) = &config.fixture {
+        let result = fixture::Fixture::load(path).and_then(|fixture| fixture.apply(&mut chip8));
+        if let Err(e) = result {
+            eprintln!("Failed to apply fixture '{}' : {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    }
+    // Otherwise this is just a black screen with no explanation
+    if rom.len() < 2 {
+        eprintln!(
+            "Warning: ROM file '{}' is {} bytes, too short to hold an instruction",
+            rom_path,
+            rom.len()
+        );
+    }
 
-            0xA000 => {
-                // ANNN: Set index register I to NNN
-                // println!("Set I = {:#05X}", nnn);
-                self.i = nnn;
+    let mut recorder = match &config.record_gif {
+        Some(path) => match record::GifRecorder::create(
+            path,
+            chip8.palette(),
+            config.gif_fps,
+            config.gif_scale,
+            config.gif_max_seconds,
+        ) {
+            Ok(recorder) => Some(recorder),
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
             }
-            0xB000 => {
-                // BNNN: Jump to address NNN + V0
-                self.pc = nnn + self.registers[0] as u16 - 2;
+        },
+        None => None,
+    };
+
+    let mut write_log = match &config.log_writes {
+        Some(path) => match writelog::WriteLog::create(path) {
+            Ok(log) => Some(log),
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
             }
-            0xC000 => {
-                // CXNN: set VX to random byte AND NN
-                let random_byte: u8 = rand::thread_rng().gen_range(0..=255);
-                self.registers[x] = random_byte & nn;
+        },
+        None => None,
+    };
+    if write_log.is_some() {
+        chip8.enable_write_log();
+    }
+
+    let mut debugger = None;
+    if config.debug {
+        let mut new_debugger = debugger::Debugger::new();
+        for &address in &config.breakpoints {
+            new_debugger.add_breakpoint(address);
+        }
+        for condition in &config.break_conditions {
+            if let Err(e) = new_debugger.add_condition(condition) {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
             }
+        }
+        for &cycle in &config.break_cycles {
+            new_debugger.add_cycle_break(cycle);
+        }
+        if config.break_at_start {
+            new_debugger.pause();
+        }
+        debugger = Some(new_debugger);
+    }
 
-            0xD000 => {
-                // DXYN Draw display
-                let x = self.registers[x] as usize;
-                let y = self.registers[y] as usize;
-                let height = n;
-                let shift = x % 8;
+    active_rom.start(&mut chip8);
+    // Coverage is read off the same counts
+    if config.profile || config.coverage {
+        chip8.enable_profiling();
+    }
+    if config.frame_histogram {
+        chip8.enable_frame_histogram();
+    }
 
-                self.registers[0xF] = 0; // Reset collision flag
+    let code = if config.headless {
+        run_headless(
+            &config,
+            &mut chip8,
+            &mut active_rom,
+            &mut recorder,
+            debugger,
+            &mut write_log,
+        )
+    } else if config.terminal {
+        terminal::run(
+            &config,
+            active_rom.settings().hz,
+            &mut chip8,
+            &mut write_log,
+        )
+    } else {
+        run_window(
+            &config,
+            &mut chip8,
+            &mut active_rom,
+            &mut recorder,
+            debugger,
+            &mut write_log,
+        )
+    };
+    // However the run ended (Escape, closing the window, a fault), finish the
+    // output files here so they're complete and any failure is reported
+    if let Some(log) = &mut write_log {
+        // Whatever the last frame wrote before the run stopped
+        log.record(&mut chip8);
+        log.finish();
+    }
+    if let Some(recorder) = &mut recorder {
+        recorder.finish();
+    }
 
-                for row in 0..height {
-                    let sprite_byte = self.memory[(self.i + row as u16) as usize];
-                    let display_row = (y + row as usize) % 32;
-                    let display_byte_index = (display_row * 8) + (x / 8);
+    if let Some(path) = &config.dump_on_exit {
+        match fs::write(path, chip8.dump_state_json_with_memory()) {
+            Ok(()) => println!("Wrote final state to {}", path),
+            Err(e) => eprintln!("Failed to write '{}' : {}", path, e),
+        }
+    }
+    if let Some(path) = &config.svg_on_exit {
+        let result = svg::display_svg(&chip8).and_then(|svg| {
+            fs::write(path, svg).map_err(|e| format!("Failed to write '{}' : {}", path, e))
+        });
+        match result {
+            Ok(()) => println!("Wrote the display to {}", path),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
 
-                    let old = self.display[display_byte_index];
-                    self.display[display_byte_index] ^= sprite_byte >> shift;
+    // Whatever was changed in the window sticks for next time, for whichever
+    // ROM was running when it was changed
+    active_rom.save_settings(&chip8);
+    active_rom.save_rpl(&chip8);
 
-                    if (old & sprite_byte >> shift) != 0 {
-                        self.registers[0xF] = 1;
-                    }
+    if let Some(profile) = chip8.profile() {
+        if config.profile {
+            print!("\n{}", profile.report());
+        }
+        if config.coverage {
+            print!("\n{}", profile.coverage_report());
+        }
+    }
+    if let Some(histogram) = chip8.frame_histogram() {
+        print!("\n{}", histogram.report());
+    }
 
-                    if shift != 0 && (x + 8) < 64 {
-                        let old = self.display[display_byte_index + 1];
-                        self.display[display_byte_index + 1] ^= sprite_byte << (8 - shift);
+    code
+}
 
-                        if old != 0 && self.display[display_byte_index + 1] < old {
-                            self.registers[0xF] = 1;
-                        }
-                    }
-                }
-            }
+// Whole ROM from a file, or from stdin when the path is `-`. Gzipped ROMs
+// are unpacked, whatever they're called.
+fn read_rom(path: &str) -> Result<Vec<u8>, String> {
+    #[cfg(feature = "embed")]
+    if path == EMBEDDED_ROM_PATH {
+        return Ok(EMBEDDED_ROM.to_vec());
+    }
+    let rom = if path != "-" {
+        fs::read(path).map_err(|e| format!("Failed to read ROM file '{}' : {}", path, e))?
+    } else {
+        let mut rom = Vec::new();
+        io::stdin()
+            .read_to_end(&mut rom)
+            .map_err(|e| format!("Failed to read ROM from stdin : {}", e))?;
+        if rom.is_empty() {
+            return Err("No ROM data on stdin".to_string());
+        }
+        rom
+    };
 
-            0xE000 => {
-                match opcode & 0x00FF {
-                    0x009E => {
-                        // EX9E: Skip next instruction if key VX is pressed
-                        let key = self.registers[x] as usize;
-                        if self.keys[key] {
-                            self.pc += 2;
-                        }
-                    }
-                    0x00A1 => {
-                        // EXA1: Skip next instruction if key VX is NOT pressed
-                        let key = self.registers[x] as usize;
-                        if !self.keys[key] {
-                            self.pc += 2;
-                        }
-                    }
-                    _ => println!("Unkown 0xE ... opcode: {:#06X}", opcode),
-                }
-            }
+    if rom.starts_with(&GZIP_MAGIC) {
+        return gunzip(&rom).map_err(|e| format!("Failed to unpack ROM file '{}' : {}", path, e));
+    }
+    Ok(rom)
+}
 
-            0xF000 => {
-                match opcode & 0x00FF {
-                    0x07 => {
-                        // FX07: Set VX to delay timer value
-                        self.registers[x] = self.delay_timer;
-                    }
-                    0x0A => {
-                        // FX0A: wait for key press
-                        self.waiting_for_key = true;
-                        self.key_register = x;
-                        self.pc -= 2;
-                    }
-                    0x15 => {
-                        // FX15: Set delay timer to VX
-                        self.delay_timer = self.registers[x];
-                    }
-                    0x18 => {
-                        // FX18: Set sound timer to VX
-                        self.sound_timer = self.registers[x];
-                    }
-                    0x1E => {
-                        // FX1E: Add VX to I
-                        self.i += self.registers[x] as u16;
-                    }
-                    0x29 => {
-                        // FX29: Sets I to the location of the sprite for the character in VX
-                        self.i = (self.registers[x] * 5) as u16 + 0x050
-                    }
-                    0x33 => {
-                        // FX33: Store decimal representation of VX with hundreds at I tens at I+1
-                        // and ones at I+2
-                        let hundreds = self.registers[x] / 100;
-                        let tens = (self.registers[x] % 100) / 10;
-                        let ones = (self.registers[x] % 100) % 10;
-                        self.memory[self.i as usize] = hundreds;
-                        self.memory[(self.i + 1) as usize] = tens;
-                        self.memory[(self.i + 2) as usize] = ones;
-                    }
-                    0x55 => {
-                        // FX55: Stores from V0 to VX in memory starting at address I
-                        for i in 0..=x {
-                            self.memory[(self.i + i as u16) as usize] = self.registers[i];
-                        }
-                    }
-                    0x65 => {
-                        // FX65: Fills from V0 to VX with values from memory starting at address I
-                        for i in 0..=x {
-                            self.registers[i] = self.memory[(self.i + i as u16) as usize];
-                        }
-                    }
-                    _ => println!("Unknown 0xF... opcode: {:#06X}", opcode),
-                }
-            }
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
 
-            _ => {
-                println!("Unknown opcode: {:#06X}", opcode);
+// A ROM built into the binary for self-contained demos, run when no ROM is
+// given on the command line. The file is picked when building:
+//
+//     CHIP8_EMBED_ROM=/path/to/game.ch8 cargo build --release --features embed
+#[cfg(feature = "embed")]
+const EMBEDDED_ROM: &[u8] = include_bytes!(env!("CHIP8_EMBED_ROM"));
+
+// Stands in for a path when running the embedded ROM
+#[cfg(feature = "embed")]
+const EMBEDDED_ROM_PATH: &str = "<embedded>";
+
+#[cfg(feature = "gzip")]
+fn gunzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut rom = Vec::new();
+    flate2::read::GzDecoder::new(data)
+        .read_to_end(&mut rom)
+        .map_err(|e| e.to_string())?;
+    Ok(rom)
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gunzip(_data: &[u8]) -> Result<Vec<u8>, String> {
+    Err("it's gzipped, which needs the emulator built with the `gzip` feature".to_string())
+}
+
+// The CPU and timer clocks for a run at `hz`, both scaled by --speed
+fn clocks_for(config: &Config, hz: u32) -> (Clock, Clock) {
+    (
+        Clock::new(hz as f64 * config.speed),
+        Clock::new(60.0 * config.speed),
+    )
+}
+
+// After `ActiveRom::switch`, the new ROM's speed for the clock (and the CPU
+// thread's with --threaded) and its palette for the GIF
+fn follow_rom(
+    config: &Config,
+    rom: &activerom::ActiveRom,
+    clock: &mut Clock,
+    control: Option<&cputhread::Control>,
+    recorder: &mut Option<record::GifRecorder>,
+) {
+    let hz = rom.settings().hz;
+    clock.set_rate(hz as f64 * config.speed);
+    if let Some(control) = control {
+        control.set_hz(hz);
+    }
+    if let Some(recorder) = recorder {
+        recorder.set_source_palette(rom.settings().palette);
+    }
+}
+
+// Returns how many cycles ran, one per instruction unless --vip-timing
+// stretched some over several, or None if the user quit from the debugger
+fn run_cycles(
+    chip8: &mut Chip8,
+    count: u32,
+    skip_idle: bool,
+    debugger: &mut Option<debugger::Debugger>,
+    trace: &mut Option<tracecheck::TraceCheck>,
+) -> Option<u32> {
+    for executed in 0..count {
+        // The rest of the frame would only spin on the delay timer
+        if skip_idle && chip8.is_waiting_on_delay_timer() {
+            return Some(executed);
+        }
+        // The rest of the frame belongs to a DXYN's wait
+        if chip8.is_waiting_for_vblank() {
+            return Some(executed);
+        }
+        // Paying for an expensive instruction, nothing to step or trace
+        if chip8.is_stalled() {
+            let _ = chip8.cycle();
+            continue;
+        }
+
+        if let Some(debugger) = debugger {
+            if debugger.before_cycle(chip8) == debugger::Action::Quit {
+                return None;
+            }
+            // A ROM swapped in with `load` waits for the caller to switch its
+            // RPL flags over
+            if debugger.has_loaded_rom() {
+                return Some(executed);
             }
         }
+
+        if let Some(trace) = trace {
+            trace.before_cycle(chip8);
+        }
+        let result = chip8.cycle();
+
+        if let Some(debugger) = debugger {
+            debugger.after_cycle(chip8, &result);
+        }
+        // Stopped here, so what diverged is the last thing run
+        if let Some(trace) = trace
+            && trace.after_cycle(chip8)
+        {
+            return Some(executed + 1);
+        }
+        // Skipped opcodes are already logged, faults are reported by the caller
+        if result.is_err() && chip8.fault().is_some() {
+            return Some(executed + 1);
+        }
     }
+    Some(count)
+}
 
-    fn cycle(&mut self) {
-        if self.waiting_for_key {
-            for (i, &pressed) in self.keys.iter().enumerate() {
-                if pressed {
-                    self.registers[self.key_register] = i as u8;
-                    self.waiting_for_key = false;
-                    self.pc += 2;
+fn report_fault(chip8: &Chip8) -> Option<ExitCode> {
+    let fault = chip8.fault()?;
+    eprintln!("Emulator fault at {:#05X}: {}", chip8.pc(), fault);
+    Some(ExitCode::from(EXIT_FAULT))
+}
+
+fn run_headless(
+    config: &Config,
+    chip8: &mut Chip8,
+    rom: &mut activerom::ActiveRom,
+    recorder: &mut Option<record::GifRecorder>,
+    mut debugger: Option<debugger::Debugger>,
+    write_log: &mut Option<writelog::WriteLog>,
+) -> ExitCode {
+    let mut input = match &config.input {
+        Some(path) => match replay::InputReplay::load(path) {
+            Ok(input) => Some(input),
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+    let mut trace = match &config.compare_trace {
+        Some(path) => match tracecheck::TraceCheck::load(path) {
+            Ok(trace) => Some(trace),
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+    let (mut clock, mut timer_clock) = clocks_for(config, rom.settings().hz);
+    let mut watchdog = config.watchdog.map(watchdog::Watchdog::new);
+    let mut draw_snapshots = match &config.snapshot_on_draw {
+        Some(dir) => match drawsnapshots::DrawSnapshots::create(dir) {
+            Ok(snapshots) => Some(snapshots),
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+    if draw_snapshots.is_some() {
+        chip8.enable_draw_snapshots();
+    }
+    // With --duration real time drives both clocks, as in the window, and a
+    // frame is a timer tick. Otherwise a frame is a 60th of a second of
+    // emulated time however long it takes to run.
+    let start = Instant::now();
+    let mut frame = 0;
+    loop {
+        let (cycles, timer_ticks) = match config.duration {
+            Some(duration) => {
+                if start.elapsed() >= duration {
+                    break;
+                }
+                thread::sleep(REAL_TIME_STEP);
+                (clock.ticks_elapsed(), timer_clock.ticks_elapsed())
+            }
+            None => {
+                if frame >= config.frames {
                     break;
                 }
+                (clock.ticks_per_frame(), timer_clock.ticks_per_frame())
             }
-            return;
+        };
+        let frames = if config.duration.is_some() {
+            timer_ticks
+        } else {
+            1
+        };
+
+        if let Some(input) = &mut input {
+            input.apply(frame, chip8);
+        }
+        let executed = run_cycles(chip8, cycles, config.skip_idle, &mut debugger, &mut trace);
+        if let Some(path) = debugger
+            .as_mut()
+            .and_then(debugger::Debugger::take_loaded_rom)
+        {
+            rom.switch(&path, chip8);
+            follow_rom(config, rom, &mut clock, None, recorder);
+        }
+        // Written before anything stops the run, the last draws may be the
+        // ones that matter
+        if let Some(snapshots) = &mut draw_snapshots {
+            snapshots.record(chip8);
+        }
+        if executed.is_none() {
+            return ExitCode::SUCCESS;
+        }
+        if trace.as_ref().is_some_and(|trace| trace.diverged()) {
+            return ExitCode::from(EXIT_TRACE_MISMATCH);
+        }
+        if let Some(log) = write_log {
+            log.record(chip8);
+        }
+        for _ in 0..timer_ticks {
+            chip8.update_timers();
+        }
+
+        if frames > 0
+            && let Some(recorder) = recorder
+        {
+            recorder.capture(&chip8.get_display_buffer(), chip8.display_width());
+        }
+
+        if chip8.is_halted() || chip8.fault().is_some() {
+            break;
         }
+        if let Some(watchdog) = &mut watchdog
+            && watchdog.check(chip8, frames)
+        {
+            return ExitCode::from(EXIT_WATCHDOG);
+        }
+        frame += frames;
+    }
+
+    // Before the fault report, a --max-cycles run still wants its registers
+    if config.print_regs {
+        println!("{}", register_line(chip8));
+    }
+    if let Some(code) = report_fault(chip8) {
+        return code;
+    }
+    if let Some(trace) = &trace {
+        trace.report();
+    }
+    if let Some(snapshots) = &draw_snapshots {
+        snapshots.report();
+    }
+
+    let hash = chip8.framebuffer_hash();
+    println!("Framebuffer hash: {:016x}", hash);
+
+    match config.expect_hash {
+        Some(expected) if expected != hash => {
+            eprintln!("Expected framebuffer hash {:016x}", expected);
+            ExitCode::from(EXIT_HASH_MISMATCH)
+        }
+        _ => ExitCode::SUCCESS,
+    }
+}
+
+// What's about to run, once the quirks are settled
+fn print_startup(config: &Config, rom: &activerom::ActiveRom, chip8: &Chip8, rom_size: usize) {
+    if config.verbosity == Verbosity::Quiet {
+        return;
+    }
+    let quirks = chip8.quirks();
+    let preset = QUIRK_PRESETS
+        .iter()
+        .find(|&&name| Quirks::preset(name) == Some(quirks))
+        .map_or(
+            if quirks == Quirks::default() {
+                "default"
+            } else {
+                "custom"
+            },
+            |name| name,
+        );
+    println!("ROM: {} ({} bytes)", config.rom_path, rom_size);
+    println!(
+        "Speed: {} instructions per second",
+        (rom.settings().hz as f64 * config.speed).round()
+    );
+    println!("Quirks: {}", preset);
+    println!(
+        "Display: {}x{}",
+        chip8.display_width(),
+        chip8.display_height()
+    );
+    if config.verbosity == Verbosity::Verbose {
+        println!("{:#?}", quirks);
+        println!("{:#?}", config);
+    }
+    println!();
+}
 
-        let opcode = self.fetch();
+// `PC=0x2A4 I=0x300 V0=0x42 ... VF=0x01` on one line, for scripts to grep
+fn register_line(chip8: &Chip8) -> String {
+    let mut line = format!("PC={:#05X} I={:#05X}", chip8.pc(), chip8.index());
+    for (register, value) in chip8.registers().iter().enumerate() {
+        line.push_str(&format!(" V{:X}={:#04X}", register, value));
+    }
+    line
+}
 
-        self.execute(opcode);
+// ROM name, instructions per frame and anything unusual about the speed,
+// for the F4 overlay
+fn hud_text(
+    config: &Config,
+    rom: &activerom::ActiveRom,
+    paused: bool,
+    quirks: Option<&str>,
+) -> String {
+    let rom_path = rom.path();
+    let name = std::path::Path::new(rom_path).file_name().map_or_else(
+        || rom_path.to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    let cycles_per_frame = (rom.settings().hz as f64 * config.speed / 60.0).round();
+    let status = if paused {
+        " PAUSED"
+    } else if config.speed > 1.0 {
+        " TURBO"
+    } else if config.speed < 1.0 {
+        " SLOW"
+    } else {
+        ""
+    };
+    // Only once picked with P, the quirks the ROM started with have no name
+    let quirks = quirks.map_or_else(String::new, |name| format!(" {}", name.to_uppercase()));
+    format!("{} {} C/F{}{}", name, cycles_per_frame, quirks, status)
+}
+
+// Hex digit or space typed into the poke overlay, None for other keys
+fn poke_char(key: Key) -> Option<char> {
+    let c = match key {
+        Key::Key0 | Key::NumPad0 => '0',
+        Key::Key1 | Key::NumPad1 => '1',
+        Key::Key2 | Key::NumPad2 => '2',
+        Key::Key3 | Key::NumPad3 => '3',
+        Key::Key4 | Key::NumPad4 => '4',
+        Key::Key5 | Key::NumPad5 => '5',
+        Key::Key6 | Key::NumPad6 => '6',
+        Key::Key7 | Key::NumPad7 => '7',
+        Key::Key8 | Key::NumPad8 => '8',
+        Key::Key9 | Key::NumPad9 => '9',
+        Key::A => 'A',
+        Key::B => 'B',
+        Key::C => 'C',
+        Key::D => 'D',
+        Key::E => 'E',
+        Key::F => 'F',
+        Key::Space => ' ',
+        _ => return None,
+    };
+    Some(c)
+}
 
-        // each instruction is 2 bytes
-        self.pc += 2;
+// Writes what was typed into the poke overlay, "2F0 05" for 0x05 at 0x2F0.
+// Anything else is reported and left alone.
+fn apply_poke(chip8: &mut Chip8, text: &str) {
+    let mut parts = text.split_whitespace();
+    let poke = match (parts.next(), parts.next(), parts.next()) {
+        (Some(address), Some(value), None) => u16::from_str_radix(address, 16)
+            .ok()
+            .zip(u8::from_str_radix(value, 16).ok()),
+        _ => None,
+    };
+    let Some((address, value)) = poke else {
+        eprintln!(
+            "Ignoring poke '{}', expected 'ADDR VALUE' in hex",
+            text.trim()
+        );
+        return;
+    };
+    match chip8.force_load_at(address, &[value]) {
+        Ok(()) => println!("Poked {:#04X} at {:#05X}", value, address),
+        Err(_) => eprintln!("Ignoring poke, {:#05X} is outside memory", address),
     }
+}
 
-    fn print_state(&self) {
-        println!("\n--- CPU State ---");
-        println!("PC: {:#05X}", self.pc);
-        println!("I: {:#05X}", self.i);
-        print!("Registers: ");
-        for (i, &val) in self.registers.iter().enumerate() {
-            print!("V{:X}={:#04X} ", i, val);
-            if i == 7 {
-                print!("\n           ");
+// Resets the machine and loads the ROM `step` places on from `current`,
+// wrapping around the playlist. ROMs that fail to load are skipped with a
+// warning. Returns the index loaded, None when none of the others would.
+fn switch_rom(
+    chip8: &mut Chip8,
+    playlist: &[String],
+    current: usize,
+    step: usize,
+) -> Option<usize> {
+    let mut index = current;
+    for _ in 1..playlist.len() {
+        index = (index + step) % playlist.len();
+        let path = &playlist[index];
+        match read_rom(path).and_then(|rom| chip8.load_rom(&rom).map_err(|e| e.to_string())) {
+            Ok(()) => {
+                println!("Switched to {}", path);
+                return Some(index);
             }
+            Err(e) => eprintln!("Warning: skipping '{}' : {}", path, e),
         }
-        println!("\n");
     }
+    eprintln!("None of the other ROMs could be loaded");
+    None
+}
 
-    fn update_timers(&mut self) {
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
+// For --watch. A file caught half written is left for the next change,
+// the program already running keeps going.
+fn reload_rom(chip8: &mut Chip8, path: &str) -> bool {
+    let result = read_rom(path)
+        .and_then(|rom| {
+            if rom.is_empty() {
+                Err("the file is empty".to_string())
+            } else {
+                Ok(rom)
+            }
+        })
+        .and_then(|rom| chip8.load_rom(&rom).map_err(|e| e.to_string()));
+    match result {
+        Ok(()) => {
+            println!("Reloaded {}", path);
+            true
         }
-        if self.sound_timer > 0 {
-            self.sound_timer -= 1;
+        Err(e) => {
+            eprintln!("Warning: couldn't reload '{}' : {}", path, e);
+            false
         }
     }
 }
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
+fn run_window(
+    config: &Config,
+    chip8: &mut Chip8,
+    rom: &mut activerom::ActiveRom,
+    recorder: &mut Option<record::GifRecorder>,
+    mut debugger: Option<debugger::Debugger>,
+    write_log: &mut Option<writelog::WriteLog>,
+) -> ExitCode {
+    let mut window = open_window(640, 320, false, config.key_polls);
+    let mut fullscreen = false;
+    // Windowed size and position to go back to when leaving fullscreen
+    let mut windowed_size = window.get_size();
+    let mut windowed_position = window.get_position();
 
-    if args.len() < 2 {
-        eprintln!("Usage: {} <rom_file>", args[0]);
-        eprintln!("Example: {} roms/pong.ch8", args[0]);
-        return;
+    let beeper = audio::Beeper::new(config.volume, config.waveform, config.frequency);
+    if beeper.is_none() && cfg!(feature = "audio") {
+        eprintln!("No audio output available, running without sound");
     }
 
-    let rom_path = &args[1];
+    let (mut clock, mut timer_clock) = clocks_for(config, rom.settings().hz);
+    let mut watchdog = config.watchdog.map(watchdog::Watchdog::new);
+    let mut show_stats = false;
+    let mut show_hud = false;
+    let mut show_keypad = false;
+    let mut show_wrap = false;
+    let mut scanlines = config.scanlines;
+    let mut grid = config.grid;
+    let mut smooth_scaling = config.smooth_scaling;
+    // Index into PALETTE_PRESETS, None for the palette from the command line
+    let mut preset: Option<usize> = None;
+    // Index into QUIRK_PRESETS, None for the quirks the ROM started with
+    let mut quirk_preset: Option<usize> = None;
+    // Frames and instructions counted since `stats_start`, shown once per second
+    let mut stats_start = Instant::now();
+    let mut frame_count = 0;
+    let mut instruction_count = 0;
+    let mut fps = 0;
+    let mut ips = 0;
+    // Index into the playlist of the ROM running now
+    let mut playlist_index = 0;
+    let mut rom_path = config.rom_path.as_str();
+    let mut rom_watch = config.watch.then(|| romwatch::RomWatch::new(rom_path));
+    // Window loop iterations so far, each one a key poll
+    let mut poll_count: u64 = 0;
+    // Text typed so far while the Insert poke overlay is open
+    let mut poke: Option<String> = None;
+    // Shared with the CPU thread with --threaded, otherwise only locked here
+    let shared = Mutex::new(chip8);
+    let control = cputhread::Control::default();
+    control.set_hz(rom.settings().hz);
 
-    println!("=== Chip-8 Emulator - Starting ===\n");
+    thread::scope(|scope| {
+        let _stop = cputhread::StopOnDrop(&control);
+        if config.threaded {
+            scope.spawn(|| cputhread::run(&shared, config, &control));
+        }
 
-    let mut chip8 = Chip8::new();
+        while window.is_open() && !window.is_key_down(Key::Escape) {
+            let mut guard = shared.lock().unwrap();
+            let chip8: &mut Chip8 = &mut guard;
 
-    let mut window = Window::new("Chip-8 Emulator", 640, 320, WindowOptions::default())
-        .expect("Failed to create window");
+            if window.is_key_pressed(Key::F2, KeyRepeat::No) {
+                // A quick look without stopping, the game keeps running
+                chip8.print_state();
+            }
+            if window.is_key_pressed(Key::F1, KeyRepeat::No) {
+                // The screen as text, for pasting into a bug report
+                chip8.print_display();
+            }
+            if window.is_key_pressed(Key::Insert, KeyRepeat::No) {
+                // Insert again closes it without writing anything
+                poke = match poke {
+                    Some(_) => None,
+                    None => Some(String::new()),
+                };
+            } else if let Some(text) = &mut poke {
+                let mut done = false;
+                for key in window.get_keys_pressed(KeyRepeat::Yes) {
+                    match key {
+                        Key::Enter | Key::NumPadEnter => done = true,
+                        Key::Backspace => {
+                            text.pop();
+                        }
+                        // Room for a 4 digit address and a byte
+                        _ => {
+                            if let Some(c) = poke_char(key)
+                                && text.len() < 8
+                            {
+                                text.push(c);
+                            }
+                        }
+                    }
+                }
+                if done {
+                    apply_poke(chip8, text);
+                    poke = None;
+                }
+            }
+            if window.is_key_pressed(Key::F3, KeyRepeat::No) {
+                show_stats = !show_stats;
+            }
+            if window.is_key_pressed(Key::F4, KeyRepeat::No) {
+                show_hud = !show_hud;
+            }
+            if poke.is_none() && window.is_key_pressed(Key::K, KeyRepeat::No) {
+                show_keypad = !show_keypad;
+            }
+            if config.playlist.len() > 1 {
+                let step = if window.is_key_pressed(Key::PageDown, KeyRepeat::No) {
+                    Some(1)
+                } else if window.is_key_pressed(Key::PageUp, KeyRepeat::No) {
+                    Some(config.playlist.len() - 1)
+                } else {
+                    None
+                };
+                if let Some(step) = step
+                    && let Some(index) = switch_rom(chip8, &config.playlist, playlist_index, step)
+                {
+                    playlist_index = index;
+                    rom_path = &config.playlist[index];
+                    rom.switch(rom_path, chip8);
+                    follow_rom(config, rom, &mut clock, Some(&control), recorder);
+                    if config.watch {
+                        rom_watch = Some(romwatch::RomWatch::new(rom_path));
+                    }
+                }
+            }
+            // Back from whatever the debugger loaded to the watched file
+            if let Some(watch) = &mut rom_watch
+                && watch.poll()
+                && reload_rom(chip8, rom_path)
+            {
+                rom.switch(rom_path, chip8);
+                follow_rom(config, rom, &mut clock, Some(&control), recorder);
+            }
+            if window.is_key_pressed(Key::F6, KeyRepeat::No) {
+                show_wrap = !show_wrap;
+            }
+            if window.is_key_pressed(Key::F7, KeyRepeat::No) {
+                scanlines = !scanlines;
+            }
+            if window.is_key_pressed(Key::F12, KeyRepeat::No) {
+                smooth_scaling = !smooth_scaling;
+            }
+            if window.is_key_pressed(Key::F10, KeyRepeat::No) {
+                chip8.set_invert_display(!chip8.invert_display());
+            }
+            if window.is_key_pressed(Key::F8, KeyRepeat::No) {
+                let next = preset.map_or(0, |index| (index + 1) % PALETTE_PRESETS.len());
+                let (name, foreground, background) = PALETTE_PRESETS[next];
+                let palette = [
+                    background,
+                    foreground,
+                    DEFAULT_PALETTE[2],
+                    DEFAULT_PALETTE[3],
+                ];
+                chip8.set_palette(palette);
+                if let Some(recorder) = recorder {
+                    recorder.set_source_palette(palette);
+                }
+                println!("Palette: {}", name);
+                preset = Some(next);
+            }
+            // P, M and G do nothing while a poke is being typed
+            if poke.is_none() && window.is_key_pressed(Key::G, KeyRepeat::No) {
+                grid = !grid;
+            }
+            if poke.is_none() && window.is_key_pressed(Key::P, KeyRepeat::No) {
+                // Takes effect from the next instruction, no reset needed
+                let next = quirk_preset.map_or(0, |index| (index + 1) % QUIRK_PRESETS.len());
+                chip8.set_quirks(Quirks::preset(QUIRK_PRESETS[next]).unwrap());
+                println!("Quirks: {}", QUIRK_PRESETS[next]);
+                quirk_preset = Some(next);
+            }
+            if let Some(beeper) = &beeper
+                && poke.is_none()
+                && window.is_key_pressed(Key::M, KeyRepeat::No)
+            {
+                let muted = beeper.toggle_mute();
+                println!("{}", if muted { "Muted" } else { "Unmuted" });
+            }
+            if let Some(debugger) = &mut debugger
+                && window.is_key_pressed(Key::F5, KeyRepeat::No)
+            {
+                debugger.pause();
+            }
+            if let Some(recorder) = recorder
+                && window.is_key_pressed(Key::F9, KeyRepeat::No)
+            {
+                let recording = recorder.toggle();
+                println!(
+                    "{}",
+                    if recording {
+                        "GIF recording resumed"
+                    } else {
+                        "GIF recording paused"
+                    }
+                );
+            }
 
-    window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
+            if window.is_key_pressed(Key::F11, KeyRepeat::No) {
+                // minifb can't change window styles, so recreate the window
+                if fullscreen {
+                    window = open_window(windowed_size.0, windowed_size.1, false, config.key_polls);
+                    window.set_position(windowed_position.0, windowed_position.1);
+                } else {
+                    windowed_size = window.get_size();
+                    windowed_position = window.get_position();
+                    window = open_window(
+                        config.screen_size.0,
+                        config.screen_size.1,
+                        true,
+                        config.key_polls,
+                    );
+                }
+                fullscreen = !fullscreen;
+            }
 
-    // Read the ROM file
-    let rom = fs::read(rom_path).unwrap_or_else(|e| {
-        eprintln!("Failed to read ROM file '{}' : {}", rom_path, e);
-        std::process::exit(1);
-    });
-    // Load it into memory
-    chip8.load_program(&rom);
+            // Keep redrawing while in the background, but don't advance the machine
+            // and while a poke is being typed
+            let paused = (config.pause_on_focus_loss && !window.is_active()) || poke.is_some();
+            // Taken even while paused so time spent paused isn't made up afterwards
+            let cycles = clock.ticks_elapsed();
+            let timer_ticks = timer_clock.ticks_elapsed();
+            control.set_paused(paused);
 
-    while window.is_open() && !window.is_key_down(Key::Escape) {
-        chip8.set_keys(&window);
+            if !paused {
+                // The running ROM's, which changes with it
+                match rom.settings().input_profile {
+                    Some(profile) => profile.set_keys(chip8, &window),
+                    None => chip8.set_keys(&window),
+                }
 
-        for _ in 0..11 {
-            chip8.cycle();
-            //  chip8.print_state();
-        }
+                let timer_ticks = if config.threaded {
+                    // Run by the CPU thread since the last frame
+                    let (executed, timer_ticks) = control.take_counts();
+                    instruction_count += executed as u128;
+                    if let Some(log) = write_log {
+                        log.record(chip8);
+                    }
+                    timer_ticks
+                } else {
+                    let Some(executed) =
+                        run_cycles(chip8, cycles, config.skip_idle, &mut debugger, &mut None)
+                    else {
+                        return ExitCode::SUCCESS;
+                    };
+                    if let Some(path) = debugger
+                        .as_mut()
+                        .and_then(debugger::Debugger::take_loaded_rom)
+                    {
+                        rom.switch(&path, chip8);
+                        follow_rom(config, rom, &mut clock, Some(&control), recorder);
+                    }
+                    //  chip8.print_state();
+                    instruction_count += executed as u128;
+                    if let Some(log) = write_log {
+                        log.record(chip8);
+                    }
 
-        chip8.update_timers();
+                    for _ in 0..timer_ticks {
+                        chip8.update_timers();
+                    }
+                    timer_ticks
+                };
 
-        let buffer = chip8.get_display_buffer();
-        window.update_with_buffer(&buffer, 64, 32).unwrap();
-    }
+                if let Some(code) = report_fault(chip8) {
+                    return code;
+                }
+                // Only a warning here, the player can see for themselves
+                if let Some(watchdog) = &mut watchdog {
+                    watchdog.check(chip8, timer_ticks);
+                }
+            }
+
+            if let Some(beeper) = &beeper {
+                // Stays silent while paused. A click set and run out since
+                // the last frame still gets this one, unless --exact-beep.
+                let started = chip8.take_beep_started() && !config.exact_beep;
+                beeper.set_playing(!paused && (chip8.is_beeping() || started));
+                beeper.set_frequency(config.frequency * chip8.pitch_ratio());
+            }
+
+            // Only the keys and the instructions so far this frame, the
+            // picture waits for the last poll
+            poll_count += 1;
+            if !poll_count.is_multiple_of(config.key_polls as u64) {
+                drop(guard);
+                window.update();
+                continue;
+            }
+
+            let buffer = chip8.get_display_buffer();
+            if !paused && let Some(recorder) = recorder {
+                recorder.capture(&buffer, chip8.display_width());
+            }
+            let (width, height) = window.get_size();
+            if width == 0 || height == 0 {
+                // Minimized, nothing to draw into. The CPU thread gets the
+                // machine while this one waits on the window.
+                drop(guard);
+                window.update();
+                continue;
+            }
+            // Read every frame, so the picture follows a change of mode
+            let (display_width, display_height) = chip8.display_dimensions();
+            let scale_to_window = if smooth_scaling {
+                render::scale_to_window_smooth
+            } else {
+                render::scale_to_window
+            };
+            let mut scaled = if show_wrap {
+                // Tint a copy, the recording and emulated display stay as they are
+                let mut tinted = buffer.clone();
+                let off = chip8.palette()[if chip8.invert_display() { 3 } else { 0 }];
+                for (pixel, wrapped) in tinted.iter_mut().zip(chip8.wrapped_pixels()) {
+                    if wrapped && *pixel != off {
+                        *pixel = WRAP_TINT;
+                    }
+                }
+                let mut scaled =
+                    scale_to_window(&tinted, display_width, display_height, width, height);
+                render::draw_border(
+                    &mut scaled,
+                    display_width,
+                    display_height,
+                    width,
+                    height,
+                    WRAP_TINT,
+                );
+                scaled
+            } else {
+                scale_to_window(&buffer, display_width, display_height, width, height)
+            };
+            if grid {
+                render::draw_grid(
+                    &mut scaled,
+                    display_width,
+                    display_height,
+                    width,
+                    height,
+                    config.grid_color,
+                    config.grid_opacity,
+                );
+            }
+            if scanlines {
+                render::apply_scanlines(&mut scaled, width);
+            }
+
+            frame_count += 1;
+            let elapsed = stats_start.elapsed();
+            if elapsed >= Duration::from_secs(1) {
+                fps = frame_count * 1000 / elapsed.as_millis();
+                ips = instruction_count * 1000 / elapsed.as_millis();
+                frame_count = 0;
+                instruction_count = 0;
+                stats_start = Instant::now();
+            }
+
+            if show_stats {
+                // Drawn on the scaled output so the emulated display is untouched
+                let scale = (height / 160).max(2);
+                render::draw_text(
+                    &mut scaled,
+                    width,
+                    scale,
+                    scale,
+                    &fps.to_string(),
+                    scale,
+                    0xFFFF00,
+                );
+                render::draw_text(
+                    &mut scaled,
+                    width,
+                    scale,
+                    scale * 7,
+                    &ips.to_string(),
+                    scale,
+                    0xFFFF00,
+                );
+            }
+
+            if show_hud {
+                let scale = (height / 160).max(2);
+                render::draw_label(
+                    &mut scaled,
+                    width,
+                    scale,
+                    height.saturating_sub(scale * 6),
+                    &hud_text(
+                        config,
+                        rom,
+                        paused,
+                        quirk_preset.map(|index| QUIRK_PRESETS[index]),
+                    ),
+                    scale,
+                    0xFFFF00,
+                );
+            }
+
+            if show_keypad {
+                let scale = (height / 160).max(2);
+                render::draw_keypad(&mut scaled, width, chip8.keys(), scale, 0xFFFF00);
+            }
+
+            if let Some(text) = &poke {
+                let scale = (height / 160).max(2);
+                render::draw_label(
+                    &mut scaled,
+                    width,
+                    scale,
+                    scale * 13,
+                    &format!("POKE: {}_", text),
+                    scale,
+                    0xFFFF00,
+                );
+            }
+
+            // The CPU thread runs while this one waits on the window
+            drop(guard);
+            window.update_with_buffer(&scaled, width, height).unwrap();
+        }
+
+        ExitCode::SUCCESS
+    })
 }
@@ -0,0 +1,496 @@
+// Every instruction the emulator runs, as data. The disassembler decodes
+// and the assembler encodes from this table, the profiler names its families
+// after it, and a test checks it against `execute`, so adding an opcode
+// means adding it here too.
+use Extension::*;
+use OperandFormat::*;
+use serde::Serialize;
+
+// Where an instruction comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Extension {
+    Chip8,
+    Schip,
+    XoChip,
+    // Only with `Chip8Config::chip8x`, where it takes over from what the
+    // same opcode does otherwise
+    Chip8x,
+}
+
+// What an operand looks like in assembler source, and where it goes in the
+// opcode
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum OperandFormat {
+    // Register from the X or Y digit
+    Vx,
+    Vy,
+    // Just V0, as in `JP V0, #NNN`
+    V0,
+    // NNN, NN and N, the low digits
+    Address,
+    Byte,
+    Nibble,
+    // The X digit as a number rather than a register, FN01's plane mask
+    XNibble,
+    I,
+    // [I], the memory at I
+    IndirectI,
+    DT,
+    ST,
+    K,
+    F,
+    B,
+    // SCHIP RPL user flags
+    R,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct OpcodeInfo {
+    // The opcode with X, Y and N for the digits that vary, e.g. "8XY4"
+    pub pattern: &'static str,
+    // Bits the pattern fixes, and what they are
+    pub mask: u16,
+    pub bits: u16,
+    // Assembler syntax, `mnemonic operand, operand`
+    pub mnemonic: &'static str,
+    pub operands: &'static [OperandFormat],
+    pub extension: Extension,
+    // Names of the `Quirks` fields that change what it does
+    pub quirks: &'static [&'static str],
+    pub description: &'static str,
+}
+
+impl OperandFormat {
+    // How a fixed operand is written, None for ones that take a value
+    pub fn keyword(self) -> Option<&'static str> {
+        Some(match self {
+            V0 => "V0",
+            I => "I",
+            IndirectI => "[I]",
+            DT => "DT",
+            ST => "ST",
+            K => "K",
+            F => "F",
+            B => "B",
+            R => "R",
+            Vx | Vy | Address | Byte | Nibble | XNibble => return None,
+        })
+    }
+}
+
+impl OpcodeInfo {
+    pub fn matches(&self, opcode: u16) -> bool {
+        opcode & self.mask == self.bits
+    }
+}
+
+// In opcode order. Where patterns overlap the first match wins, so the
+// fixed opcodes come before 0NNN and each Chip-8X entry before the one it
+// replaces.
+pub const OPCODES: [OpcodeInfo; 51] = [
+    entry(
+        "00CN",
+        "SCD",
+        &[Nibble],
+        Schip,
+        &["half_lores_scroll"],
+        "Scroll the selected planes down N rows",
+    ),
+    entry(
+        "00DN",
+        "SCU",
+        &[Nibble],
+        XoChip,
+        &["half_lores_scroll"],
+        "Scroll the selected planes up N rows",
+    ),
+    entry("00E0", "CLS", &[], Chip8, &[], "Clear the selected planes"),
+    entry("00EE", "RET", &[], Chip8, &[], "Return from a subroutine"),
+    entry(
+        "00FA",
+        "TOGGLEI",
+        &[],
+        Schip,
+        &["load_store_increments_i"],
+        "Flip whether FX55/FX65 move I past the registers",
+    ),
+    entry(
+        "00FB",
+        "SCR",
+        &[],
+        Schip,
+        &["half_lores_scroll"],
+        "Scroll the selected planes right 4 pixels",
+    ),
+    entry(
+        "00FC",
+        "SCL",
+        &[],
+        Schip,
+        &["half_lores_scroll"],
+        "Scroll the selected planes left 4 pixels",
+    ),
+    entry(
+        "00FD",
+        "EXIT",
+        &[],
+        Schip,
+        &[],
+        "Exit the interpreter, halting the machine",
+    ),
+    entry(
+        "00FE",
+        "LOW",
+        &[],
+        Schip,
+        &[],
+        "Switch to the 64x32 lores mode, clearing the display",
+    ),
+    entry(
+        "00FF",
+        "HIGH",
+        &[],
+        Schip,
+        &[],
+        "Switch to the 128x64 hires mode, clearing the display",
+    ),
+    entry(
+        "02A0",
+        "STEPBG",
+        &[],
+        Chip8x,
+        &[],
+        "Step the background color",
+    ),
+    entry(
+        "0NNN",
+        "SYS",
+        &[Address],
+        Chip8,
+        &[],
+        "Call machine code at NNN, ignored unless SysCallMode::Trap (0000 halts)",
+    ),
+    entry("1NNN", "JP", &[Address], Chip8, &[], "Jump to NNN"),
+    entry(
+        "2NNN",
+        "CALL",
+        &[Address],
+        Chip8,
+        &[],
+        "Call the subroutine at NNN",
+    ),
+    entry(
+        "3XNN",
+        "SE",
+        &[Vx, Byte],
+        Chip8,
+        &[],
+        "Skip the next instruction if VX equals NN",
+    ),
+    entry(
+        "4XNN",
+        "SNE",
+        &[Vx, Byte],
+        Chip8,
+        &[],
+        "Skip the next instruction if VX doesn't equal NN",
+    ),
+    entry(
+        "5XY0",
+        "SE",
+        &[Vx, Vy],
+        Chip8,
+        &[],
+        "Skip the next instruction if VX equals VY",
+    ),
+    entry(
+        "5XY1",
+        "ADDN",
+        &[Vx, Vy],
+        Chip8x,
+        &[],
+        "Add VY to VX a nibble at a time, each 0-7",
+    ),
+    entry("6XNN", "LD", &[Vx, Byte], Chip8, &[], "Set VX to NN"),
+    entry(
+        "7XNN",
+        "ADD",
+        &[Vx, Byte],
+        Chip8,
+        &[],
+        "Add NN to VX, no carry",
+    ),
+    entry("8XY0", "LD", &[Vx, Vy], Chip8, &[], "Set VX to VY"),
+    entry(
+        "8XY1",
+        "OR",
+        &[Vx, Vy],
+        Chip8,
+        &["vf_reset"],
+        "Set VX to VX OR VY",
+    ),
+    entry(
+        "8XY2",
+        "AND",
+        &[Vx, Vy],
+        Chip8,
+        &["vf_reset"],
+        "Set VX to VX AND VY",
+    ),
+    entry(
+        "8XY3",
+        "XOR",
+        &[Vx, Vy],
+        Chip8,
+        &["vf_reset"],
+        "Set VX to VX XOR VY",
+    ),
+    entry(
+        "8XY4",
+        "ADD",
+        &[Vx, Vy],
+        Chip8,
+        &["vf_written_first"],
+        "Add VY to VX, VF = carry",
+    ),
+    entry(
+        "8XY5",
+        "SUB",
+        &[Vx, Vy],
+        Chip8,
+        &["vf_written_first"],
+        "Set VX to VX - VY, VF = no borrow",
+    ),
+    entry(
+        "8XY6",
+        "SHR",
+        &[Vx, Vy],
+        Chip8,
+        &["shift_uses_vy", "vf_written_first"],
+        "Shift VX right by 1, VF = the bit shifted out",
+    ),
+    entry(
+        "8XY7",
+        "SUBN",
+        &[Vx, Vy],
+        Chip8,
+        &["vf_written_first"],
+        "Set VX to VY - VX, VF = no borrow",
+    ),
+    entry(
+        "8XYE",
+        "SHL",
+        &[Vx, Vy],
+        Chip8,
+        &["shift_uses_vy", "vf_written_first"],
+        "Shift VX left by 1, VF = the bit shifted out",
+    ),
+    entry(
+        "9XY0",
+        "SNE",
+        &[Vx, Vy],
+        Chip8,
+        &[],
+        "Skip the next instruction if VX doesn't equal VY",
+    ),
+    entry("ANNN", "LD", &[I, Address], Chip8, &[], "Set I to NNN"),
+    entry(
+        "BXYN",
+        "COLOR",
+        &[Vx, Vy, Nibble],
+        Chip8x,
+        &[],
+        "Set foreground colors, zones from VX and VX+1 to VY",
+    ),
+    entry("BNNN", "JP", &[V0, Address], Chip8, &[], "Jump to NNN + V0"),
+    entry(
+        "CXNN",
+        "RND",
+        &[Vx, Byte],
+        Chip8,
+        &[],
+        "Set VX to a random byte AND NN",
+    ),
+    entry(
+        "DXYN",
+        "DRW",
+        &[Vx, Vy, Nibble],
+        Chip8,
+        &[
+            "horizontal_wrap",
+            "vertical_wrap",
+            "collision_row_count",
+            "display_wait",
+        ],
+        "Draw N sprite rows from I at VX, VY, VF = collision",
+    ),
+    entry(
+        "EX9E",
+        "SKP",
+        &[Vx],
+        Chip8,
+        &[],
+        "Skip the next instruction if the key in VX is down",
+    ),
+    entry(
+        "EXA1",
+        "SKNP",
+        &[Vx],
+        Chip8,
+        &[],
+        "Skip the next instruction if the key in VX is up",
+    ),
+    entry(
+        "F000",
+        "LONG",
+        &[],
+        XoChip,
+        &[],
+        "Load I with the 16-bit address in the next two bytes",
+    ),
+    entry(
+        "FN01",
+        "PLANE",
+        &[XNibble],
+        XoChip,
+        &[],
+        "Select the drawing planes, bitmask N",
+    ),
+    entry(
+        "FX07",
+        "LD",
+        &[Vx, DT],
+        Chip8,
+        &[],
+        "Set VX to the delay timer",
+    ),
+    entry(
+        "FX0A",
+        "LD",
+        &[Vx, K],
+        Chip8,
+        &[],
+        "Wait for a key press and release, then set VX to it",
+    ),
+    entry(
+        "FX15",
+        "LD",
+        &[DT, Vx],
+        Chip8,
+        &[],
+        "Set the delay timer to VX",
+    ),
+    entry(
+        "FX18",
+        "LD",
+        &[ST, Vx],
+        Chip8,
+        &[],
+        "Set the sound timer to VX",
+    ),
+    entry("FX1E", "ADD", &[I, Vx], Chip8, &[], "Add VX to I"),
+    entry(
+        "FX29",
+        "LD",
+        &[F, Vx],
+        Chip8,
+        &[],
+        "Point I at the font sprite for the digit in VX",
+    ),
+    entry(
+        "FX33",
+        "LD",
+        &[B, Vx],
+        Chip8,
+        &[],
+        "Store VX as three decimal digits at I",
+    ),
+    entry(
+        "FX3A",
+        "PITCH",
+        &[Vx],
+        XoChip,
+        &[],
+        "Set the audio pitch to VX",
+    ),
+    entry(
+        "FX55",
+        "LD",
+        &[IndirectI, Vx],
+        Chip8,
+        &["load_store_increments_i"],
+        "Store V0-VX at I",
+    ),
+    entry(
+        "FX65",
+        "LD",
+        &[Vx, IndirectI],
+        Chip8,
+        &["load_store_increments_i"],
+        "Load V0-VX from I",
+    ),
+    entry(
+        "FX75",
+        "LD",
+        &[R, Vx],
+        Schip,
+        &[],
+        "Save V0-VX to the RPL user flags",
+    ),
+    entry(
+        "FX85",
+        "LD",
+        &[Vx, R],
+        Schip,
+        &[],
+        "Load V0-VX from the RPL user flags",
+    ),
+];
+
+// The entry for `opcode`, None for an unknown opcode. Chip-8X entries only
+// count with `chip8x`.
+pub fn find(opcode: u16, chip8x: bool) -> Option<&'static OpcodeInfo> {
+    OPCODES
+        .iter()
+        .find(|info| (chip8x || info.extension != Chip8x) && info.matches(opcode))
+}
+
+// Works the mask and bits out from the pattern: hex digits are fixed, X, Y
+// and N vary
+const fn entry(
+    pattern: &'static str,
+    mnemonic: &'static str,
+    operands: &'static [OperandFormat],
+    extension: Extension,
+    quirks: &'static [&'static str],
+    description: &'static str,
+) -> OpcodeInfo {
+    let digits = pattern.as_bytes();
+    let mut mask = 0;
+    let mut bits = 0;
+    let mut index = 0;
+    while index < 4 {
+        let value = match digits[index] {
+            digit @ b'0'..=b'9' => Some(digit - b'0'),
+            digit @ b'A'..=b'F' => Some(digit - b'A' + 10),
+            _ => None,
+        };
+        mask <<= 4;
+        bits <<= 4;
+        if let Some(value) = value {
+            mask |= 0xF;
+            bits |= value as u16;
+        }
+        index += 1;
+    }
+    OpcodeInfo {
+        pattern,
+        mask,
+        bits,
+        mnemonic,
+        operands,
+        extension,
+        quirks,
+        description,
+    }
+}
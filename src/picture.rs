@@ -0,0 +1,75 @@
+// Monochrome pictures for --display-png, to put something on the display
+// without running a ROM. Decoding PNG needs the `png` feature, without it
+// loading fails with a message saying so.
+
+// Pixels of the picture as lit (light) or off (dark), row by row, with the
+// width and height
+#[cfg(feature = "png")]
+pub fn load_png(path: &str) -> Result<(Vec<bool>, usize, usize), String> {
+    let file =
+        std::fs::File::open(path).map_err(|e| format!("Failed to open '{}' : {}", path, e))?;
+    let mut decoder = png::Decoder::new(std::io::BufReader::new(file));
+    // Palettes and low bit depths become plain 8-bit gray or RGB
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| format!("Failed to read PNG '{}' : {}", path, e))?;
+    let mut buffer = vec![0; reader.output_buffer_size().unwrap_or(0)];
+    let frame = reader
+        .next_frame(&mut buffer)
+        .map_err(|e| format!("Failed to read PNG '{}' : {}", path, e))?;
+
+    let channels = frame.color_type.samples();
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let pixels = buffer[..frame.buffer_size()]
+        .chunks(frame.line_size)
+        .flat_map(|row| row.chunks(channels).take(width))
+        .map(|pixel| {
+            // Gray or RGB, alpha ignored
+            let color = &pixel[..if channels >= 3 { 3 } else { 1 }];
+            let brightness = color.iter().map(|&c| c as u32).sum::<u32>() / color.len() as u32;
+            brightness >= 128
+        })
+        .collect();
+    Ok((pixels, width, height))
+}
+
+#[cfg(not(feature = "png"))]
+pub fn load_png(_path: &str) -> Result<(Vec<bool>, usize, usize), String> {
+    Err("Loading PNG pictures needs the emulator built with the `png` feature".to_string())
+}
+
+#[cfg(all(test, feature = "png"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkerboard() {
+        let path =
+            std::env::temp_dir().join(format!("chip8-checkerboard-{}.png", std::process::id()));
+        let gray: Vec<u8> = (0..64 * 32)
+            .map(|pixel| {
+                if (pixel % 64 + pixel / 64) % 2 == 0 {
+                    255
+                } else {
+                    0
+                }
+            })
+            .collect();
+        let file = std::fs::File::create(&path).unwrap();
+        let mut encoder = png::Encoder::new(file, 64, 32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&gray)
+            .unwrap();
+
+        let (pixels, width, height) = load_png(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut chip8 = chip8::Chip8::new();
+        chip8.import_display_pixels(&pixels, width, height).unwrap();
+        let lit: Vec<bool> = gray.iter().map(|&value| value == 255).collect();
+        assert_eq!(chip8.display_grid().concat(), lit);
+    }
+}
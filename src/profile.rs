@@ -0,0 +1,142 @@
+// Counts how often each kind of instruction runs, to see where a ROM spends
+// its time. Off unless `Chip8::enable_profiling` is called.
+use crate::opcodes::{self, Extension, OPCODES};
+use std::collections::BTreeSet;
+
+// Instruction families in opcode order, the patterns from
+// `opcodes::OPCODES` and a last one that catches anything unknown. Chip-8X
+// opcodes aren't told apart, they count as what they'd be without it.
+pub const FAMILIES: [&str; FAMILY_COUNT] = {
+    let mut families = ["unknown"; FAMILY_COUNT];
+    let (mut index, mut family) = (0, 0);
+    while index < OPCODES.len() {
+        if !matches!(OPCODES[index].extension, Extension::Chip8x) {
+            families[family] = OPCODES[index].pattern;
+            family += 1;
+        }
+        index += 1;
+    }
+    families
+};
+
+const FAMILY_COUNT: usize = {
+    let (mut index, mut count) = (0, 1);
+    while index < OPCODES.len() {
+        if !matches!(OPCODES[index].extension, Extension::Chip8x) {
+            count += 1;
+        }
+        index += 1;
+    }
+    count
+};
+
+const UNKNOWN: usize = FAMILIES.len() - 1;
+
+// Index into FAMILIES for an opcode
+fn classify(opcode: u16) -> usize {
+    opcodes::find(opcode, false)
+        .and_then(|info| FAMILIES.iter().position(|&family| family == info.pattern))
+        .unwrap_or(UNKNOWN)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    counts: [u64; FAMILIES.len()],
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Profile {
+            counts: [0; FAMILIES.len()],
+        }
+    }
+
+    pub fn record(&mut self, opcode: u16) {
+        self.counts[classify(opcode)] += 1;
+    }
+
+    // Times a family from FAMILIES ran, e.g. `count("DXYN")`
+    pub fn count(&self, family: &str) -> u64 {
+        FAMILIES
+            .iter()
+            .position(|&name| name == family)
+            .map_or(0, |index| self.counts[index])
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    // Families that ran at least once, most frequent first
+    pub fn sorted(&self) -> Vec<(&'static str, u64)> {
+        let mut counts: Vec<(&'static str, u64)> = FAMILIES
+            .iter()
+            .copied()
+            .zip(self.counts)
+            .filter(|&(_, count)| count > 0)
+            .collect();
+        counts.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        counts
+    }
+
+    // Known families that ran at least once, for how much of the instruction
+    // set a test ROM exercises
+    pub fn covered(&self) -> BTreeSet<&'static str> {
+        FAMILIES[..UNKNOWN]
+            .iter()
+            .zip(self.counts)
+            .filter(|&(_, count)| count > 0)
+            .map(|(&family, _)| family)
+            .collect()
+    }
+
+    // Which families ran and which didn't, for printing on exit
+    pub fn coverage_report(&self) -> String {
+        let covered = self.covered();
+        let missing: Vec<&str> = FAMILIES[..UNKNOWN]
+            .iter()
+            .copied()
+            .filter(|family| !covered.contains(family))
+            .collect();
+        let mut report = format!(
+            "Coverage: {} of {} opcode families ({:.1}%)\n",
+            covered.len(),
+            UNKNOWN,
+            covered.len() as f64 * 100.0 / UNKNOWN as f64
+        );
+        report.push_str(&format!(
+            "Ran:     {}\n",
+            covered.into_iter().collect::<Vec<_>>().join(" ")
+        ));
+        report.push_str(&format!("Missing: {}\n", missing.join(" ")));
+        if self.counts[UNKNOWN] > 0 {
+            report.push_str(&format!(
+                "Unknown opcodes ran {} times\n",
+                self.counts[UNKNOWN]
+            ));
+        }
+        report
+    }
+
+    // Table for printing on exit
+    pub fn report(&self) -> String {
+        let total = self.total().max(1);
+        let mut report = String::from("Opcode     Count      %\n");
+        for (family, count) in self.sorted() {
+            report.push_str(&format!(
+                "{:<7} {:>8} {:>6.2}\n",
+                family,
+                count,
+                count as f64 * 100.0 / total as f64
+            ));
+        }
+        report.push_str(&format!("Total   {:>8}\n", self.total()));
+        report
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,185 @@
+// Animated GIF recording of the emulated display.
+//
+// Like audio, the real encoder needs a feature (`gif`). Without it creating a
+// recorder fails with a message saying so.
+
+#[cfg(feature = "gif")]
+use std::fs::File;
+#[cfg(feature = "gif")]
+use std::io::{BufWriter, Write};
+
+#[cfg(feature = "gif")]
+pub struct GifRecorder {
+    // None once the file has been finished
+    encoder: Option<gif::Encoder<BufWriter<File>>>,
+    path: String,
+    // Colors of the buffers being captured, by GIF palette index. They change
+    // with the display's palette, the GIF keeps the colors it started with.
+    palette: [u32; 4],
+    scale: usize,
+    // Emulator frames between captures, and the matching GIF delay in 1/100 s
+    frame_step: u32,
+    delay: u16,
+    frames_until_capture: u32,
+    // Captures left before the recording is cut off
+    frames_left: u32,
+    recording: bool,
+}
+
+#[cfg(feature = "gif")]
+impl GifRecorder {
+    pub fn create(
+        path: &str,
+        palette: [u32; 4],
+        fps: u32,
+        scale: usize,
+        max_seconds: u32,
+    ) -> Result<Self, String> {
+        let file =
+            File::create(path).map_err(|e| format!("Failed to create '{}' : {}", path, e))?;
+
+        // The GIF palette is the emulator palette, so each pixel is an index
+        let global_palette: Vec<u8> = palette
+            .iter()
+            .flat_map(|&color| [(color >> 16) as u8, (color >> 8) as u8, color as u8])
+            .collect();
+        let mut encoder = gif::Encoder::new(
+            BufWriter::new(file),
+            (64 * scale) as u16,
+            (32 * scale) as u16,
+            &global_palette,
+        )
+        .map_err(|e| format!("Failed to start GIF '{}' : {}", path, e))?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(|e| format!("Failed to start GIF '{}' : {}", path, e))?;
+
+        // The display runs at 60 Hz, so only whole steps of it can be captured
+        let frame_step = (60 / fps).max(1);
+        let delay = ((frame_step * 100 + 30) / 60) as u16;
+
+        Ok(GifRecorder {
+            encoder: Some(encoder),
+            path: path.to_string(),
+            palette,
+            scale,
+            frame_step,
+            delay,
+            frames_until_capture: 0,
+            frames_left: max_seconds * fps,
+            recording: true,
+        })
+    }
+
+    // Pauses or resumes capturing, returns whether it's now recording
+    pub fn toggle(&mut self) -> bool {
+        if self.encoder.is_some() {
+            self.recording = !self.recording;
+        }
+        self.is_recording()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording && self.encoder.is_some()
+    }
+
+    // For when the display switches palettes mid-recording
+    pub fn set_source_palette(&mut self, palette: [u32; 4]) {
+        self.palette = palette;
+    }
+
+    // Call once per emulator frame with the output of `get_display_buffer`
+    // and the display's width. The GIF stays the lores size times the scale,
+    // hires frames are fitted into it.
+    pub fn capture(&mut self, buffer: &[u32], width: usize) {
+        if !self.is_recording() {
+            return;
+        }
+        if self.frames_until_capture > 0 {
+            self.frames_until_capture -= 1;
+            return;
+        }
+        self.frames_until_capture = self.frame_step - 1;
+
+        let height = buffer.len() / width;
+        let (out_width, out_height) = (64 * self.scale, 32 * self.scale);
+        let mut pixels = Vec::with_capacity(out_width * out_height);
+        for y in 0..out_height {
+            let row = &buffer[y * height / out_height * width..][..width];
+            pixels.extend((0..out_width).map(|x| {
+                let color = row[x * width / out_width];
+                self.palette.iter().position(|&c| c == color).unwrap_or(0) as u8
+            }));
+        }
+
+        let frame = gif::Frame {
+            width: out_width as u16,
+            height: out_height as u16,
+            delay: self.delay,
+            buffer: pixels.into(),
+            ..gif::Frame::default()
+        };
+
+        let encoder = self.encoder.as_mut().unwrap();
+        if let Err(e) = encoder.write_frame(&frame) {
+            eprintln!("Failed to write GIF frame, stopping recording: {}", e);
+            self.finish();
+            return;
+        }
+
+        self.frames_left -= 1;
+        if self.frames_left == 0 {
+            println!("GIF recording reached its length limit");
+            self.finish();
+        }
+    }
+
+    // Writes the GIF trailer and flushes the file, later captures are
+    // ignored. Dropping the encoder would too, but without saying if it failed.
+    pub fn finish(&mut self) {
+        let Some(encoder) = self.encoder.take() else {
+            return;
+        };
+        let result = encoder
+            .into_inner()
+            .map_err(|e| e.to_string())
+            .and_then(|mut file| file.flush().map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => println!("Saved GIF recording to {}", self.path),
+            Err(e) => eprintln!("Failed to finish GIF '{}' : {}", self.path, e),
+        }
+    }
+}
+
+#[cfg(feature = "gif")]
+impl Drop for GifRecorder {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(not(feature = "gif"))]
+pub struct GifRecorder;
+
+#[cfg(not(feature = "gif"))]
+impl GifRecorder {
+    pub fn create(
+        _path: &str,
+        _palette: [u32; 4],
+        _fps: u32,
+        _scale: usize,
+        _max_seconds: u32,
+    ) -> Result<Self, String> {
+        Err("GIF recording needs the emulator built with the `gif` feature".to_string())
+    }
+
+    pub fn toggle(&mut self) -> bool {
+        false
+    }
+
+    pub fn set_source_palette(&mut self, _palette: [u32; 4]) {}
+
+    pub fn capture(&mut self, _buffer: &[u32], _width: usize) {}
+
+    pub fn finish(&mut self) {}
+}
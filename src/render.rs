@@ -0,0 +1,367 @@
+// Scale a logical framebuffer (e.g. 64x32) up to the window size.
+//
+// Uses nearest-neighbor so pixels stay sharp, and letterboxes with black bars
+// so the image keeps the source aspect ratio.
+pub fn scale_to_window(
+    src: &[u32],
+    src_width: usize,
+    src_height: usize,
+    dst_width: usize,
+    dst_height: usize,
+) -> Vec<u32> {
+    let mut dst = vec![0u32; dst_width * dst_height];
+
+    if src_width == 0 || src_height == 0 || dst_width == 0 || dst_height == 0 {
+        return dst;
+    }
+
+    let (offset_x, offset_y, out_width, out_height) =
+        fit(src_width, src_height, dst_width, dst_height);
+
+    for y in 0..out_height {
+        let src_y = y * src_height / out_height;
+        let src_row = &src[src_y * src_width..(src_y + 1) * src_width];
+        let dst_start = (offset_y + y) * dst_width + offset_x;
+
+        for (x, pixel) in dst[dst_start..dst_start + out_width].iter_mut().enumerate() {
+            *pixel = src_row[x * src_width / out_width];
+        }
+    }
+
+    dst
+}
+
+// Like `scale_to_window` but blends the four nearest source pixels
+// (bilinear), so edges come out soft instead of blocky
+pub fn scale_to_window_smooth(
+    src: &[u32],
+    src_width: usize,
+    src_height: usize,
+    dst_width: usize,
+    dst_height: usize,
+) -> Vec<u32> {
+    let mut dst = vec![0u32; dst_width * dst_height];
+
+    if src_width == 0 || src_height == 0 || dst_width == 0 || dst_height == 0 {
+        return dst;
+    }
+
+    let (offset_x, offset_y, out_width, out_height) =
+        fit(src_width, src_height, dst_width, dst_height);
+
+    // Source position of an output pixel's center, and the pixel after it
+    let sample = |out: usize, out_size: usize, src_size: usize| {
+        let position = ((out as f32 + 0.5) * src_size as f32 / out_size as f32 - 0.5)
+            .clamp(0.0, (src_size - 1) as f32);
+        let first = position as usize;
+        (
+            first,
+            (first + 1).min(src_size - 1),
+            position - first as f32,
+        )
+    };
+
+    for y in 0..out_height {
+        let (top, bottom, fy) = sample(y, out_height, src_height);
+        let dst_start = (offset_y + y) * dst_width + offset_x;
+
+        for (x, pixel) in dst[dst_start..dst_start + out_width].iter_mut().enumerate() {
+            let (left, right, fx) = sample(x, out_width, src_width);
+            let corners = [
+                (src[top * src_width + left], (1.0 - fx) * (1.0 - fy)),
+                (src[top * src_width + right], fx * (1.0 - fy)),
+                (src[bottom * src_width + left], (1.0 - fx) * fy),
+                (src[bottom * src_width + right], fx * fy),
+            ];
+            *pixel = [16, 8, 0].iter().fold(0, |color, &shift| {
+                let channel: f32 = corners
+                    .iter()
+                    .map(|&(corner, weight)| ((corner >> shift) & 0xFF) as f32 * weight)
+                    .sum();
+                color | (channel.round() as u32).min(0xFF) << shift
+            });
+        }
+    }
+
+    dst
+}
+
+// Where `scale_to_window` puts the image: x, y, width, height
+fn fit(
+    src_width: usize,
+    src_height: usize,
+    dst_width: usize,
+    dst_height: usize,
+) -> (usize, usize, usize, usize) {
+    // Largest size that fits the window while keeping the aspect ratio
+    let (out_width, out_height) = if dst_width * src_height <= dst_height * src_width {
+        (dst_width, dst_width * src_height / src_width)
+    } else {
+        (dst_height * src_width / src_height, dst_height)
+    };
+
+    // Center the image, the rest stays black
+    let offset_x = (dst_width - out_width) / 2;
+    let offset_y = (dst_height - out_height) / 2;
+    (offset_x, offset_y, out_width, out_height)
+}
+
+// Outline the edges of an image placed by `scale_to_window`, one window
+// pixel wide and inside the image so it's visible without letterboxing
+pub fn draw_border(
+    dst: &mut [u32],
+    src_width: usize,
+    src_height: usize,
+    dst_width: usize,
+    dst_height: usize,
+    color: u32,
+) {
+    if src_width == 0 || src_height == 0 || dst_width == 0 || dst_height == 0 {
+        return;
+    }
+    let (left, top, width, height) = fit(src_width, src_height, dst_width, dst_height);
+    if width == 0 || height == 0 {
+        return;
+    }
+    let (right, bottom) = (left + width - 1, top + height - 1);
+
+    for x in left..=right {
+        dst[top * dst_width + x] = color;
+        dst[bottom * dst_width + x] = color;
+    }
+    for y in top..=bottom {
+        dst[y * dst_width + left] = color;
+        dst[y * dst_width + right] = color;
+    }
+}
+
+// Darken every other row like the gaps between a CRT's scanlines. Runs on
+// the scaled output, each channel drops to 5/8 with shifts only.
+pub fn apply_scanlines(dst: &mut [u32], dst_width: usize) {
+    if dst_width == 0 {
+        return;
+    }
+    for row in dst.chunks_mut(dst_width).skip(1).step_by(2) {
+        for pixel in row {
+            let half = (*pixel >> 1) & 0x7F7F7F;
+            let eighth = (*pixel >> 3) & 0x1F1F1F;
+            *pixel = half + eighth;
+        }
+    }
+}
+
+// Faint lines between the source pixels of an image placed by
+// `scale_to_window`, for counting pixels. `color` is blended in at `opacity`
+// (0.0 to 1.0). Lines fall on the first window pixel of each source pixel,
+// so they only show once a source pixel is a few window pixels wide.
+pub fn draw_grid(
+    dst: &mut [u32],
+    src_width: usize,
+    src_height: usize,
+    dst_width: usize,
+    dst_height: usize,
+    color: u32,
+    opacity: f32,
+) {
+    if src_width == 0 || src_height == 0 || dst_width == 0 || dst_height == 0 {
+        return;
+    }
+    let (left, top, width, height) = fit(src_width, src_height, dst_width, dst_height);
+    let blend = |pixel: &mut u32| {
+        *pixel = [16, 8, 0].iter().fold(0, |blended, &shift| {
+            let under = ((*pixel >> shift) & 0xFF) as f32;
+            let over = ((color >> shift) & 0xFF) as f32;
+            let channel = under + (over - under) * opacity;
+            blended | (channel.round() as u32).min(0xFF) << shift
+        });
+    };
+
+    // Each boundary once, where `scale_to_window` starts the next source pixel
+    for column in 1..src_width {
+        let x = left + (column * width).div_ceil(src_width);
+        for y in top..top + height {
+            blend(&mut dst[y * dst_width + x]);
+        }
+    }
+    for row in 1..src_height {
+        let y = top + (row * height).div_ceil(src_height);
+        for x in left..left + width {
+            blend(&mut dst[y * dst_width + x]);
+        }
+    }
+}
+
+// Draw a string of hex digits into a framebuffer using the CHIP-8 font.
+// Each font pixel becomes a `scale` x `scale` block. Anything that isn't a
+// hex digit is left as a gap.
+pub fn draw_text(
+    buffer: &mut [u32],
+    width: usize,
+    x: usize,
+    y: usize,
+    text: &str,
+    scale: usize,
+    color: u32,
+) {
+    let height = buffer.len() / width;
+
+    for (i, c) in text.chars().enumerate() {
+        let Some(digit) = c.to_digit(16) else {
+            continue;
+        };
+        let glyph = &chip8::FONT[digit as usize * 5..digit as usize * 5 + 5];
+        // 4 pixels wide plus 1 pixel spacing
+        let glyph_x = x + i * 5 * scale;
+
+        for (row, &bits) in glyph.iter().enumerate() {
+            for col in 0..4 {
+                if bits & (0x80 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = glyph_x + col * scale + dx;
+                        let py = y + row * scale + dy;
+                        if px < width && py < height {
+                            buffer[py * width + px] = color;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// 3x5 glyphs for `draw_label`, one row per byte with the low three bits
+// used. Covers what file names and status words need, the CHIP-8 font only
+// has hex digits.
+fn label_glyph(c: char) -> Option<[u8; 5]> {
+    let glyph = match c.to_ascii_uppercase() {
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        _ => return None,
+    };
+    Some(glyph)
+}
+
+// Like `draw_text` but for any text, with the small font from
+// `label_glyph`. Characters it doesn't have are left as gaps.
+pub fn draw_label(
+    buffer: &mut [u32],
+    width: usize,
+    x: usize,
+    y: usize,
+    text: &str,
+    scale: usize,
+    color: u32,
+) {
+    let height = buffer.len() / width;
+
+    for (i, c) in text.chars().enumerate() {
+        let Some(glyph) = label_glyph(c) else {
+            continue;
+        };
+        // 3 pixels wide plus 1 pixel spacing
+        let glyph_x = x + i * 4 * scale;
+
+        for (row, &bits) in glyph.iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = glyph_x + col * scale + dx;
+                        let py = y + row * scale + dy;
+                        if px < width && py < height {
+                            buffer[py * width + px] = color;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Chip-8 keys as laid out on the COSMAC VIP's keypad, row by row
+const KEYPAD_ROWS: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+// The 16 keys as a 4x4 grid in the top right corner, the ones held down
+// filled in with `color`, to check what a game sees of the keyboard. Each
+// cell is 7x7 `scale` blocks around its label.
+pub fn draw_keypad(buffer: &mut [u32], width: usize, keys: [bool; 16], scale: usize, color: u32) {
+    let height = buffer.len() / width;
+    let cell = 7 * scale;
+    let step = cell + scale;
+    let Some(left) = width.checked_sub(4 * step) else {
+        return;
+    };
+
+    for (row, keys_in_row) in KEYPAD_ROWS.iter().enumerate() {
+        for (column, &key) in keys_in_row.iter().enumerate() {
+            let (x, y) = (left + column * step, scale + row * step);
+            let pressed = keys[key as usize];
+            let (fill, label) = if pressed {
+                (color, 0x000000)
+            } else {
+                (0x303030, 0x808080)
+            };
+            for py in y..(y + cell).min(height) {
+                buffer[py * width + x..py * width + x + cell].fill(fill);
+            }
+            draw_label(
+                buffer,
+                width,
+                x + 2 * scale,
+                y + scale,
+                &format!("{:X}", key),
+                scale,
+                label,
+            );
+        }
+    }
+}
@@ -0,0 +1,69 @@
+// Scripted keypad input for headless runs (--input). One event per line:
+//
+//     <frame> <key> down|up
+//
+// with the key as a hex digit. Events apply before that frame's instructions
+// run, so the same file always gives the same run. `#` starts a comment.
+use chip8::Chip8;
+use std::fs;
+
+pub struct InputReplay {
+    // Frame, key, pressed, in frame order
+    events: Vec<(u32, u8, bool)>,
+    next: usize,
+}
+
+impl InputReplay {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read input file '{}' : {}", path, e))?;
+
+        let mut events = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let event = parse_event(line).ok_or_else(|| {
+                format!(
+                    "{}:{}: expected '<frame> <key> down|up', got '{}'",
+                    path,
+                    number + 1,
+                    line
+                )
+            })?;
+            events.push(event);
+        }
+        // Keep the file's order for events on the same frame
+        events.sort_by_key(|&(frame, _, _)| frame);
+
+        Ok(InputReplay { events, next: 0 })
+    }
+
+    // Call at the start of each frame, counting from 0
+    pub fn apply(&mut self, frame: u32, chip8: &mut Chip8) {
+        while let Some(&(event_frame, key, pressed)) = self.events.get(self.next) {
+            if event_frame > frame {
+                break;
+            }
+            chip8.set_key(key, pressed);
+            self.next += 1;
+        }
+    }
+}
+
+fn parse_event(line: &str) -> Option<(u32, u8, bool)> {
+    let mut parts = line.split_whitespace();
+    let frame = parts.next()?.parse().ok()?;
+    let key = parts.next().filter(|key| key.len() == 1)?;
+    let key = u8::from_str_radix(key, 16).ok()?;
+    let pressed = match parts.next()? {
+        "down" => true,
+        "up" => false,
+        _ => return None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((frame, key, pressed))
+}
@@ -0,0 +1,77 @@
+// Settings remembered per ROM, so a game tuned once stays tuned. Anything
+// given on the command line is stored for that ROM and used on later runs
+// without it, and the palette and inversion are stored when changed in the
+// window. One small JSON file per ROM under the user's config directory.
+use chip8::Quirks;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct RomSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hz: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quirks: Option<Quirks>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub palette: Option<[u32; 4]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invert: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_profile: Option<String>,
+}
+
+impl RomSettings {
+    // Takes every setting `other` has, returns whether anything changed
+    pub fn update(&mut self, other: &RomSettings) -> bool {
+        let before = self.clone();
+        self.hz = other.hz.or(self.hz);
+        self.quirks = other.quirks.or(self.quirks);
+        self.palette = other.palette.or(self.palette);
+        self.invert = other.invert.or(self.invert);
+        self.input_profile = other.input_profile.clone().or(self.input_profile.take());
+        *self != before
+    }
+}
+
+// $XDG_CONFIG_HOME/chip8/roms/<rom name>.json, falling back to ~/.config
+pub fn settings_path(rom_path: &str) -> Option<PathBuf> {
+    let name = Path::new(rom_path).file_stem()?;
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(
+        config_dir
+            .join("chip8")
+            .join("roms")
+            .join(name)
+            .with_extension("json"),
+    )
+}
+
+// A ROM without a file yet has nothing stored, so the defaults apply
+pub fn load(path: &Path) -> RomSettings {
+    let Ok(json) = fs::read_to_string(path) else {
+        return RomSettings::default();
+    };
+    serde_json::from_str(&json).unwrap_or_else(|e| {
+        eprintln!(
+            "Ignoring unreadable ROM settings '{}' : {}",
+            path.display(),
+            e
+        );
+        RomSettings::default()
+    })
+}
+
+pub fn save(path: &Path, settings: &RomSettings) {
+    let json = serde_json::to_string_pretty(settings).unwrap();
+    let result = fs::create_dir_all(path.parent().unwrap()).and_then(|_| fs::write(path, json));
+    if let Err(e) = result {
+        eprintln!(
+            "Failed to save ROM settings to '{}' : {}",
+            path.display(),
+            e
+        );
+    }
+}
@@ -0,0 +1,64 @@
+// --watch: reloads the ROM when its file changes on disk, for working on a
+// game: reassemble it and the emulator starts it over. Polls the file's
+// modification time and waits for it to settle, so a file written in
+// several steps is loaded once, after the last one.
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+// How often the file is looked at
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+// How long the file has to stay unchanged before it's reloaded
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+pub struct RomWatch {
+    path: PathBuf,
+    // None while the file can't be read, a file that comes back counts as
+    // changed
+    modified: Option<SystemTime>,
+    last_check: Instant,
+    // When the last change not yet reported was seen
+    changed_at: Option<Instant>,
+}
+
+impl RomWatch {
+    pub fn new(path: &str) -> Self {
+        let path = PathBuf::from(path);
+        RomWatch {
+            modified: modified(&path),
+            path,
+            last_check: Instant::now(),
+            changed_at: None,
+        }
+    }
+
+    // True once after the file changed and then stayed the same for a while
+    pub fn poll(&mut self) -> bool {
+        if self.last_check.elapsed() < CHECK_INTERVAL {
+            return false;
+        }
+        self.last_check = Instant::now();
+
+        let modified = modified(&self.path);
+        if modified != self.modified {
+            self.modified = modified;
+            self.changed_at = Some(Instant::now());
+            return false;
+        }
+        // Gone or still being replaced, wait for it to come back
+        if modified.is_none() {
+            return false;
+        }
+        match self.changed_at {
+            Some(changed_at) if changed_at.elapsed() >= SETTLE_TIME => {
+                self.changed_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
@@ -0,0 +1,40 @@
+// Keeps SCHIP RPL flags (used for high scores) between sessions, one small
+// file per ROM under the user's data directory.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// $XDG_DATA_HOME/chip8/rpl/<rom name>.rpl, falling back to ~/.local/share
+pub fn flags_path(rom_path: &str) -> Option<PathBuf> {
+    let name = Path::new(rom_path).file_stem()?;
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+    Some(
+        data_dir
+            .join("chip8")
+            .join("rpl")
+            .join(name)
+            .with_extension("rpl"),
+    )
+}
+
+// Missing or damaged files give zeroed flags
+pub fn load(path: &Path) -> [u8; 16] {
+    match fs::read(path) {
+        Ok(bytes) => bytes.try_into().unwrap_or_else(|_| {
+            eprintln!(
+                "Ignoring corrupt RPL flags file '{}', starting from zero",
+                path.display()
+            );
+            [0; 16]
+        }),
+        Err(_) => [0; 16],
+    }
+}
+
+pub fn save(path: &Path, flags: [u8; 16]) {
+    let result = fs::create_dir_all(path.parent().unwrap()).and_then(|_| fs::write(path, flags));
+    if let Err(e) = result {
+        eprintln!("Failed to save RPL flags to '{}' : {}", path.display(), e);
+    }
+}
@@ -0,0 +1,96 @@
+// --self-test: a quick check that a build works, with nothing to download.
+// Assembles a small program that runs a handful of instructions, checks
+// their results and draws "OK" if they were all right (an "X" if not), then
+// compares the framebuffer hash with the one a correct build gives.
+use chip8::{Chip8, asm};
+use std::process::ExitCode;
+
+const SOURCE: &str = "
+        CLS
+        LD V0, 7
+        LD V1, 5
+        ADD V0, V1
+        SE V0, 12
+        JP fail
+        LD V5, 2
+        SUB V0, V5
+        SNE V0, 10
+        JP arithmetic_ok
+        JP fail
+arithmetic_ok:
+        CALL check_bcd
+        LD V2, 20
+        LD V3, 12
+        LD I, letter_o
+        ; Drawing twice erases it and reports the collision
+        DRW V2, V3, 8
+        DRW V2, V3, 8
+        SE VF, 1
+        JP fail
+        DRW V2, V3, 8
+        ADD V2, 12
+        LD I, letter_k
+        DRW V2, V3, 8
+done:
+        JP done
+
+check_bcd:
+        LD V4, 123
+        LD I, scratch
+        LD B, V4
+        LD V2, [I]
+        SE V0, 1
+        JP fail
+        SE V1, 2
+        JP fail
+        SE V2, 3
+        JP fail
+        RET
+
+fail:
+        CLS
+        LD V2, 28
+        LD V3, 12
+        LD I, letter_x
+        DRW V2, V3, 8
+failed:
+        JP failed
+
+letter_o:
+        DB #3C, #66, #66, #66, #66, #66, #3C, #00
+letter_k:
+        DB #66, #6C, #78, #70, #78, #6C, #66, #00
+letter_x:
+        DB #66, #66, #3C, #18, #3C, #66, #66, #00
+scratch:
+        DB 0, 0, 0
+";
+
+// Frames to run, plenty for the program to reach its final loop
+const FRAMES: usize = 10;
+
+// Framebuffer hash of "OK", the display a correct build ends up with
+const EXPECTED_HASH: u64 = 0xB236B9B16A978403;
+
+pub fn run() -> ExitCode {
+    let program = asm::assemble(SOURCE).expect("self-test program doesn't assemble");
+    let mut chip8 = Chip8::new();
+    if let Err(e) = chip8.load_program(&program) {
+        eprintln!("Self-test failed to load its program : {}", e);
+        return ExitCode::FAILURE;
+    }
+    chip8.run_frames(FRAMES);
+    chip8.print_display();
+
+    let hash = chip8.framebuffer_hash();
+    if hash == EXPECTED_HASH {
+        println!("Self-test passed");
+        ExitCode::SUCCESS
+    } else {
+        eprintln!(
+            "Self-test failed: framebuffer hash {:016x}, expected {:016x}",
+            hash, EXPECTED_HASH
+        );
+        ExitCode::FAILURE
+    }
+}
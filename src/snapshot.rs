@@ -0,0 +1,369 @@
+// Saved machine state, for save files and for comparing two runs.
+//
+// Settings (quirks, palette, seed) and the keypad aren't part of it, they
+// belong to whoever restores the snapshot.
+use crate::chip8::Chip8xColors;
+use crate::error::Chip8Error;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub memory: Vec<u8>,
+    pub registers: [u8; 16],
+    pub pc: u16,
+    pub i: u16,
+    // Return addresses in use, oldest first
+    pub stack: Vec<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    // One packed 64x32 bitmap per plane, 128x64 with `hires`, 8 pixels per
+    // byte
+    pub display: Vec<Vec<u8>>,
+    #[serde(default)]
+    pub hires: bool,
+    pub selected_planes: u8,
+    // Register FX0A stores the key in, if it's waiting for one
+    pub waiting_for_key: Option<usize>,
+    pub halted: bool,
+    // Missing from snapshots saved before it was tracked
+    #[serde(default)]
+    pub cycle_count: u64,
+    #[serde(default)]
+    pub rpl: [u8; 16],
+    #[serde(default = "default_pitch")]
+    pub pitch: u8,
+    #[serde(default)]
+    pub chip8x_colors: Chip8xColors,
+}
+
+fn default_pitch() -> u8 {
+    64
+}
+
+impl Snapshot {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Chip8Error> {
+        let snapshot: Snapshot =
+            serde_json::from_str(json).map_err(|e| Chip8Error::BadSnapshot(e.to_string()))?;
+        let (width, height) = snapshot.display_dimensions();
+        if snapshot.display.len() != 2
+            || snapshot
+                .display
+                .iter()
+                .any(|plane| plane.len() != width * height / 8)
+        {
+            return Err(Chip8Error::BadSnapshot(format!(
+                "the display isn't two {}x{} planes",
+                width, height
+            )));
+        }
+        Ok(snapshot)
+    }
+
+    // Width and height of the display it holds
+    pub fn display_dimensions(&self) -> (usize, usize) {
+        if self.hires { (128, 64) } else { (64, 32) }
+    }
+}
+
+// Just what's on screen, for visual regression tests where a whole
+// machine snapshot is more than needed
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DisplaySnapshot {
+    pub width: usize,
+    pub height: usize,
+    // One packed bitmap per plane, 8 pixels per byte, row by row
+    pub planes: Vec<Vec<u8>>,
+}
+
+impl DisplaySnapshot {
+    // Bits of the planes the pixel is lit in, the palette index
+    pub fn color(&self, x: usize, y: usize) -> u8 {
+        let pixel = y * self.width + x;
+        self.planes
+            .iter()
+            .enumerate()
+            .map(|(plane, bits)| ((bits[pixel / 8] >> (7 - pixel % 8)) & 1) << plane)
+            .sum()
+    }
+
+    // Pixels that differ between the two, as (x, y). Everything differs
+    // when the resolutions do.
+    pub fn diff(&self, other: &DisplaySnapshot) -> Vec<(usize, usize)> {
+        let all = (0..self.height).flat_map(|y| (0..self.width).map(move |x| (x, y)));
+        if (self.width, self.height) != (other.width, other.height) {
+            return all.collect();
+        }
+        all.filter(|&(x, y)| self.color(x, y) != other.color(x, y))
+            .collect()
+    }
+
+    // FNV-1a over the plane bytes. Fixed constants rather than std's seeded
+    // hasher, so the same screen gives the same value on every run and machine.
+    pub fn fnv_hash(&self) -> u64 {
+        let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+        for &byte in self.planes.iter().flatten() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01B3);
+        }
+        hash
+    }
+
+    // Run-length encoded for test fixtures: (count, color) byte pairs over
+    // the pixels row by row, color being the plane bits from `color`. Runs
+    // are at most 255 pixels long.
+    pub fn to_rle(&self) -> Vec<u8> {
+        let mut rle: Vec<u8> = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let color = self.color(x, y);
+                match rle.len() {
+                    len if len >= 2 && rle[len - 1] == color && rle[len - 2] < 255 => {
+                        rle[len - 2] += 1
+                    }
+                    _ => rle.extend([1, color]),
+                }
+            }
+        }
+        rle
+    }
+
+    // The other way from `to_rle`, for a display of the given size with
+    // `planes` planes. The data has to cover it exactly.
+    pub fn from_rle(
+        rle: &[u8],
+        width: usize,
+        height: usize,
+        planes: usize,
+    ) -> Result<Self, String> {
+        if !rle.len().is_multiple_of(2) {
+            return Err("RLE data has an odd number of bytes".to_string());
+        }
+        let size = width * height;
+        let mut bits = vec![vec![0; size / 8]; planes];
+        let mut pixel = 0;
+        for run in rle.chunks(2) {
+            let (count, color) = (run[0] as usize, run[1]);
+            if color >> planes != 0 {
+                return Err(format!("invalid color {} in RLE data", color));
+            }
+            if pixel + count > size {
+                return Err("RLE data runs past the end of the screen".to_string());
+            }
+            for pixel in pixel..pixel + count {
+                for (plane, bits) in bits.iter_mut().enumerate() {
+                    if color & (1 << plane) != 0 {
+                        bits[pixel / 8] |= 0x80 >> (pixel % 8);
+                    }
+                }
+            }
+            pixel += count;
+        }
+        if pixel != size {
+            return Err(format!(
+                "RLE data covers {} pixels, the screen has {}",
+                pixel, size
+            ));
+        }
+        Ok(DisplaySnapshot {
+            width,
+            height,
+            planes: bits,
+        })
+    }
+}
+
+// Every difference between two snapshots, one line each
+pub fn diff_states(a: &Snapshot, b: &Snapshot) -> Vec<String> {
+    let mut differences = Vec::new();
+
+    if a.pc != b.pc {
+        differences.push(format!(
+            "PC: {:#05X} -> {:#05X} ({:+})",
+            a.pc,
+            b.pc,
+            b.pc as i32 - a.pc as i32
+        ));
+    }
+    if a.i != b.i {
+        differences.push(format!("I: {:#05X} -> {:#05X}", a.i, b.i));
+    }
+    for (register, (old, new)) in a.registers.iter().zip(&b.registers).enumerate() {
+        if old != new {
+            differences.push(format!("V{:X}: {:#04X} -> {:#04X}", register, old, new));
+        }
+    }
+    if a.stack != b.stack {
+        differences.push(format!("stack: {:03X?} -> {:03X?}", a.stack, b.stack));
+    }
+    if a.delay_timer != b.delay_timer {
+        differences.push(format!("DT: {} -> {}", a.delay_timer, b.delay_timer));
+    }
+    if a.sound_timer != b.sound_timer {
+        differences.push(format!("ST: {} -> {}", a.sound_timer, b.sound_timer));
+    }
+    if a.pitch != b.pitch {
+        differences.push(format!("pitch: {} -> {}", a.pitch, b.pitch));
+    }
+    if a.selected_planes != b.selected_planes {
+        differences.push(format!(
+            "planes: {:#04b} -> {:#04b}",
+            a.selected_planes, b.selected_planes
+        ));
+    }
+    if a.waiting_for_key != b.waiting_for_key {
+        differences.push(format!(
+            "waiting for key: {:?} -> {:?}",
+            a.waiting_for_key, b.waiting_for_key
+        ));
+    }
+    if a.cycle_count != b.cycle_count {
+        differences.push(format!(
+            "cycle: {} -> {} ({:+})",
+            a.cycle_count,
+            b.cycle_count,
+            b.cycle_count as i128 - a.cycle_count as i128
+        ));
+    }
+    for (flag, (old, new)) in a.rpl.iter().zip(&b.rpl).enumerate() {
+        if old != new {
+            differences.push(format!("RPL {:X}: {:#04X} -> {:#04X}", flag, old, new));
+        }
+    }
+    if a.halted != b.halted {
+        differences.push(format!("halted: {} -> {}", a.halted, b.halted));
+    }
+
+    if a.memory.len() != b.memory.len() {
+        differences.push(format!(
+            "memory size: {} -> {}",
+            a.memory.len(),
+            b.memory.len()
+        ));
+    }
+    for (address, (old, new)) in a.memory.iter().zip(&b.memory).enumerate() {
+        if old != new {
+            differences.push(format!(
+                "memory {:#05X}: {:#04X} -> {:#04X}",
+                address, old, new
+            ));
+        }
+    }
+
+    if a.hires != b.hires {
+        let (old, new) = (a.display_dimensions(), b.display_dimensions());
+        differences.push(format!(
+            "resolution: {}x{} -> {}x{}",
+            old.0, old.1, new.0, new.1
+        ));
+    }
+    // Snapshots built in code rather than read with `from_json` may not
+    // have the planes a machine would give them
+    if a.display.len() != b.display.len() {
+        differences.push(format!(
+            "display planes: {} -> {}",
+            a.display.len(),
+            b.display.len()
+        ));
+    }
+    let (width, _) = a.display_dimensions();
+    for (plane, (old, new)) in a.display.iter().zip(&b.display).enumerate() {
+        if old.len() != new.len() {
+            differences.push(format!(
+                "display plane {} size: {} -> {} bytes",
+                plane,
+                old.len(),
+                new.len()
+            ));
+            continue;
+        }
+        let changed: Vec<(usize, usize)> = old
+            .iter()
+            .zip(new)
+            .enumerate()
+            .flat_map(|(byte, (old, new))| {
+                (0..8)
+                    .filter(move |bit| (old ^ new) & (0x80 >> bit) != 0)
+                    .map(move |bit| byte * 8 + bit)
+            })
+            .map(|pixel| (pixel % width, pixel / width))
+            .collect();
+        if !changed.is_empty() {
+            let (x, y) = changed[0];
+            differences.push(format!(
+                "display plane {}: {} pixels differ, first at ({}, {})",
+                plane,
+                changed.len(),
+                x,
+                y
+            ));
+        }
+    }
+
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chip8;
+
+    #[test]
+    fn diff_one_register() {
+        let mut chip8 = Chip8::new();
+        let before = chip8.snapshot();
+        chip8.set_register(3, 0x2A);
+        assert_eq!(
+            diff_states(&before, &chip8.snapshot()),
+            ["V3: 0x00 -> 0x2A"]
+        );
+    }
+
+    #[test]
+    fn diff_display() {
+        let mut chip8 = Chip8::new();
+        let before = chip8.snapshot();
+        // The font's 0 at 10, 3
+        chip8.set_register(0, 10);
+        chip8.set_register(1, 3);
+        chip8.set_index(0x050);
+        chip8.execute_opcode(0xD015).unwrap();
+        let after = chip8.snapshot();
+        let differences = diff_states(&before, &after);
+        assert_eq!(
+            differences.last().unwrap(),
+            "display plane 0: 14 pixels differ, first at (10, 3)"
+        );
+    }
+
+    #[test]
+    fn diff_display_of_another_size() {
+        let before = Chip8::new().snapshot();
+        let mut after = before.clone();
+        after.display[1].truncate(8);
+        after.display.pop();
+        assert_eq!(diff_states(&before, &after), ["display planes: 2 -> 1"]);
+        after.display[0].truncate(8);
+        assert_eq!(
+            diff_states(&before, &after),
+            [
+                "display planes: 2 -> 1",
+                "display plane 0 size: 256 -> 8 bytes"
+            ]
+        );
+    }
+
+    #[test]
+    fn from_json_checks_the_display() {
+        let mut snapshot = Chip8::new().snapshot();
+        assert_eq!(Snapshot::from_json(&snapshot.to_json()).unwrap(), snapshot);
+        snapshot.display[1].truncate(8);
+        assert!(matches!(
+            Snapshot::from_json(&snapshot.to_json()),
+            Err(Chip8Error::BadSnapshot(_))
+        ));
+    }
+}
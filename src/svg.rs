@@ -0,0 +1,55 @@
+// --svg: the display at the end of a run as an SVG picture, for articles
+// and docs about a ROM where a PNG would blur when zoomed. One square per
+// lit pixel on a background rectangle, in the palette's colors. Writing it
+// needs the `svg` feature, without it the export fails with a message
+// saying so.
+use chip8::Chip8;
+
+// Side of one CHIP-8 pixel in SVG units
+#[cfg(feature = "svg")]
+const PIXEL_SIZE: usize = 10;
+
+#[cfg(feature = "svg")]
+pub fn display_svg(chip8: &Chip8) -> Result<String, String> {
+    use std::fmt::Write;
+
+    let grid = chip8.display_grid();
+    let (width, height) = (grid[0].len(), grid.len());
+    // Palette entries for off and for lit in plane 1, swapped over by F10
+    let invert = if chip8.invert_display() { 0b11 } else { 0 };
+    let off = chip8.palette()[invert];
+    let on = chip8.palette()[1 ^ invert];
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" shape-rendering=\"crispEdges\">\n",
+        width * PIXEL_SIZE,
+        height * PIXEL_SIZE
+    );
+    writeln!(
+        svg,
+        "<rect width=\"100%\" height=\"100%\" fill=\"#{:06X}\"/>",
+        off
+    )
+    .unwrap();
+    writeln!(svg, "<g fill=\"#{:06X}\">", on).unwrap();
+    for (y, row) in grid.iter().enumerate() {
+        for (x, _) in row.iter().enumerate().filter(|(_, lit)| **lit) {
+            writeln!(
+                svg,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/>",
+                x * PIXEL_SIZE,
+                y * PIXEL_SIZE,
+                PIXEL_SIZE,
+                PIXEL_SIZE
+            )
+            .unwrap();
+        }
+    }
+    svg.push_str("</g>\n</svg>\n");
+    Ok(svg)
+}
+
+#[cfg(not(feature = "svg"))]
+pub fn display_svg(_chip8: &Chip8) -> Result<String, String> {
+    Err("Writing SVG pictures needs the emulator built with the `svg` feature".to_string())
+}
@@ -0,0 +1,253 @@
+// Runs the emulator inside the terminal instead of a window, for SSH and
+// other places without a display.
+//
+// Needs the `terminal` feature (crossterm). Two display rows share a text line
+// through half-block characters, so the screen fits in 64x16 columns/lines.
+
+#[cfg(feature = "terminal")]
+use crate::writelog::WriteLog;
+#[cfg(feature = "terminal")]
+use crate::{Config, clocks_for, report_fault, run_cycles};
+use chip8::Chip8;
+#[cfg(feature = "terminal")]
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+#[cfg(feature = "terminal")]
+use crossterm::{cursor, execute, queue, style, terminal};
+#[cfg(feature = "terminal")]
+use std::io::{self, Write};
+use std::process::ExitCode;
+#[cfg(feature = "terminal")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "terminal")]
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// Most terminals only report presses, so a key counts as held for this many
+// frames after its last press or auto-repeat
+#[cfg(feature = "terminal")]
+const HOLD_FRAMES: u8 = 8;
+
+// Same layout as the window frontend
+#[cfg(feature = "terminal")]
+const KEYMAP: [(char, u8); 16] = [
+    ('1', 0x1),
+    ('2', 0x2),
+    ('3', 0x3),
+    ('4', 0xC),
+    ('q', 0x4),
+    ('w', 0x5),
+    ('e', 0x6),
+    ('r', 0xD),
+    ('a', 0x7),
+    ('s', 0x8),
+    ('d', 0x9),
+    ('f', 0xE),
+    ('z', 0xA),
+    ('x', 0x0),
+    ('c', 0xB),
+    ('v', 0xF),
+];
+
+// Puts the terminal back the way it was, also when unwinding from a panic
+#[cfg(feature = "terminal")]
+struct TerminalGuard {
+    enhanced_keys: bool,
+}
+
+#[cfg(feature = "terminal")]
+impl TerminalGuard {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(
+            io::stdout(),
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            terminal::Clear(terminal::ClearType::All)
+        )?;
+
+        // Terminals that support it tell us when keys are released
+        let enhanced_keys = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced_keys {
+            execute!(
+                io::stdout(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(TerminalGuard { enhanced_keys })
+    }
+}
+
+#[cfg(feature = "terminal")]
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        if self.enhanced_keys {
+            let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+#[cfg(feature = "terminal")]
+// `hz` is the ROM's speed, from its settings or the command line
+pub fn run(
+    config: &Config,
+    hz: u32,
+    chip8: &mut Chip8,
+    write_log: &mut Option<WriteLog>,
+) -> ExitCode {
+    let result = run_loop(config, hz, chip8, write_log);
+
+    // The guard is gone by now, so messages land on the normal screen
+    if let Err(e) = result {
+        eprintln!("Terminal error: {}", e);
+        return ExitCode::FAILURE;
+    }
+    report_fault(chip8).unwrap_or(ExitCode::SUCCESS)
+}
+
+#[cfg(feature = "terminal")]
+fn run_loop(
+    config: &Config,
+    hz: u32,
+    chip8: &mut Chip8,
+    write_log: &mut Option<WriteLog>,
+) -> io::Result<()> {
+    let guard = TerminalGuard::enter()?;
+    let mut stdout = io::stdout();
+
+    let (mut clock, mut timer_clock) = clocks_for(config, hz);
+    let mut held = [0u8; 16];
+    let mut last_frame = String::new();
+    let mut next_frame = Instant::now();
+
+    loop {
+        while event::poll(Duration::ZERO)? {
+            match event::read()? {
+                Event::Key(key) => {
+                    let quit = key.code == KeyCode::Esc
+                        || (key.code == KeyCode::Char('c')
+                            && key.modifiers.contains(KeyModifiers::CONTROL));
+                    if quit {
+                        return Ok(());
+                    }
+
+                    let KeyCode::Char(c) = key.code else {
+                        continue;
+                    };
+                    let Some(&(_, chip8_key)) =
+                        KEYMAP.iter().find(|(k, _)| *k == c.to_ascii_lowercase())
+                    else {
+                        continue;
+                    };
+                    held[chip8_key as usize] = match key.kind {
+                        KeyEventKind::Release => 0,
+                        // Stays down until the release comes in
+                        _ if guard.enhanced_keys => u8::MAX,
+                        _ => HOLD_FRAMES,
+                    };
+                }
+                // Redraw everything at the new size
+                Event::Resize(..) => {
+                    queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
+                    last_frame.clear();
+                }
+                _ => {}
+            }
+        }
+
+        for (key, frames) in held.iter_mut().enumerate() {
+            chip8.set_key(key as u8, *frames > 0);
+            if !guard.enhanced_keys {
+                *frames = frames.saturating_sub(1);
+            }
+        }
+
+        let cycles = clock.ticks_elapsed();
+        run_cycles(chip8, cycles, config.skip_idle, &mut None, &mut None);
+        if let Some(log) = write_log {
+            log.record(chip8);
+        }
+        chip8.update_timers_from(&mut timer_clock);
+        if chip8.fault().is_some() {
+            return Ok(());
+        }
+
+        let text = chip8.render_text();
+        if text != last_frame {
+            draw(&mut stdout, &text, &last_frame)?;
+            last_frame = text;
+        }
+
+        next_frame += FRAME;
+        let now = Instant::now();
+        if next_frame > now {
+            std::thread::sleep(next_frame - now);
+        } else {
+            // Fell behind, don't try to catch up
+            next_frame = now;
+        }
+    }
+}
+
+// Only the lines that differ from `previous` are printed, so a mostly still
+// screen costs little over a slow connection. An empty `previous` (first
+// frame, resize) redraws everything, and so does a change of display mode.
+#[cfg(feature = "terminal")]
+fn draw(stdout: &mut io::Stdout, text: &str, previous: &str) -> io::Result<()> {
+    let (columns, lines) = terminal::size()?;
+    let rows: Vec<&[u8]> = text.lines().map(str::as_bytes).collect();
+    let mut previous_rows: Vec<&[u8]> = previous.lines().map(str::as_bytes).collect();
+    // Two pixel rows to a line
+    let (width, height) = (rows.first().map_or(0, |row| row.len()), rows.len() / 2);
+
+    if (columns as usize) < width || (lines as usize) < height {
+        queue!(
+            stdout,
+            cursor::MoveTo(0, 0),
+            terminal::Clear(terminal::ClearType::All),
+            style::Print(format!("Terminal too small, needs {}x{}", width, height))
+        )?;
+        return stdout.flush();
+    }
+    if previous_rows.len() != rows.len() {
+        queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
+        previous_rows.clear();
+    }
+
+    for (line_index, pair) in rows.chunks(2).enumerate() {
+        let first_row = line_index * 2;
+        if previous_rows.get(first_row..first_row + 2) == Some(pair) {
+            continue;
+        }
+        let line: String = (0..width)
+            .map(|x| match (pair[0][x] == b'#', pair[1][x] == b'#') {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            })
+            .collect();
+        queue!(
+            stdout,
+            cursor::MoveTo(0, line_index as u16),
+            style::Print(line)
+        )?;
+    }
+    stdout.flush()
+}
+
+#[cfg(not(feature = "terminal"))]
+pub fn run(
+    _config: &crate::Config,
+    _hz: u32,
+    _chip8: &mut Chip8,
+    _write_log: &mut Option<crate::writelog::WriteLog>,
+) -> ExitCode {
+    eprintln!("Terminal mode needs the emulator built with the `terminal` feature");
+    ExitCode::FAILURE
+}
@@ -0,0 +1,64 @@
+// Helpers for code that drives the emulator with small hand-written
+// programs, tests and benchmarks mostly, where a byte array of opcodes is
+// easy to get wrong.
+
+// A program built up one opcode at a time, for `Chip8::load_program`:
+//
+//     let program = ProgramBuilder::new().op(0x6A02).op(0x7A01).build();
+#[derive(Clone, Debug, Default)]
+pub struct ProgramBuilder {
+    bytes: Vec<u8>,
+}
+
+impl ProgramBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Appends one instruction, high byte first as CHIP-8 stores them
+    pub fn op(&mut self, opcode: u16) -> &mut Self {
+        self.bytes.extend_from_slice(&opcode.to_be_bytes());
+        self
+    }
+
+    pub fn ops(&mut self, opcodes: &[u16]) -> &mut Self {
+        for &opcode in opcodes {
+            self.op(opcode);
+        }
+        self
+    }
+
+    // Appends raw bytes, e.g. sprite data after the code
+    pub fn data(&mut self, bytes: &[u8]) -> &mut Self {
+        self.bytes.extend_from_slice(bytes);
+        self
+    }
+
+    // Address the next opcode or byte will load at, for a program loaded at
+    // `start`. Handy for jumps to a point in the program still being built.
+    pub fn address(&self, start: u16) -> u16 {
+        start + self.bytes.len() as u16
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chip8, Chip8Config};
+
+    #[test]
+    fn built_program_runs() {
+        let program = ProgramBuilder::new().op(0x6A02).op(0x7A01).build();
+        assert_eq!(program, [0x6A, 0x02, 0x7A, 0x01]);
+        let mut chip8 = Chip8::with_config(Chip8Config::default());
+        chip8.load_program(&program).unwrap();
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.registers()[0xA], 3);
+        assert_eq!(chip8.pc(), 0x200 + program.len() as u16);
+    }
+}
@@ -0,0 +1,110 @@
+// --compare-trace: runs in lockstep with an instruction trace taken from
+// another emulator and stops at the first instruction where this one runs
+// something different. One executed instruction per line, PC and opcode in
+// hex:
+//
+//     200 00E0
+//     202 A22A
+//
+// `#` starts a comment. FX0A waiting for a key doesn't count as a step.
+use chip8::Chip8;
+use std::fs;
+
+pub struct TraceCheck {
+    // PC and opcode of each reference instruction, in order
+    steps: Vec<(u16, u16)>,
+    matched: usize,
+    // PC, opcode and cycle count before the instruction being run
+    pending: Option<(u16, u16, u64)>,
+    diverged: bool,
+}
+
+impl TraceCheck {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read trace '{}' : {}", path, e))?;
+
+        let mut steps = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let step = parse_step(line).ok_or_else(|| {
+                format!(
+                    "{}:{}: expected '<pc> <opcode>' in hex, got '{}'",
+                    path,
+                    number + 1,
+                    line
+                )
+            })?;
+            steps.push(step);
+        }
+
+        Ok(TraceCheck {
+            steps,
+            matched: 0,
+            pending: None,
+            diverged: false,
+        })
+    }
+
+    pub fn before_cycle(&mut self, chip8: &Chip8) {
+        let pc = chip8.pc();
+        self.pending = Some((pc, chip8.opcode_at(pc), chip8.cycle_count()));
+    }
+
+    // True when the instruction just run is the first to differ from the
+    // reference
+    pub fn after_cycle(&mut self, chip8: &Chip8) -> bool {
+        let Some((pc, opcode, cycles)) = self.pending.take() else {
+            return false;
+        };
+        if chip8.cycle_count() == cycles || self.diverged || self.matched == self.steps.len() {
+            return false;
+        }
+
+        let (expected_pc, expected_opcode) = self.steps[self.matched];
+        if (pc, opcode) == (expected_pc, expected_opcode) {
+            self.matched += 1;
+            return false;
+        }
+        eprintln!(
+            "Diverged from the reference trace at instruction {}: expected {:03X} {:04X}, ran {:03X} {:04X}",
+            self.matched + 1,
+            expected_pc,
+            expected_opcode,
+            pc,
+            opcode
+        );
+        self.diverged = true;
+        true
+    }
+
+    pub fn diverged(&self) -> bool {
+        self.diverged
+    }
+
+    // Summary for the end of a run that didn't diverge
+    pub fn report(&self) {
+        if self.matched == self.steps.len() {
+            println!(
+                "Matched all {} instructions of the reference trace",
+                self.steps.len()
+            );
+        } else {
+            println!(
+                "Matched {} of {} reference instructions before the run ended",
+                self.matched,
+                self.steps.len()
+            );
+        }
+    }
+}
+
+fn parse_step(line: &str) -> Option<(u16, u16)> {
+    let mut parts = line.split_whitespace();
+    let pc = u16::from_str_radix(parts.next()?, 16).ok()?;
+    let opcode = u16::from_str_radix(parts.next()?, 16).ok()?;
+    parts.next().is_none().then_some((pc, opcode))
+}
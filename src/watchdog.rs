@@ -0,0 +1,46 @@
+// --watchdog: notices when the display has stopped changing, for catching
+// ROMs that hang somewhere other than a jump to themselves. Counted in 60Hz
+// frames of emulated time, so a headless run fires at the same point every
+// time however fast it goes.
+use chip8::Chip8;
+
+pub struct Watchdog {
+    timeout_frames: u64,
+    last_hash: Option<u64>,
+    unchanged_frames: u64,
+    fired: bool,
+}
+
+impl Watchdog {
+    pub fn new(timeout_seconds: u32) -> Self {
+        Watchdog {
+            timeout_frames: timeout_seconds as u64 * 60,
+            last_hash: None,
+            unchanged_frames: 0,
+            fired: false,
+        }
+    }
+
+    // Call after `frames` frames have run. True the first time the display
+    // has gone the whole timeout without changing, until it changes again.
+    pub fn check(&mut self, chip8: &Chip8, frames: u32) -> bool {
+        let hash = chip8.framebuffer_hash();
+        if self.last_hash != Some(hash) {
+            self.last_hash = Some(hash);
+            self.unchanged_frames = 0;
+            self.fired = false;
+            return false;
+        }
+        self.unchanged_frames += frames as u64;
+        if self.fired || self.unchanged_frames < self.timeout_frames {
+            return false;
+        }
+        self.fired = true;
+        eprintln!(
+            "Watchdog: no display change for {}s (PC at {:#05X})",
+            self.timeout_frames / 60,
+            chip8.pc()
+        );
+        true
+    }
+}
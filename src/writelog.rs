@@ -0,0 +1,63 @@
+// Text log of every memory write for --log-writes, one line each:
+// `<pc> <address> <value>` in hex. Shows where a game keeps its variables.
+use chip8::Chip8;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+pub struct WriteLog {
+    // None after a write failed, the run carries on without the log
+    file: Option<BufWriter<File>>,
+    path: String,
+}
+
+impl WriteLog {
+    pub fn create(path: &str) -> Result<Self, String> {
+        let file =
+            File::create(path).map_err(|e| format!("Failed to create '{}' : {}", path, e))?;
+        let mut file = BufWriter::new(file);
+        writeln!(file, "# pc address value")
+            .map_err(|e| format!("Failed to write '{}' : {}", path, e))?;
+        Ok(WriteLog {
+            file: Some(file),
+            path: path.to_string(),
+        })
+    }
+
+    // Call after running instructions to move their writes into the file
+    pub fn record(&mut self, chip8: &mut Chip8) {
+        let writes = chip8.take_writes();
+        let Some(file) = &mut self.file else {
+            return;
+        };
+
+        let result = writes.iter().try_for_each(|write| {
+            writeln!(
+                file,
+                "{:03X} {:03X} {:02X}",
+                write.pc, write.address, write.value
+            )
+        });
+        if let Err(e) = result {
+            eprintln!(
+                "Failed to write '{}', stopping the write log: {}",
+                self.path, e
+            );
+            self.file = None;
+        }
+    }
+
+    // Flushes what's buffered, later writes are dropped
+    pub fn finish(&mut self) {
+        if let Some(mut file) = self.file.take()
+            && let Err(e) = file.flush()
+        {
+            eprintln!("Failed to write '{}' : {}", self.path, e);
+        }
+    }
+}
+
+impl Drop for WriteLog {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}